@@ -7,20 +7,21 @@ extern crate image;
 extern crate tobj;
 
 use std::mem;
-use std::ptr;
 use std::time;
 use std::path::Path;
 use std::collections::HashMap;
 use image::{ImageFormat, DynamicImage};
 use cgmath::{SquareMatrix, One, Rotation, Rotation3, Basis3, Matrix3, Matrix4, Vector3};
 use vkc::winit::{EventsLoop, WindowBuilder, Window, Event, WindowEvent};
-use vkc::{vk, util, device, VkcResult, Version, Instance, Device, Surface, Swapchain, ImageView,
-    PipelineLayout, RenderPass, GraphicsPipeline, Framebuffer, CommandPool, Semaphore,
-    Buffer, DeviceMemory, Vertex, DescriptorSetLayout, UniformBufferObject, DescriptorPool,
-    Image, Sampler};
+use vkc::{vk, util, device, VkcResult, Version, Instance, InstanceBuilder, Device, Surface,
+    Swapchain, ImageView, PipelineLayout, RenderPass, GraphicsPipeline, GraphicsPipelineBuilder,
+    Framebuffer, CommandPool, CommandBuffer, Semaphore, Fence, SubmitInfo, Buffer, DeviceMemory,
+    Vertex, DescriptorSetLayout, ShaderModule, UniformBufferObject, UniformBufferObjectStd140,
+    AsStd140, DescriptorAllocator, DescriptorSet, Image, ImageBarrier, Sampler, PipelineCache, Pod};
 
 static MODEL_PATH: &str = "/src/shared_assets/models/chalet.obj";
 static TEXTURE_PATH: &str = "/src/shared_assets/textures/chalet.jpg";
+static PIPELINE_CACHE_PATH: &str = "/tmp/vkc_pipeline_cache.bin";
 // static TEXTURE_PATH: &str = "/src/vkc/textures/texture.jpg";
 
 const VERTICES_UNUSED: [Vertex; 8] =  [
@@ -56,75 +57,37 @@ fn init_window() -> (Window, EventsLoop) {
     (window, events_loop)
 }
 
-fn init_instance() -> VkcResult<Instance> {
-    let app_name = b"Hello Triangle\0";
-    let engine_name = b"No Engine\0";
-
-    let app_info = vk::VkApplicationInfo {
-        sType: vk::VK_STRUCTURE_TYPE_APPLICATION_INFO,
-        pNext: ptr::null(),
-        pApplicationName: app_name.as_ptr() as *const i8,
-        applicationVersion: Version::new(1, 0, 0).into(),
-        pEngineName: engine_name.as_ptr() as *const i8,
-        engineVersion: Version::new(1, 0, 0).into(),
-        apiVersion: Version::new(1, 0, 51).into(),
-    };
-
-    unsafe { Instance::new(&app_info) }
+fn init_instance(window: &Window) -> VkcResult<Instance> {
+    let mut builder = InstanceBuilder::new()
+        .application_name("Hello Triangle")
+        .application_version(Version::new(1, 0, 0))
+        .engine_name("No Engine")
+        .engine_version(Version::new(1, 0, 0))
+        .api_version(Version::new(1, 0, 51))
+        .surface_extensions_for(window);
+    if vkc::ENABLE_VALIDATION_LAYERS { builder = builder.validation(); }
+    builder.build()
 }
 
 fn begin_single_time_commands(device: &Device, command_pool: &CommandPool)
-        -> VkcResult<vk::VkCommandBuffer>
+        -> VkcResult<CommandBuffer>
 {
-    let alloc_info = vk::VkCommandBufferAllocateInfo {
-        sType: vk::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
-        pNext: ptr::null(),
-        commandPool: command_pool.handle(),
-        level: vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY,
-        commandBufferCount: 1,
-    };
-
-    let mut command_buffer = ptr::null_mut();
-    unsafe {
-        vkc::check(device.vk().core.vkAllocateCommandBuffers(device.handle(), &alloc_info,
-            &mut command_buffer));
-    }
-
-    let begin_info = vk::VkCommandBufferBeginInfo {
-        sType: vk::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
-        pNext: ptr::null(),
-        flags: vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT,
-        pInheritanceInfo: ptr::null(),
-    };
-
-    unsafe { device.vk().core.vkBeginCommandBuffer(command_buffer, &begin_info); }
+    let mut command_buffer = CommandBuffer::new(command_pool.clone(),
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY)?;
+    command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT)?;
     Ok(command_buffer)
 }
 
-fn end_single_time_commands(device: &Device, command_pool: &CommandPool,
-        command_buffer: vk::VkCommandBuffer) -> VkcResult<()>
+fn end_single_time_commands(device: &Device, _command_pool: &CommandPool,
+        mut command_buffer: CommandBuffer) -> VkcResult<()>
 {
-    unsafe { vkc::check(device.vk().core.vkEndCommandBuffer(command_buffer)); }
-
-    let submit_info = vk::VkSubmitInfo {
-        sType: vk::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-        pNext: ptr::null(),
-        waitSemaphoreCount: 0,
-        pWaitSemaphores: ptr::null(),
-        pWaitDstStageMask: ptr::null(),
-        commandBufferCount: 1,
-        pCommandBuffers: &command_buffer,
-        signalSemaphoreCount: 0,
-        pSignalSemaphores: ptr::null(),
-    };
+    command_buffer.end()?;
 
-    unsafe {
-        vkc::check(device.vk().core.vkQueueSubmit(device.queue(0), 1,
-            &submit_info, 0));
-        vkc::check(device.vk().core.vkQueueWaitIdle(device.queue(0)));
-        device.vk().core.vkFreeCommandBuffers(device.handle(),
-            command_pool.handle(), 1, &command_buffer);
-    }
+    // Wait on a fence rather than the whole queue so that other work
+    // submitted to it is not stalled:
+    let fence = Fence::new(device.clone(), false)?;
+    device.queue(0)?.submit(&[SubmitInfo::new().command_buffer(&command_buffer)], Some(&fence))?;
+    fence.wait(u64::max_value())?;
 
     Ok(())
 }
@@ -137,9 +100,9 @@ fn transition_image_layout(device: &Device, command_pool: &CommandPool, image: &
         format: vk::VkFormat, old_layout: vk::VkImageLayout, new_layout: vk::VkImageLayout)
          -> VkcResult<()>
 {
-    let command_buffer = begin_single_time_commands(device, command_pool)?;
+    let mut command_buffer = begin_single_time_commands(device, command_pool)?;
 
-    let subresource_range = vk::VkImageSubresourceRange {
+    let mut subresource_range = vk::VkImageSubresourceRange {
         aspectMask: vk::VK_IMAGE_ASPECT_COLOR_BIT,
         baseMipLevel: 0,
         levelCount: 1,
@@ -147,49 +110,38 @@ fn transition_image_layout(device: &Device, command_pool: &CommandPool, image: &
         layerCount: 1,
     };
 
-    let mut barrier = vk::VkImageMemoryBarrier {
-        sType: vk::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
-        pNext: ptr::null(),
-        srcAccessMask: 0,
-        dstAccessMask: 0,
-        oldLayout: old_layout,
-        newLayout: new_layout,
-        srcQueueFamilyIndex: vk::VK_QUEUE_FAMILY_IGNORED,
-        dstQueueFamilyIndex: vk::VK_QUEUE_FAMILY_IGNORED,
-        image: image.handle(),
-        subresourceRange: subresource_range,
-    };
-
     if new_layout == vk::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
-        barrier.subresourceRange.aspectMask = vk::VK_IMAGE_ASPECT_DEPTH_BIT;
+        subresource_range.aspectMask = vk::VK_IMAGE_ASPECT_DEPTH_BIT;
         if has_stencil_component(format) {
-            barrier.subresourceRange.aspectMask |= vk::VK_IMAGE_ASPECT_STENCIL_BIT;
+            subresource_range.aspectMask |= vk::VK_IMAGE_ASPECT_STENCIL_BIT;
         }
     } else {
-        barrier.subresourceRange.aspectMask = vk::VK_IMAGE_ASPECT_COLOR_BIT;
+        subresource_range.aspectMask = vk::VK_IMAGE_ASPECT_COLOR_BIT;
     }
 
+    let src_access_mask: vk::VkAccessFlags;
+    let dst_access_mask: vk::VkAccessFlags;
     let source_stage: vk::VkPipelineStageFlags;
     let destination_stage: vk::VkPipelineStageFlags;
 
     if old_layout == vk::VK_IMAGE_LAYOUT_UNDEFINED &&
             new_layout == vk::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL
     {
-        barrier.srcAccessMask = 0;
-        barrier.dstAccessMask = vk::VK_ACCESS_TRANSFER_WRITE_BIT;
+        src_access_mask = 0;
+        dst_access_mask = vk::VK_ACCESS_TRANSFER_WRITE_BIT;
         source_stage = vk::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT;
         destination_stage = vk::VK_PIPELINE_STAGE_TRANSFER_BIT;
     } else if old_layout == vk::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL &&
             new_layout == vk::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL
     {
-        barrier.srcAccessMask = vk::VK_ACCESS_TRANSFER_WRITE_BIT;
-        barrier.dstAccessMask = vk::VK_ACCESS_SHADER_READ_BIT;
+        src_access_mask = vk::VK_ACCESS_TRANSFER_WRITE_BIT;
+        dst_access_mask = vk::VK_ACCESS_SHADER_READ_BIT;
         source_stage = vk::VK_PIPELINE_STAGE_TRANSFER_BIT;
         destination_stage = vk::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT;
     } else if old_layout == vk::VK_IMAGE_LAYOUT_UNDEFINED &&
             new_layout == vk::VK_IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
-        barrier.srcAccessMask = 0;
-        barrier.dstAccessMask = vk::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT |
+        src_access_mask = 0;
+        dst_access_mask = vk::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT |
             vk::VK_ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT;
         source_stage = vk::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT;
         destination_stage = vk::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT;
@@ -197,16 +149,10 @@ fn transition_image_layout(device: &Device, command_pool: &CommandPool, image: &
         panic!("unsupported layout transition");
     }
 
-    unsafe {
-        device.vk().vkCmdPipelineBarrier(
-            command_buffer,
-            source_stage, destination_stage,
-            0,
-            0, ptr::null(),
-            0, ptr::null(),
-            1, &barrier
-        );
-    }
+    let barrier = ImageBarrier::new(image, subresource_range, old_layout, new_layout)
+        .access(src_access_mask, dst_access_mask);
+
+    command_buffer.pipeline_barrier(source_stage, destination_stage, 0, &[], &[], &[barrier]);
 
     end_single_time_commands(device, command_pool, command_buffer)
 }
@@ -214,7 +160,7 @@ fn transition_image_layout(device: &Device, command_pool: &CommandPool, image: &
 fn copy_buffer_to_image(device: &Device, command_pool: &CommandPool, buffer: &Buffer,
         image: &Image, width: u32, height: u32)  -> VkcResult<()>
 {
-    let command_buffer = begin_single_time_commands(device, command_pool)?;
+    let mut command_buffer = begin_single_time_commands(device, command_pool)?;
 
     let image_subresource_layers = vk::VkImageSubresourceLayers {
         aspectMask: vk::VK_IMAGE_ASPECT_COLOR_BIT,
@@ -232,16 +178,8 @@ fn copy_buffer_to_image(device: &Device, command_pool: &CommandPool, buffer: &Bu
         imageExtent: vk::VkExtent3D { width, height, depth: 1 },
     };
 
-    unsafe {
-        device.vk().vkCmdCopyBufferToImage(
-            command_buffer,
-            buffer.handle(),
-            image.handle(),
-            vk::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
-            1,
-            &region
-        );
-    }
+    command_buffer.copy_buffer_to_image(buffer, image, vk::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+        &[region]);
 
     end_single_time_commands(device, command_pool, command_buffer)
 }
@@ -252,7 +190,7 @@ fn copy_buffer(device: &Device, command_pool: &CommandPool, src_buffer: &Buffer,
     // TODO: Look into creating a separate command pool with the
     // `VK_COMMAND_POOL_CREATE_TRANSIENT_BIT` flag for short lived command
     // buffers like this.
-    let command_buffer = begin_single_time_commands(device, command_pool)?;
+    let mut command_buffer = begin_single_time_commands(device, command_pool)?;
 
     let copy_region = vk::VkBufferCopy {
         srcOffset: 0,
@@ -260,8 +198,7 @@ fn copy_buffer(device: &Device, command_pool: &CommandPool, src_buffer: &Buffer,
         size: size,
     };
 
-    unsafe { device.vk().core.vkCmdCopyBuffer(command_buffer, src_buffer.handle(),
-        dst_buffer.handle(), 1, &copy_region); }
+    command_buffer.copy_buffer(src_buffer, dst_buffer, &[copy_region]);
 
     end_single_time_commands(device, command_pool, command_buffer)
 }
@@ -323,13 +260,7 @@ fn create_vertex_buffer(device: &Device, command_pool: &CommandPool, vertices: &
         vk::VK_BUFFER_USAGE_TRANSFER_SRC_BIT, vk::VK_SHARING_MODE_EXCLUSIVE,
         vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT)?;
 
-    let mut data = ptr::null_mut();
-    unsafe {
-        vkc::check(device.vk().core.vkMapMemory(device.handle(),
-            staging_buffer.device_memory().handle(), 0, buffer_bytes, 0, &mut data));
-        ptr::copy_nonoverlapping(vertices.as_ptr(), data as *mut _, vertices.len());
-        device.vk().core.vkUnmapMemory(device.handle(), staging_buffer.device_memory().handle());
-    }
+    staging_buffer.map::<Vertex>()?[..vertices.len()].copy_from_slice(vertices);
 
     // HOST-RW:
     // let vertex_buffer = Buffer::new(device.clone(), buffer_bytes,
@@ -344,7 +275,7 @@ fn create_vertex_buffer(device: &Device, command_pool: &CommandPool, vertices: &
     Ok(vertex_buffer)
 }
 
-fn create_index_buffer<T>(device: &Device, command_pool: &CommandPool, indices: &[T])
+fn create_index_buffer<T: Pod>(device: &Device, command_pool: &CommandPool, indices: &[T])
         -> VkcResult<Buffer>
 {
     let buffer_bytes = (mem::size_of::<T>() * indices.len()) as u64;
@@ -353,13 +284,7 @@ fn create_index_buffer<T>(device: &Device, command_pool: &CommandPool, indices:
         vk::VK_BUFFER_USAGE_TRANSFER_SRC_BIT, vk::VK_SHARING_MODE_EXCLUSIVE,
         vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT)?;
 
-    unsafe {
-        let mut data = ptr::null_mut();
-        vkc::check(device.vk().core.vkMapMemory(device.handle(),
-            staging_buffer.device_memory().handle(), 0, buffer_bytes, 0, &mut data));
-        ptr::copy_nonoverlapping(indices.as_ptr(), data as *mut _, indices.len());
-        device.vk().core.vkUnmapMemory(device.handle(), staging_buffer.device_memory().handle());
-    }
+    staging_buffer.map::<T>()?[..indices.len()].copy_from_slice(indices);
 
     let index_buffer = Buffer::new(device.clone(), buffer_bytes,
         vk::VK_BUFFER_USAGE_TRANSFER_DST_BIT | vk::VK_BUFFER_USAGE_INDEX_BUFFER_BIT,
@@ -373,11 +298,13 @@ fn create_index_buffer<T>(device: &Device, command_pool: &CommandPool, indices:
 fn create_uniform_buffer(device: &Device, command_pool: &CommandPool, _extent: vk::VkExtent2D)
         -> VkcResult<Buffer>
 {
-    let buffer_bytes = mem::size_of::<UniformBufferObject>() as u64;
+    let buffer_bytes = UniformBufferObject::STD140_SIZE as u64;
     let uniform_buffer = Buffer::new(device.clone(), buffer_bytes,
         vk::VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT,
         vk::VK_SHARING_MODE_EXCLUSIVE, vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT |
         vk::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT)?;
+    // Updated every frame:
+    uniform_buffer.set_persistently_mapped(true)?;
     Ok(uniform_buffer)
 }
 
@@ -388,7 +315,7 @@ fn find_supported_format(device: &Device, candidates: &[vk::VkFormat], tiling: v
         let mut props: vk::VkFormatProperties;
         unsafe {
             props = mem::uninitialized();
-            device.instance().vk().vkGetPhysicalDeviceFormatProperties(device.physical_device(),
+            device.instance().vk().vkGetPhysicalDeviceFormatProperties(device.physical_device().handle(),
             format, &mut props);
         }
 
@@ -403,7 +330,7 @@ fn find_supported_format(device: &Device, candidates: &[vk::VkFormat], tiling: v
         }
     }
 
-    panic!("Failed to find supported format.")
+    Err("Failed to find supported format.".into())
 }
 
 
@@ -441,13 +368,7 @@ fn create_texture_image(device: &Device, command_pool: &CommandPool) -> VkcResul
         vk::VK_BUFFER_USAGE_TRANSFER_SRC_BIT, vk::VK_SHARING_MODE_EXCLUSIVE,
         vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT)?;
 
-    unsafe {
-        let mut data = ptr::null_mut();
-        vkc::check(device.vk().vkMapMemory(device.handle(),
-            staging_buffer.device_memory().handle(), 0, image_bytes, 0, &mut data));
-        ptr::copy_nonoverlapping(pixels.as_ptr(), data as *mut _, pixels.len());
-        device.vk().vkUnmapMemory(device.handle(), staging_buffer.device_memory().handle());
-    }
+    staging_buffer.map::<u8>()?[..pixels.len()].copy_from_slice(&pixels);
 
     let extent = vk::VkExtent3D { width: tex_width, height: tex_height, depth: 1 };
     let texture_image = Image::new(device.clone(), extent, vk::VK_FORMAT_R8G8B8A8_UNORM,
@@ -475,6 +396,24 @@ fn create_texture_sampler(device: Device) -> VkcResult<Sampler> {
     Sampler::new(device)
 }
 
+/// Creates the pipeline with a dynamic viewport and scissor so that it can be
+/// reused when the swapchain is resized.
+fn create_graphics_pipeline(pipeline_layout: &PipelineLayout, render_pass: &RenderPass,
+        vert_shader_module: &ShaderModule, frag_shader_module: &ShaderModule,
+        pipeline_cache: &PipelineCache) -> VkcResult<GraphicsPipeline>
+{
+    GraphicsPipelineBuilder::new(pipeline_layout, render_pass)
+        .cache(pipeline_cache)
+        .stage(vert_shader_module, "main")?
+        .stage(frag_shader_module, "main")?
+        .vertex_input::<Vertex>(0, vk::VK_VERTEX_INPUT_RATE_VERTEX)
+        .std140_block::<UniformBufferObject>(0, 0)
+        .dynamic_state(vk::VK_DYNAMIC_STATE_VIEWPORT)
+        .dynamic_state(vk::VK_DYNAMIC_STATE_SCISSOR)
+        .depth_test(vk::VK_COMPARE_OP_LESS, true)
+        .build()
+}
+
 fn create_render_pass(device: Device, swapchain_image_format: vk::VkFormat)
         -> VkcResult<RenderPass>
 {
@@ -482,85 +421,20 @@ fn create_render_pass(device: Device, swapchain_image_format: vk::VkFormat)
     RenderPass::new(device.clone(), swapchain_image_format, depth_image_format)
 }
 
-fn create_descriptor_pool(device: Device) -> VkcResult<DescriptorPool> {
-    DescriptorPool::new(device)
-}
-
-fn create_descriptor_set_layout(device: Device) -> VkcResult<DescriptorSetLayout> {
-    DescriptorSetLayout::new(device)
-}
-
-fn create_descriptor_set(device: &Device, layout: &DescriptorSetLayout,
-        pool: &DescriptorPool, uniform_buffer: &Buffer, texture_image_view: &ImageView,
-        texture_sampler: &Sampler) -> VkcResult<vk::VkDescriptorSet>
+fn create_descriptor_set(allocator: &mut DescriptorAllocator, layout: &DescriptorSetLayout,
+        uniform_buffer: &Buffer, texture_image_view: &ImageView, texture_sampler: &Sampler)
+        -> VkcResult<DescriptorSet>
 {
-    let layouts = [layout.handle()];
-
-    let alloc_info = vk::VkDescriptorSetAllocateInfo {
-        sType: vk::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
-        pNext: ptr::null(),
-        descriptorPool: pool.handle(),
-        descriptorSetCount: layouts.len() as u32,
-        pSetLayouts: layouts.as_ptr(),
-    };
-
-    let mut descriptor_set = 0;
-    unsafe {
-        vkc::check(device.vk().vkAllocateDescriptorSets(device.handle(), &alloc_info,
-            &mut descriptor_set));
-    }
-
-    let buffer_info = vk::VkDescriptorBufferInfo {
-        buffer: uniform_buffer.handle(),
-        offset: 0,
-        range: mem::size_of::<UniformBufferObject>() as u64,
-    };
-
-    let image_info = vk::VkDescriptorImageInfo {
-        sampler: texture_sampler.handle(),
-        imageView: texture_image_view.handle(),
-        imageLayout: vk::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
-    };
-
-    let descriptor_writes = [
-        vk::VkWriteDescriptorSet {
-            sType: vk::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-            pNext: ptr::null(),
-            dstSet: descriptor_set,
-            dstBinding: 0,
-            dstArrayElement: 0,
-            descriptorCount: 1,
-            descriptorType: vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-            pImageInfo: ptr::null(),
-            pBufferInfo: &buffer_info,
-            pTexelBufferView: ptr::null(),
-        },
-        vk::VkWriteDescriptorSet {
-            sType: vk::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-            pNext: ptr::null(),
-            dstSet: descriptor_set,
-            dstBinding: 1,
-            dstArrayElement: 0,
-            descriptorCount: 1,
-            descriptorType: vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-            pImageInfo: &image_info,
-            pBufferInfo: ptr::null(),
-            pTexelBufferView: ptr::null(),
-        },
-    ];
-
-    unsafe {
-        device.vk().vkUpdateDescriptorSets(device.handle(), descriptor_writes.len() as u32,
-            descriptor_writes.as_ptr(), 0, ptr::null());
-    }
-
+    let descriptor_set = allocator.allocate(layout)?;
+    descriptor_set.write_buffer(0, 0, uniform_buffer, 0,
+        UniformBufferObject::STD140_SIZE as u64)?;
+    descriptor_set.write_combined_image_sampler(1, 0, texture_image_view,
+        vk::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL, texture_sampler)?;
     Ok(descriptor_set)
 }
 
 struct SwapchainComponents {
     image_views: Vec<ImageView>,
-    render_pass: RenderPass,
-    graphics_pipeline: GraphicsPipeline,
     depth_image: Image,
     depth_image_view: ImageView,
     framebuffers: Vec<Framebuffer>,
@@ -575,8 +449,11 @@ struct App {
     surface: Surface,
     descriptor_set_layout: DescriptorSetLayout,
     pipeline_layout: PipelineLayout,
-    vert_shader_code: Vec<u8>,
-    frag_shader_code: Vec<u8>,
+    vert_shader_module: ShaderModule,
+    frag_shader_module: ShaderModule,
+    pipeline_cache: PipelineCache,
+    render_pass: RenderPass,
+    graphics_pipeline: GraphicsPipeline,
     command_pool: CommandPool,
     texture_image: Image,
     texture_image_view: ImageView,
@@ -586,37 +463,42 @@ struct App {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     uniform_buffer: Buffer,
-    descriptor_pool: DescriptorPool,
-    descriptor_set: vk::VkDescriptorSet,
+    descriptor_allocator: DescriptorAllocator,
+    descriptor_set: DescriptorSet,
     image_available_semaphore: Semaphore,
     render_finished_semaphore: Semaphore,
     start_time: time::Instant,
     swapchain: Option<Swapchain>,
     swapchain_components: Option<SwapchainComponents>,
-    command_buffers: Option<Vec<vk::VkCommandBuffer>>,
+    command_buffers: Option<Vec<CommandBuffer>>,
 }
 
 impl App {
     #[allow(unused_unsafe)]
     pub unsafe fn new() -> VkcResult<App> {
-        let instance = init_instance()?;
         let (window, events_loop) = init_window();
+        let instance = init_instance(&window)?;
         let surface = Surface::new(instance.clone(), &window)?;
         let queue_family_flags = vk::VK_QUEUE_GRAPHICS_BIT;
-        let physical_device = device::choose_physical_device(&instance, &surface,
+        let physical_device = device::choose_physical_device(&instance, Some(&surface),
+            queue_family_flags)?;
+        let device = Device::new(instance.clone(), Some(&surface), physical_device,
             queue_family_flags)?;
-        let device = Device::new(instance.clone(), &surface, physical_device, queue_family_flags)?;
         let swapchain = Swapchain::new(surface.clone(), device.clone(), queue_family_flags,
             None, None)?;
         let image_views = vkc::create_image_views(&swapchain)?;
         let render_pass = create_render_pass(device.clone(), swapchain.image_format())?;
-        let descriptor_set_layout = create_descriptor_set_layout(device.clone())?;
-        let pipeline_layout = PipelineLayout::new(device.clone(), Some(&descriptor_set_layout))?;
-        let vert_shader_code = util::read_file("/src/vkc/shaders/vert.spv")?;
-        let frag_shader_code = util::read_file("/src/vkc/shaders/frag.spv")?;
-        let graphics_pipeline = GraphicsPipeline::new(device.clone(), &pipeline_layout,
-            &render_pass, swapchain.extent().clone(), &vert_shader_code, &frag_shader_code)?;
-        let command_pool = CommandPool::new(device.clone(), &surface, queue_family_flags)?;
+        let vert_shader_module = ShaderModule::new(device.clone(),
+            &util::read_file("/src/vkc/shaders/vert.spv")?)?;
+        let frag_shader_module = ShaderModule::new(device.clone(),
+            &util::read_file("/src/vkc/shaders/frag.spv")?)?;
+        let pipeline_layout = PipelineLayout::from_modules(device.clone(),
+            &[&vert_shader_module, &frag_shader_module])?;
+        let descriptor_set_layout = pipeline_layout.set_layouts()[0].clone();
+        let pipeline_cache = PipelineCache::load(device.clone(), PIPELINE_CACHE_PATH)?;
+        let graphics_pipeline = create_graphics_pipeline(&pipeline_layout, &render_pass,
+            &vert_shader_module, &frag_shader_module, &pipeline_cache)?;
+        let command_pool = CommandPool::new(device.clone(), queue_family_flags, 0)?;
         let (depth_image, depth_image_view) = create_depth_resources(&device, &command_pool,
             swapchain.extent().clone())?;
         let framebuffers = vkc::create_framebuffers(&device, &render_pass,
@@ -630,21 +512,19 @@ impl App {
         let index_buffer = create_index_buffer(&device, &command_pool, &indices)?;
         let uniform_buffer = create_uniform_buffer(&device, &command_pool,
             swapchain.extent().clone())?;
-        let descriptor_pool = create_descriptor_pool(device.clone())?;
-        let descriptor_set = create_descriptor_set(&device, &descriptor_set_layout,
-            &descriptor_pool, &uniform_buffer, &texture_image_view, &texture_sampler)?;
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone(), 0);
+        let descriptor_set = create_descriptor_set(&mut descriptor_allocator,
+            &descriptor_set_layout, &uniform_buffer, &texture_image_view, &texture_sampler)?;
         let command_buffers = vkc::create_command_buffers(&device, &command_pool, &render_pass,
             &graphics_pipeline, &framebuffers, swapchain.extent(),
             &vertex_buffer, &index_buffer,
-            vertices.len() as u32, vertices.len() as u32, &pipeline_layout, descriptor_set)?;
+            vertices.len() as u32, vertices.len() as u32, &pipeline_layout, &descriptor_set)?;
         let image_available_semaphore = Semaphore::new(device.clone())?;
         let render_finished_semaphore = Semaphore::new(device.clone())?;
         let start_time = time::Instant::now();
 
         let swapchain_components = SwapchainComponents {
             image_views: image_views,
-            render_pass: render_pass,
-            graphics_pipeline: graphics_pipeline,
             depth_image,
             depth_image_view,
             framebuffers: framebuffers,
@@ -659,8 +539,11 @@ impl App {
             surface: surface,
             descriptor_set_layout,
             pipeline_layout,
-            vert_shader_code,
-            frag_shader_code,
+            vert_shader_module,
+            frag_shader_module,
+            pipeline_cache,
+            render_pass,
+            graphics_pipeline,
             command_pool,
             texture_image,
             texture_image_view,
@@ -670,7 +553,7 @@ impl App {
             vertex_buffer,
             index_buffer,
             uniform_buffer,
-            descriptor_pool,
+            descriptor_allocator,
             descriptor_set,
             image_available_semaphore,
             render_finished_semaphore,
@@ -684,45 +567,43 @@ impl App {
     fn cleanup_swapchain(&mut self) {
         self.swapchain = None;
         self.swapchain_components = None;
-        unsafe {
-            self.device.vk().core.vkFreeCommandBuffers(self.device.handle(),
-                self.command_pool.handle(),
-                self.command_buffers.as_ref().unwrap().len() as u32,
-                self.command_buffers.as_mut().unwrap().as_mut_ptr());
-        }
         self.command_buffers = None;
     }
 
     fn recreate_swapchain(&mut self, current_extent: vk::VkExtent2D) -> VkcResult<()> {
-        unsafe { vkc::check(self.device.vk().core.vkDeviceWaitIdle(self.device.handle())); }
+        unsafe { vkc::check(self.device.vk().core.vkDeviceWaitIdle(self.device.handle()))?; }
 
+        let old_image_format = self.swapchain.as_ref().map(|s| s.image_format());
         let swapchain = Swapchain::new(self.surface.clone(), self.device.clone(),
             self.queue_family_flags, Some(current_extent), self.swapchain.take())?;
 
         self.cleanup_swapchain();
 
+        // The viewport and scissor are dynamic so the render pass and
+        // pipeline only depend on the image format, which rarely changes.
+        if old_image_format != Some(swapchain.image_format()) {
+            self.render_pass = create_render_pass(self.device.clone(),
+                swapchain.image_format())?;
+            self.graphics_pipeline = create_graphics_pipeline(&self.pipeline_layout,
+                &self.render_pass, &self.vert_shader_module, &self.frag_shader_module,
+                &self.pipeline_cache)?;
+        }
+
         let image_views = vkc::create_image_views(&swapchain)?;
-        let render_pass = create_render_pass(self.device.clone(),
-            swapchain.image_format())?;
-        let graphics_pipeline = GraphicsPipeline::new(self.device.clone(),
-            &self.pipeline_layout, &render_pass,
-            swapchain.extent().clone(), &self.vert_shader_code, &self.frag_shader_code)?;
         let (depth_image, depth_image_view) = create_depth_resources(&self.device,
             &self.command_pool, swapchain.extent().clone())?;
         let framebuffers = vkc::create_framebuffers(&self.device,
-            &render_pass, &image_views,
+            &self.render_pass, &image_views,
             &depth_image_view, swapchain.extent().clone())?;
         let command_buffers = vkc::create_command_buffers(&self.device, &self.command_pool,
-            &render_pass, &graphics_pipeline,
+            &self.render_pass, &self.graphics_pipeline,
             &framebuffers, swapchain.extent(),
             &self.vertex_buffer, &self.index_buffer, self.vertices.len() as u32,
-            self.indices.len() as u32, &self.pipeline_layout, self.descriptor_set)?;
+            self.indices.len() as u32, &self.pipeline_layout, &self.descriptor_set)?;
 
         self.swapchain = Some(swapchain);
         self.swapchain_components = Some(SwapchainComponents {
             image_views: image_views,
-            render_pass: render_pass,
-            graphics_pipeline: graphics_pipeline,
             depth_image,
             depth_image_view,
             framebuffers: framebuffers,
@@ -754,15 +635,7 @@ impl App {
             proj: proj.into(),
         };
 
-        let mut data = ptr::null_mut();
-        unsafe {
-            vkc::check(self.device.vk().core.vkMapMemory(self.device.handle(),
-                self.uniform_buffer.device_memory().handle(), 0,
-                mem::size_of::<UniformBufferObject>() as u64, 0, &mut data));
-            ptr::copy_nonoverlapping(&ubo, data as *mut _, 1);
-            self.device.vk().core.vkUnmapMemory(self.device.handle(),
-                self.uniform_buffer.device_memory().handle());
-        }
+        self.uniform_buffer.map::<UniformBufferObjectStd140>()?[0] = ubo.as_std140();
 
         Ok(())
     }
@@ -775,51 +648,26 @@ impl App {
                 u64::max_value(), self.image_available_semaphore.handle(), 0, &mut image_index)
         };
 
-        if acq_res == vk::VK_ERROR_OUT_OF_DATE_KHR {
-            let dims = self.window.get_inner_size_pixels().unwrap();
-            self.recreate_swapchain(vk::VkExtent2D { height: dims.0, width: dims.1 } )?;
-            return Ok(());
-        } else if acq_res != vk::VK_SUCCESS && acq_res != vk::VK_SUBOPTIMAL_KHR {
-            panic!("Unable to present swap chain image");
+        match vkc::check(acq_res) {
+            Ok(_) => (),
+            Err(ref err) if err.vk_result_code() == Some(vk::VK_ERROR_OUT_OF_DATE_KHR) => {
+                let dims = self.window.get_inner_size_pixels().unwrap();
+                self.recreate_swapchain(vk::VkExtent2D { height: dims.0, width: dims.1 } )?;
+                return Ok(());
+            },
+            Err(err) => return Err(err),
         }
 
-        let wait_semaphores = [self.image_available_semaphore.handle()];
-        let wait_stages = [vk::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT];
-        let signal_semaphores = [self.render_finished_semaphore.handle()];
-
-        let submit_info = vk::VkSubmitInfo {
-            sType: vk::VK_STRUCTURE_TYPE_SUBMIT_INFO,
-            pNext: ptr::null(),
-            waitSemaphoreCount: wait_semaphores.len() as u32,
-            pWaitSemaphores: wait_semaphores.as_ptr(),
-            pWaitDstStageMask: wait_stages.as_ptr(),
-            commandBufferCount: 1,
-            pCommandBuffers: self.command_buffers.as_ref().unwrap()
-                .get(image_index as usize).unwrap(),
-            signalSemaphoreCount: signal_semaphores.len() as u32,
-            pSignalSemaphores: signal_semaphores.as_ptr(),
-        };
-
-        unsafe { vkc::check(self.device.vk().core.vkQueueSubmit(self.device.queue(0), 1,
-            &submit_info, 0)); }
+        let queue = self.device.queue(0)?;
+        let submit_info = SubmitInfo::new()
+            .wait(&self.image_available_semaphore, vk::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT)
+            .command_buffer(self.command_buffers.as_ref().unwrap().get(image_index as usize).unwrap())
+            .signal(&self.render_finished_semaphore);
+        queue.submit(&[submit_info], None)?;
 
-        let swapchains = [self.swapchain.as_ref().unwrap().handle()];
-
-        let present_info = vk::VkPresentInfoKHR {
-            sType: vk::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
-            pNext: ptr::null(),
-            waitSemaphoreCount: signal_semaphores.len() as u32,
-            pWaitSemaphores: signal_semaphores.as_ptr(),
-            swapchainCount: swapchains.len() as u32,
-            pSwapchains: swapchains.as_ptr(),
-            pImageIndices: &image_index,
-            pResults: ptr::null_mut(),
-        };
-
-        unsafe {
-            vkc::check(self.device.vk().khr_swapchain.vkQueuePresentKHR(self.device.queue(0), &present_info));
-            vkc::check(self.device.vk().core.vkQueueWaitIdle(self.device.queue(0)));
-        }
+        queue.present(self.swapchain.as_ref().unwrap(), image_index,
+            &[&self.render_finished_semaphore])?;
+        queue.wait_idle()?;
 
         Ok(())
     }
@@ -855,7 +703,8 @@ impl App {
             self.draw_frame()?;
         }
 
-        unsafe { vkc::check(self.device.vk().core.vkDeviceWaitIdle(self.device.handle())); }
+        unsafe { vkc::check(self.device.vk().core.vkDeviceWaitIdle(self.device.handle()))?; }
+        self.pipeline_cache.save(PIPELINE_CACHE_PATH)?;
         Ok(())
     }
 }
@@ -1,17 +1,41 @@
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::ptr;
 use std::mem;
+use std::slice;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use vk;
 use vks;
-use ::{util, VkcResult, Device, DeviceMemory};
+use ::{util, VkcResult, Device, Allocation, AllocationKind};
 
 
+/// A plain data type which can be read from and written to mapped memory.
+///
+/// # Safety
+///
+/// Every bit pattern of the type's size must be a valid value, which rules
+/// out `bool`, enums, references and types containing them.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => { $(unsafe impl Pod for $ty {})* };
+}
+
+impl_pod!(u8, i8, u16, i16, u32, i32, u64, i64, usize, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkBuffer,
-    device_memory: DeviceMemory,
+    bytes: u64,
+    allocation: Allocation,
+    // Set while a `MappedBuffer` exists:
+    mapped: AtomicBool,
+    persistently_mapped: AtomicBool,
     device: Device,
 }
 
@@ -39,7 +63,7 @@ impl Buffer {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreateBuffer(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         // Memory Requirements:
@@ -50,65 +74,35 @@ impl Buffer {
                 &mut mem_requirements);
         }
 
-        // It should be noted that in a real world application, you're not
-        // supposed to actually call vkAllocateMemory for every individual
-        // buffer. The maximum number of simultaneous memory allocations is
-        // limited by the maxMemoryAllocationCount physical device limit,
-        // which may be as low as 4096 even on high end hardware like an
-        // NVIDIA GTX 1080. The right way to allocate memory for a large
-        // number of objects at the same time is to create a custom allocator
-        // that splits up a single allocation among many different objects by
-        // using the offset parameters that we've seen in many functions.
-        //
-        // You can either implement such an allocator yourself, or use the
-        // VulkanMemoryAllocator library provided by the GPUOpen initiative. However,
-        // for this tutorial it's okay to use a separate allocation for every
-        // resource, because we won't come close to hitting any of these limits for
-        // now.
-        //
-        // The previous chapter already mentioned that you should allocate
-        // multiple resources like buffers from a single memory allocation,
-        // but in fact you should go a step further. Driver developers
-        // recommend that you also store multiple buffers, like the vertex and
-        // index buffer, into a single VkBuffer and use offsets in commands
-        // like vkCmdBindVertexBuffers. The advantage is that your data is
-        // more cache friendly in that case, because it's closer together. It
-        // is even possible to reuse the same chunk of memory for multiple
-        // resources if they are not used during the same render operations,
-        // provided that their data is refreshed, of course. This is known as
-        // aliasing and some Vulkan functions have explicit flags to specify
-        // that you want to do this.
-
         // * Use a memory heap that is host coherent, indicated with
         //   VK_MEMORY_PROPERTY_HOST_COHERENT_BIT (or)
         // * Call vkFlushMappedMemoryRanges to after writing to the mapped
         //   memory, and call vkInvalidateMappedMemoryRanges before reading from
         //   the mapped memory
-        let memory_type_index = ::find_memory_type(&device, mem_requirements.memoryTypeBits,
-            memory_properties);
-
-        let alloc_info = vk::VkMemoryAllocateInfo {
-            sType: vk::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
-            pNext: ptr::null(),
-            allocationSize: mem_requirements.size,
-            memoryTypeIndex: memory_type_index,
+        let allocation = Allocation::new(device.clone(), &mem_requirements, memory_properties,
+            AllocationKind::Linear).and_then(|allocation| {
+                unsafe {
+                    ::check(device.vk().core.vkBindBufferMemory(device.handle(), handle,
+                        allocation.memory(), allocation.offset()))?;
+                }
+                Ok(allocation)
+            });
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.vk().core.vkDestroyBuffer(device.handle(), handle, ptr::null()); }
+                return Err(err);
+            },
         };
 
-        println!("Buffer: {:?}", mem_requirements);
-
-        let device_memory = DeviceMemory::new(device.clone(), mem_requirements.size,
-            memory_type_index)?;
-
-        unsafe {
-            ::check(device.vk().core.vkBindBufferMemory(device.handle(), handle,
-                device_memory.handle(), 0));
-        }
-
         Ok(Buffer {
             inner: Arc::new(Inner {
                 handle,
+                bytes,
+                allocation,
+                mapped: AtomicBool::new(false),
+                persistently_mapped: AtomicBool::new(false),
                 device,
-                device_memory,
             })
         })
     }
@@ -117,8 +111,71 @@ impl Buffer {
         self.inner.handle
     }
 
-    pub fn device_memory(&self) -> &DeviceMemory {
-        &self.inner.device_memory
+    /// Returns the size of this buffer in bytes.
+    pub fn size(&self) -> u64 {
+        self.inner.bytes
+    }
+
+    /// Returns the memory allocation this buffer is bound to.
+    pub fn allocation(&self) -> &Allocation {
+        &self.inner.allocation
+    }
+
+    /// Maps this buffer into host memory, returning a guard which
+    /// dereferences to a slice of as many `T`s as will fit.
+    ///
+    /// The buffer must have been created with host visible memory. If its
+    /// memory is not host coherent, the mapped range is invalidated when
+    /// mapped and flushed when the guard is dropped.
+    ///
+    /// Only one guard may exist per buffer at a time. Synchronizing with
+    /// device access to the buffer is the caller's responsibility.
+    pub fn map<T: Pod>(&self) -> VkcResult<MappedBuffer<T>> {
+        if mem::size_of::<T>() == 0 {
+            return Err("Buffer::map: The element type must not be zero-sized.".into());
+        }
+        if self.inner.mapped.swap(true, Ordering::SeqCst) {
+            return Err("Buffer::map: Buffer is already mapped.".into());
+        }
+
+        let ptr = match self.inner.allocation.map() {
+            Ok(ptr) => ptr,
+            Err(err) => {
+                self.inner.mapped.store(false, Ordering::SeqCst);
+                return Err(err);
+            },
+        };
+        if ptr as usize % mem::align_of::<T>() != 0 {
+            let unmapped = self.inner.allocation.unmap();
+            self.inner.mapped.store(false, Ordering::SeqCst);
+            unmapped?;
+            return Err("Buffer::map: Mapped memory is not sufficiently aligned for the \
+                element type.".into());
+        }
+
+        let guard = MappedBuffer {
+            buffer: self,
+            ptr: ptr as *mut T,
+            len: self.inner.bytes as usize / mem::size_of::<T>(),
+            _ty: PhantomData,
+        };
+        self.inner.allocation.invalidate(0, self.inner.bytes)?;
+        Ok(guard)
+    }
+
+    /// Keeps this buffer's memory mapped between calls to `map` when
+    /// `persistent` is true, avoiding repeated calls to `vkMapMemory`.
+    pub fn set_persistently_mapped(&self, persistent: bool) -> VkcResult<()> {
+        let was_persistent = self.inner.persistently_mapped.swap(persistent, Ordering::SeqCst);
+        if persistent && !was_persistent {
+            if let Err(err) = self.inner.allocation.map() {
+                self.inner.persistently_mapped.store(false, Ordering::SeqCst);
+                return Err(err);
+            }
+        } else if !persistent && was_persistent {
+            self.inner.allocation.unmap()?;
+        }
+        Ok(())
     }
 
     pub fn device(&self) -> &Device {
@@ -128,8 +185,57 @@ impl Buffer {
 
 impl Drop for Inner {
     fn drop(&mut self) {
+        if *self.persistently_mapped.get_mut() {
+            if let Err(err) = self.allocation.unmap() {
+                error!("Error unmapping buffer: {}", err);
+            }
+        }
         unsafe {
             self.device.vk().core.vkDestroyBuffer(self.device.handle(), self.handle, ptr::null());
         }
     }
-}
\ No newline at end of file
+}
+
+
+/// A host mapping of a buffer, unmapped when dropped.
+pub struct MappedBuffer<'b, T: 'b> {
+    buffer: &'b Buffer,
+    ptr: *mut T,
+    len: usize,
+    _ty: PhantomData<&'b mut [T]>,
+}
+
+impl<'b, T> MappedBuffer<'b, T> {
+    /// Flushes host writes, making them visible to the device without
+    /// unmapping. Does nothing for host coherent memory.
+    pub fn flush(&self) -> VkcResult<()> {
+        self.buffer.inner.allocation.flush(0, self.buffer.inner.bytes)
+    }
+}
+
+impl<'b, T> Deref for MappedBuffer<'b, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<'b, T> DerefMut for MappedBuffer<'b, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<'b, T> Drop for MappedBuffer<'b, T> {
+    fn drop(&mut self) {
+        let inner = &self.buffer.inner;
+        if let Err(err) = inner.allocation.flush(0, inner.bytes) {
+            error!("Error flushing mapped buffer: {}", err);
+        }
+        if let Err(err) = inner.allocation.unmap() {
+            error!("Error unmapping buffer: {}", err);
+        }
+        inner.mapped.store(false, Ordering::SeqCst);
+    }
+}
@@ -1,198 +1,540 @@
-use std::sync::Arc;
 use std::ptr;
+use std::mem;
+use libc::c_void;
+use smallvec::SmallVec;
 use vk;
 use vks;
-use ::{util, VkcResult, Device, Framebuffer, CommandPool, RenderPass, GraphicsPipeline, Buffer,
-    PipelineLayout};
+use ::{util, VkcResult, Device, Framebuffer, CommandPool, RenderPass, GraphicsPipeline,
+    ComputePipeline, Buffer, Image, PipelineLayout, DescriptorSet};
+
+
+/// An object referenced by recorded commands.
+// Only held to keep the object alive, never read.
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Resource {
+    RenderPass(RenderPass),
+    Framebuffer(Framebuffer),
+    GraphicsPipeline(GraphicsPipeline),
+    ComputePipeline(ComputePipeline),
+    PipelineLayout(PipelineLayout),
+    DescriptorSet(DescriptorSet),
+    Buffer(Buffer),
+    Image(Image),
+}
 
 
+/// A buffer memory barrier recorded with `CommandBuffer::pipeline_barrier`.
+///
+/// Covers the whole buffer and no access types unless configured otherwise.
+#[derive(Debug, Clone)]
+pub struct BufferBarrier<'b> {
+    buffer: &'b Buffer,
+    src_access_mask: vk::VkAccessFlags,
+    dst_access_mask: vk::VkAccessFlags,
+    src_queue_family_idx: u32,
+    dst_queue_family_idx: u32,
+    offset: u64,
+    size: u64,
+}
 
-// #[derive(Debug, Clone)]
-// pub struct CommandBuffer {
-//     handle: vk::VkCommandBuffer,
-//     device: Device,
-// }
+impl<'b> BufferBarrier<'b> {
+    pub fn new(buffer: &'b Buffer) -> BufferBarrier<'b> {
+        BufferBarrier {
+            buffer,
+            src_access_mask: 0,
+            dst_access_mask: 0,
+            src_queue_family_idx: vk::VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_idx: vk::VK_QUEUE_FAMILY_IGNORED,
+            offset: 0,
+            size: vk::VK_WHOLE_SIZE,
+        }
+    }
 
-// impl CommandBuffer {
-//     pub fn new() -> VkcResult<CommandBuffer> {
+    /// Makes accesses of `src_access_mask` types before the barrier
+    /// available to accesses of `dst_access_mask` types after it.
+    pub fn access(mut self, src_access_mask: vk::VkAccessFlags,
+            dst_access_mask: vk::VkAccessFlags) -> BufferBarrier<'b>
+    {
+        self.src_access_mask = src_access_mask;
+        self.dst_access_mask = dst_access_mask;
+        self
+    }
 
-//         let mut handle = 0;
-//         unsafe {
-//             ::check(device.vk().CreateCommandBuffer(device.handle(), &create_info,
-//                 ptr::null(), &mut handle));
-//         }
+    /// Limits the barrier to `size` bytes starting at `offset`.
+    pub fn range(mut self, offset: u64, size: u64) -> BufferBarrier<'b> {
+        self.offset = offset;
+        self.size = size;
+        self
+    }
 
-//         Ok(CommandBuffer {
-//             handle,
-//             device,
-//         })
-//     }
+    /// Transfers ownership of the buffer between queue families.
+    pub fn queue_family_transfer(mut self, src_queue_family_idx: u32,
+            dst_queue_family_idx: u32) -> BufferBarrier<'b>
+    {
+        self.src_queue_family_idx = src_queue_family_idx;
+        self.dst_queue_family_idx = dst_queue_family_idx;
+        self
+    }
 
-//     pub fn handle(&self) -> vk::VkCommandBuffer {
-//         self.inner.handle
-//     }
+    fn raw(&self) -> vk::VkBufferMemoryBarrier {
+        vk::VkBufferMemoryBarrier {
+            sType: vk::VK_STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER,
+            pNext: ptr::null(),
+            srcAccessMask: self.src_access_mask,
+            dstAccessMask: self.dst_access_mask,
+            srcQueueFamilyIndex: self.src_queue_family_idx,
+            dstQueueFamilyIndex: self.dst_queue_family_idx,
+            buffer: self.buffer.handle(),
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
 
-//     pub fn device(&self) -> &Device {
-//         &self.inner.device
-//     }
-// }
 
+/// An image memory barrier recorded with `CommandBuffer::pipeline_barrier`,
+/// transitioning `subresource_range` of the image from `old_layout` to
+/// `new_layout`.
+///
+/// Covers no access types unless configured otherwise.
+#[derive(Debug, Clone)]
+pub struct ImageBarrier<'i> {
+    image: &'i Image,
+    subresource_range: vk::VkImageSubresourceRange,
+    old_layout: vk::VkImageLayout,
+    new_layout: vk::VkImageLayout,
+    src_access_mask: vk::VkAccessFlags,
+    dst_access_mask: vk::VkAccessFlags,
+    src_queue_family_idx: u32,
+    dst_queue_family_idx: u32,
+}
 
-pub fn create_command_buffers(device: &Device, command_pool: &CommandPool,
-        render_pass: &RenderPass, graphics_pipeline: &GraphicsPipeline,
-        swapchain_framebuffers: &[Framebuffer], swapchain_extent: &vk::VkExtent2D,
-        vertex_buffer: &Buffer, index_buffer: &Buffer, vertex_count: u32,
-        index_count: u32, pipeline_layout: &PipelineLayout,
-        descriptor_set: vk::VkDescriptorSet)
-        -> VkcResult<Vec<vk::VkCommandBuffer>>
-{
-    let mut command_buffers = Vec::with_capacity(swapchain_framebuffers.len());
-    unsafe { command_buffers.set_len(swapchain_framebuffers.len()); }
-
-    let alloc_info = vk::VkCommandBufferAllocateInfo {
-        sType: vk::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
-        pNext: ptr::null(),
-        commandPool: command_pool.handle(),
-        // * COMMAND_BUFFER_LEVEL_PRIMARY: Can be submitted to a queue for
-        //   execution, but cannot be called from other command buffers.
-        // * COMMAND_BUFFER_LEVEL_SECONDARY: Cannot be submitted directly, but
-        //   can be called from primary command buffers.
-        level: vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY,
-        commandBufferCount: command_buffers.len() as u32,
-    };
-
-    unsafe {
-        ::check(device.vk().vkAllocateCommandBuffers(device.handle(), &alloc_info,
-            command_buffers.as_mut_ptr()));
-    }
-
-    for (&command_buffer, swapchain_framebuffer) in command_buffers.iter()
-            .zip(swapchain_framebuffers.iter())
+impl<'i> ImageBarrier<'i> {
+    pub fn new(image: &'i Image, subresource_range: vk::VkImageSubresourceRange,
+            old_layout: vk::VkImageLayout, new_layout: vk::VkImageLayout) -> ImageBarrier<'i>
+    {
+        ImageBarrier {
+            image,
+            subresource_range,
+            old_layout,
+            new_layout,
+            src_access_mask: 0,
+            dst_access_mask: 0,
+            src_queue_family_idx: vk::VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_idx: vk::VK_QUEUE_FAMILY_IGNORED,
+        }
+    }
+
+    /// Makes accesses of `src_access_mask` types before the barrier
+    /// available to accesses of `dst_access_mask` types after it.
+    pub fn access(mut self, src_access_mask: vk::VkAccessFlags,
+            dst_access_mask: vk::VkAccessFlags) -> ImageBarrier<'i>
+    {
+        self.src_access_mask = src_access_mask;
+        self.dst_access_mask = dst_access_mask;
+        self
+    }
+
+    /// Transfers ownership of the image between queue families.
+    pub fn queue_family_transfer(mut self, src_queue_family_idx: u32,
+            dst_queue_family_idx: u32) -> ImageBarrier<'i>
+    {
+        self.src_queue_family_idx = src_queue_family_idx;
+        self.dst_queue_family_idx = dst_queue_family_idx;
+        self
+    }
+
+    fn raw(&self) -> vk::VkImageMemoryBarrier {
+        vk::VkImageMemoryBarrier {
+            sType: vk::VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+            pNext: ptr::null(),
+            srcAccessMask: self.src_access_mask,
+            dstAccessMask: self.dst_access_mask,
+            oldLayout: self.old_layout,
+            newLayout: self.new_layout,
+            srcQueueFamilyIndex: self.src_queue_family_idx,
+            dstQueueFamilyIndex: self.dst_queue_family_idx,
+            image: self.image.handle(),
+            subresourceRange: self.subresource_range.clone(),
+        }
+    }
+}
+
+
+/// A command buffer, freed back to its pool when dropped.
+///
+/// Objects referenced by recorded commands are kept alive until recording
+/// begins again or the command buffer is dropped.
+#[derive(Debug)]
+pub struct CommandBuffer {
+    handle: vk::VkCommandBuffer,
+    command_pool: CommandPool,
+    resources: Vec<Resource>,
+    // Whether recording has begun at least once.
+    recorded: bool,
+}
+
+impl CommandBuffer {
+    /// Allocates a single command buffer from `command_pool`.
+    ///
+    /// * COMMAND_BUFFER_LEVEL_PRIMARY: Can be submitted to a queue for
+    ///   execution, but cannot be called from other command buffers.
+    /// * COMMAND_BUFFER_LEVEL_SECONDARY: Cannot be submitted directly, but
+    ///   can be called from primary command buffers.
+    pub fn new(command_pool: CommandPool, level: vk::VkCommandBufferLevel)
+            -> VkcResult<CommandBuffer>
     {
+        let mut command_buffers = CommandBuffer::allocate(command_pool, level, 1)?;
+        Ok(command_buffers.pop().unwrap())
+    }
+
+    /// Allocates `count` command buffers from `command_pool`.
+    pub fn allocate(command_pool: CommandPool, level: vk::VkCommandBufferLevel, count: u32)
+            -> VkcResult<Vec<CommandBuffer>>
+    {
+        let alloc_info = vk::VkCommandBufferAllocateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            commandPool: command_pool.handle(),
+            level,
+            commandBufferCount: count,
+        };
+
+        let mut handles = Vec::with_capacity(count as usize);
+        unsafe {
+            handles.set_len(count as usize);
+            ::check(command_pool.device().vk().vkAllocateCommandBuffers(
+                command_pool.device().handle(), &alloc_info, handles.as_mut_ptr()))?;
+        }
+
+        Ok(handles.into_iter().map(|handle| {
+            CommandBuffer {
+                handle,
+                command_pool: command_pool.clone(),
+                resources: Vec::new(),
+                recorded: false,
+            }
+        }).collect())
+    }
+
+    /// Begins recording.
+    ///
+    /// * COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT: The command buffer
+    ///   will be rerecorded right after executing it once.
+    /// * COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT: This is a
+    ///   secondary command buffer that will be entirely within a
+    ///   single render pass.
+    /// * COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT: The command buffer
+    ///   can be resubmitted while it is also already pending
+    ///   execution.
+    ///
+    /// A command buffer may only be recorded again if its pool was created
+    /// with `VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT`.
+    pub fn begin(&mut self, flags: vk::VkCommandBufferUsageFlags) -> VkcResult<()> {
+        if self.recorded && (self.command_pool.flags() &
+                vk::VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT) == 0
+        {
+            return Err("CommandBuffer::begin: The command buffer has already been recorded and \
+                its pool was not created with VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT."
+                .into());
+        }
+
         let begin_info = vk::VkCommandBufferBeginInfo {
             sType: vk::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
             pNext: ptr::null(),
-            // * COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT: The command buffer
-            //   will be rerecorded right after executing it once.
-            // * COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT: This is a
-            //   secondary command buffer that will be entirely within a
-            //   single render pass.
-            // * COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT: The command buffer
-            //   can be resubmitted while it is also already pending
-            //   execution.
-            flags: vk::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT,
+            flags,
             pInheritanceInfo: ptr::null(),
         };
 
         unsafe {
-            ::check(device.vk().core.vkBeginCommandBuffer(command_buffer, &begin_info));
+            ::check(self.device().vk().core.vkBeginCommandBuffer(self.handle, &begin_info))?;
         }
+        // Beginning implicitly resets the command buffer, which the pool's
+        // flags allow.
+        self.recorded = true;
+        self.resources.clear();
+        Ok(())
+    }
 
-        // let clear_color = vk::VkClearValue {
-        //     color: vk::VkClearColorValue { float32: [0.0f32, 0.0f32, 0.0f32, 1.0f32] }
-        // };
-
-        let clear_values = [
-            vk::VkClearValue { color: vk::VkClearColorValue { float32: [0.0f32, 0.0f32, 0.0f32, 1.0f32] } },
-            vk::VkClearValue { depthStencil: vk::VkClearDepthStencilValue { depth: 1.0, stencil: 0, } },
-        ];
+    /// Ends recording.
+    pub fn end(&mut self) -> VkcResult<()> {
+        unsafe { ::check(self.device().vk().core.vkEndCommandBuffer(self.handle))?; }
+        Ok(())
+    }
 
+    pub fn begin_render_pass(&mut self, render_pass: &RenderPass, framebuffer: &Framebuffer,
+            render_area: vk::VkRect2D, clear_values: &[vk::VkClearValue],
+            contents: vk::VkSubpassContents)
+    {
         let render_pass_info = vk::VkRenderPassBeginInfo {
             sType: vk::VK_STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
             pNext: ptr::null(),
             renderPass: render_pass.handle(),
-            framebuffer:swapchain_framebuffer.handle(),
-            renderArea: vk::VkRect2D {
-                offset: vk::VkOffset2D { x: 0, y: 0, },
-                extent: swapchain_extent.clone(),
-            },
+            framebuffer: framebuffer.handle(),
+            renderArea: render_area,
             clearValueCount: clear_values.len() as u32,
             pClearValues: clear_values.as_ptr(),
         };
 
         unsafe {
-            device.vk().core.vkCmdBeginRenderPass(command_buffer, &render_pass_info,
-                vk::VK_SUBPASS_CONTENTS_INLINE);
-            device.vk().core.vkCmdBindPipeline(command_buffer, vk::VK_PIPELINE_BIND_POINT_GRAPHICS,
-                graphics_pipeline.handle());
+            self.device().vk().core.vkCmdBeginRenderPass(self.handle, &render_pass_info, contents);
+        }
+        self.resources.push(Resource::RenderPass(render_pass.clone()));
+        self.resources.push(Resource::Framebuffer(framebuffer.clone()));
+    }
 
-            let vertex_buffers = [vertex_buffer.handle()];
-            let offsets = [0];
-            device.vk().core.vkCmdBindVertexBuffers(command_buffer, 0, 1, vertex_buffers.as_ptr(),
-                offsets.as_ptr());
-            // device.vk().core.vkCmdBindIndexBuffer(command_buffer, index_buffer.handle(), 0,
-            //     vk::VK_INDEX_TYPE_UINT16);
-            device.vk().core.vkCmdBindIndexBuffer(command_buffer, index_buffer.handle(), 0,
-                vk::VK_INDEX_TYPE_UINT32);
+    pub fn end_render_pass(&mut self) {
+        unsafe { self.device().vk().core.vkCmdEndRenderPass(self.handle); }
+    }
 
-            device.vk().core.vkCmdBindDescriptorSets(command_buffer,
-                vk::VK_PIPELINE_BIND_POINT_GRAPHICS, pipeline_layout.handle(), 0, 1,
-                &descriptor_set, 0, ptr::null());
+    pub fn bind_graphics_pipeline(&mut self, pipeline: &GraphicsPipeline) {
+        unsafe {
+            self.device().vk().core.vkCmdBindPipeline(self.handle,
+                vk::VK_PIPELINE_BIND_POINT_GRAPHICS, pipeline.handle());
+        }
+        self.resources.push(Resource::GraphicsPipeline(pipeline.clone()));
+    }
 
-            // // * vertexCount: Even though we don't have a vertex buffer, we
-            // //   technically still have 3 vertices to draw.
-            // // * instanceCount: Used for instanced rendering, use 1 if you're
-            // //   not doing that.
-            // // * firstVertex: Used as an offset into the vertex buffer,
-            // //   defines the lowest value of gl_VertexIndex.
-            // // * firstInstance: Used as an offset for instanced rendering,
-            // //   defines the lowest value of gl_InstanceIndex.
-            // device.vk().core.vkCmdDraw(command_buffer, vertex_count, 1, 0, 0);
-            device.vk().core.vkCmdDrawIndexed(command_buffer, index_count, 1, 0, 0, 0);
+    pub fn bind_compute_pipeline(&mut self, pipeline: &ComputePipeline) {
+        unsafe {
+            self.device().vk().core.vkCmdBindPipeline(self.handle,
+                vk::VK_PIPELINE_BIND_POINT_COMPUTE, pipeline.handle());
+        }
+        self.resources.push(Resource::ComputePipeline(pipeline.clone()));
+    }
 
-            device.vk().core.vkCmdEndRenderPass(command_buffer);
-            device.vk().core.vkEndCommandBuffer(command_buffer);
+    /// Sets the viewports starting at index `first_viewport` for pipelines
+    /// created with `VK_DYNAMIC_STATE_VIEWPORT`.
+    pub fn set_viewport(&mut self, first_viewport: u32, viewports: &[vk::VkViewport]) {
+        unsafe {
+            self.device().vk().core.vkCmdSetViewport(self.handle, first_viewport,
+                viewports.len() as u32, viewports.as_ptr());
         }
     }
-    Ok(command_buffers)
-}
 
+    /// Sets the scissor rectangles starting at index `first_scissor` for
+    /// pipelines created with `VK_DYNAMIC_STATE_SCISSOR`.
+    pub fn set_scissor(&mut self, first_scissor: u32, scissors: &[vk::VkRect2D]) {
+        unsafe {
+            self.device().vk().core.vkCmdSetScissor(self.handle, first_scissor,
+                scissors.len() as u32, scissors.as_ptr());
+        }
+    }
 
+    pub fn bind_descriptor_sets(&mut self, bind_point: vk::VkPipelineBindPoint,
+            pipeline_layout: &PipelineLayout, first_set: u32,
+            descriptor_sets: &[&DescriptorSet], dynamic_offsets: &[u32])
+    {
+        let handles: SmallVec<[vk::VkDescriptorSet; 8]> = descriptor_sets.iter()
+            .map(|set| set.handle())
+            .collect();
 
+        unsafe {
+            self.device().vk().core.vkCmdBindDescriptorSets(self.handle, bind_point,
+                pipeline_layout.handle(), first_set, handles.len() as u32,
+                handles.as_ptr(), dynamic_offsets.len() as u32, dynamic_offsets.as_ptr());
+        }
+        self.resources.push(Resource::PipelineLayout(pipeline_layout.clone()));
+        self.resources.extend(descriptor_sets.iter()
+            .map(|&set| Resource::DescriptorSet(set.clone())));
+    }
 
+    /// Updates push constant values for the stages in `stage_flags`,
+    /// starting at `offset` bytes.
+    pub fn push_constants<T: Copy>(&mut self, pipeline_layout: &PipelineLayout,
+            stage_flags: vk::VkShaderStageFlags, offset: u32, values: &T)
+    {
+        unsafe {
+            self.device().vk().core.vkCmdPushConstants(self.handle, pipeline_layout.handle(),
+                stage_flags, offset, mem::size_of::<T>() as u32, values as *const T as *const c_void);
+        }
+        self.resources.push(Resource::PipelineLayout(pipeline_layout.clone()));
+    }
 
+    /// Binds `buffers` to the vertex input bindings starting at
+    /// `first_binding`, each starting at the corresponding `offsets` entry.
+    pub fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[&Buffer],
+            offsets: &[u64]) -> VkcResult<()>
+    {
+        if buffers.len() != offsets.len() {
+            return Err(format!("CommandBuffer::bind_vertex_buffers: Each buffer must have an \
+                offset ({} buffers, {} offsets).", buffers.len(), offsets.len()).into());
+        }
+        let handles: Vec<_> = buffers.iter().map(|b| b.handle()).collect();
 
-// #[derive(Debug)]
-// struct Inner {
-//     handle: vk::VkCommandBuffer,
-//     device: Device,
-// }
+        unsafe {
+            self.device().vk().core.vkCmdBindVertexBuffers(self.handle, first_binding,
+                handles.len() as u32, handles.as_ptr(), offsets.as_ptr());
+        }
+        self.resources.extend(buffers.iter().map(|&buffer| Resource::Buffer(buffer.clone())));
+        Ok(())
+    }
+
+    pub fn bind_index_buffer(&mut self, buffer: &Buffer, offset: u64, index_type: vk::VkIndexType) {
+        unsafe {
+            self.device().vk().core.vkCmdBindIndexBuffer(self.handle, buffer.handle(), offset,
+                index_type);
+        }
+        self.resources.push(Resource::Buffer(buffer.clone()));
+    }
+
+    /// * vertexCount: The number of vertices to draw.
+    /// * instanceCount: Used for instanced rendering, use 1 if you're
+    ///   not doing that.
+    /// * firstVertex: Used as an offset into the vertex buffer,
+    ///   defines the lowest value of gl_VertexIndex.
+    /// * firstInstance: Used as an offset for instanced rendering,
+    ///   defines the lowest value of gl_InstanceIndex.
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32, first_vertex: u32,
+            first_instance: u32)
+    {
+        unsafe {
+            self.device().vk().core.vkCmdDraw(self.handle, vertex_count, instance_count,
+                first_vertex, first_instance);
+        }
+    }
 
-// #[derive(Debug, Clone)]
-// pub struct CommandBuffer {
-//     inner: Arc<Inner>,
-// }
+    pub fn draw_indexed(&mut self, index_count: u32, instance_count: u32, first_index: u32,
+            vertex_offset: i32, first_instance: u32)
+    {
+        unsafe {
+            self.device().vk().core.vkCmdDrawIndexed(self.handle, index_count, instance_count,
+                first_index, vertex_offset, first_instance);
+        }
+    }
 
-// impl CommandBuffer {
-//     pub fn new() -> VkcResult<CommandBuffer> {
+    /// Dispatches `x * y * z` local workgroups.
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) {
+        unsafe { self.device().vk().core.vkCmdDispatch(self.handle, x, y, z); }
+    }
 
-//         let mut handle = 0;
-//         unsafe {
-//             ::check(device.vk().CreateCommandBuffer(device.handle(), &create_info,
-//                 ptr::null(), &mut handle));
-//         }
+    pub fn copy_buffer(&mut self, src: &Buffer, dst: &Buffer, regions: &[vk::VkBufferCopy]) {
+        unsafe {
+            self.device().vk().core.vkCmdCopyBuffer(self.handle, src.handle(), dst.handle(),
+                regions.len() as u32, regions.as_ptr());
+        }
+        self.resources.push(Resource::Buffer(src.clone()));
+        self.resources.push(Resource::Buffer(dst.clone()));
+    }
 
-//         Ok(CommandBuffer {
-//             inner: Arc::new(Inner {
-//                 handle,
-//                 device,
-//             })
-//         })
-//     }
+    pub fn copy_buffer_to_image(&mut self, src: &Buffer, dst: &Image,
+            dst_layout: vk::VkImageLayout, regions: &[vk::VkBufferImageCopy])
+    {
+        unsafe {
+            self.device().vk().core.vkCmdCopyBufferToImage(self.handle, src.handle(), dst.handle(),
+                dst_layout, regions.len() as u32, regions.as_ptr());
+        }
+        self.resources.push(Resource::Buffer(src.clone()));
+        self.resources.push(Resource::Image(dst.clone()));
+    }
 
-//     pub fn handle(&self) -> vk::VkCommandBuffer {
-//         self.inner.handle
-//     }
+    pub fn copy_image_to_buffer(&mut self, src: &Image, src_layout: vk::VkImageLayout,
+            dst: &Buffer, regions: &[vk::VkBufferImageCopy])
+    {
+        unsafe {
+            self.device().vk().core.vkCmdCopyImageToBuffer(self.handle, src.handle(), src_layout,
+                dst.handle(), regions.len() as u32, regions.as_ptr());
+        }
+        self.resources.push(Resource::Image(src.clone()));
+        self.resources.push(Resource::Buffer(dst.clone()));
+    }
 
-//     pub fn device(&self) -> &Device {
-//         &self.inner.device
-//     }
-// }
+    /// Records a dependency between the `src_stage_mask` stages of commands
+    /// before the barrier and the `dst_stage_mask` stages of those after it.
+    pub fn pipeline_barrier(&mut self, src_stage_mask: vk::VkPipelineStageFlags,
+            dst_stage_mask: vk::VkPipelineStageFlags, dependency_flags: vk::VkDependencyFlags,
+            memory_barriers: &[vk::VkMemoryBarrier], buffer_barriers: &[BufferBarrier],
+            image_barriers: &[ImageBarrier])
+    {
+        let buffer_memory_barriers: SmallVec<[_; 4]> = buffer_barriers.iter()
+            .map(|barrier| barrier.raw())
+            .collect();
+        let image_memory_barriers: SmallVec<[_; 4]> = image_barriers.iter()
+            .map(|barrier| barrier.raw())
+            .collect();
 
-// impl Drop for Inner {
-//     fn drop(&mut self) {
-//         unsafe {
-//             self.device.vk().DestroyCommandBuffer(self.device.handle(), self.handle, ptr::null());
-//         }
-//     }
-// }
\ No newline at end of file
+        unsafe {
+            self.device().vk().core.vkCmdPipelineBarrier(self.handle, src_stage_mask,
+                dst_stage_mask, dependency_flags,
+                memory_barriers.len() as u32, memory_barriers.as_ptr(),
+                buffer_memory_barriers.len() as u32, buffer_memory_barriers.as_ptr(),
+                image_memory_barriers.len() as u32, image_memory_barriers.as_ptr());
+        }
+        self.resources.extend(buffer_barriers.iter()
+            .map(|barrier| Resource::Buffer(barrier.buffer.clone())));
+        self.resources.extend(image_barriers.iter()
+            .map(|barrier| Resource::Image(barrier.image.clone())));
+    }
+
+    pub fn handle(&self) -> vk::VkCommandBuffer {
+        self.handle
+    }
+
+    pub fn command_pool(&self) -> &CommandPool {
+        &self.command_pool
+    }
+
+    pub fn device(&self) -> &Device {
+        self.command_pool.device()
+    }
+}
+
+impl Drop for CommandBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.command_pool.device().vk().core.vkFreeCommandBuffers(
+                self.command_pool.device().handle(), self.command_pool.handle(), 1, &self.handle);
+        }
+    }
+}
+
+
+pub fn create_command_buffers(device: &Device, command_pool: &CommandPool,
+        render_pass: &RenderPass, graphics_pipeline: &GraphicsPipeline,
+        swapchain_framebuffers: &[Framebuffer], swapchain_extent: &vk::VkExtent2D,
+        vertex_buffer: &Buffer, index_buffer: &Buffer, vertex_count: u32,
+        index_count: u32, pipeline_layout: &PipelineLayout,
+        descriptor_set: &DescriptorSet)
+        -> VkcResult<Vec<CommandBuffer>>
+{
+    let mut command_buffers = CommandBuffer::allocate(command_pool.clone(),
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY, swapchain_framebuffers.len() as u32)?;
+
+    for (command_buffer, swapchain_framebuffer) in command_buffers.iter_mut()
+            .zip(swapchain_framebuffers.iter())
+    {
+        command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT)?;
+
+        let clear_values = [
+            vk::VkClearValue { color: vk::VkClearColorValue { float32: [0.0f32, 0.0f32, 0.0f32, 1.0f32] } },
+            vk::VkClearValue { depthStencil: vk::VkClearDepthStencilValue { depth: 1.0, stencil: 0, } },
+        ];
+
+        let render_area = vk::VkRect2D {
+            offset: vk::VkOffset2D { x: 0, y: 0, },
+            extent: swapchain_extent.clone(),
+        };
+
+        command_buffer.begin_render_pass(render_pass, swapchain_framebuffer, render_area.clone(),
+            &clear_values, vk::VK_SUBPASS_CONTENTS_INLINE);
+        command_buffer.bind_graphics_pipeline(graphics_pipeline);
+        command_buffer.set_viewport(0, &[vk::VkViewport {
+            x: 0.0,
+            y: 0.0,
+            width: swapchain_extent.width as f32,
+            height: swapchain_extent.height as f32,
+            minDepth: 0.0,
+            maxDepth: 1.0,
+        }]);
+        command_buffer.set_scissor(0, &[render_area]);
+        command_buffer.bind_vertex_buffers(0, &[vertex_buffer], &[0])?;
+        command_buffer.bind_index_buffer(index_buffer, 0, vk::VK_INDEX_TYPE_UINT32);
+        command_buffer.bind_descriptor_sets(vk::VK_PIPELINE_BIND_POINT_GRAPHICS, pipeline_layout,
+            0, &[descriptor_set], &[]);
+        command_buffer.draw_indexed(index_count, 1, 0, 0, 0);
+        command_buffer.end_render_pass();
+        command_buffer.end()?;
+    }
+    Ok(command_buffers)
+}
@@ -3,12 +3,13 @@ use std::sync::Arc;
 use std::ptr;
 use vk;
 use vks;
-use ::{util, VkcResult, Device, Surface};
+use ::{util, VkcResult, Device, Queue};
 
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkCommandPool,
+    flags: vk::VkCommandPoolCreateFlags,
     device: Device,
 }
 
@@ -18,30 +19,50 @@ pub struct CommandPool {
 }
 
 impl CommandPool {
-    pub fn new(device: Device, surface: &Surface, queue_family_flags: vk::VkQueueFlags)
-        -> VkcResult<CommandPool>
+    /// Creates a command pool for the first queue family supporting
+    /// `queue_family_flags`.
+    ///
+    /// * COMMAND_POOL_CREATE_TRANSIENT_BIT: Command buffers are rerecorded
+    ///   with new commands very often.
+    /// * COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT: Command buffers can
+    ///   be rerecorded individually. Without it, each command buffer may
+    ///   only be recorded once.
+    pub fn new(device: Device, queue_family_flags: vk::VkQueueFlags,
+            flags: vk::VkCommandPoolCreateFlags) -> VkcResult<CommandPool>
     {
-        let queue_family_idx = ::queue_families(device.instance(), surface,
-            device.physical_device(), queue_family_flags).family_idxs()[0];
+        let queue_family_idx = ::queue_families(device.instance(), None,
+            device.physical_device().handle(), queue_family_flags)?.first_family_idx()?;
+        CommandPool::for_family(device, queue_family_idx, flags)
+    }
+
+    /// Creates a command pool for use with the queues of `queue`'s family.
+    /// See `new` for `flags`.
+    pub fn for_queue(queue: &Queue, flags: vk::VkCommandPoolCreateFlags)
+            -> VkcResult<CommandPool>
+    {
+        CommandPool::for_family(queue.device().clone(), queue.family_idx(), flags)
+    }
 
+    fn for_family(device: Device, queue_family_idx: u32, flags: vk::VkCommandPoolCreateFlags)
+            -> VkcResult<CommandPool>
+    {
         let create_info = vk::VkCommandPoolCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
             pNext: ptr::null(),
-            // vk::VK_COMMAND_POOL_CREATE_TRANSIENT_BIT
-            // vk::VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT
-            flags: 0,
-            queueFamilyIndex: queue_family_idx as u32,
+            flags,
+            queueFamilyIndex: queue_family_idx,
         };
 
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreateCommandPool(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(CommandPool {
             inner: Arc::new(Inner {
                 handle,
+                flags,
                 device,
             })
         })
@@ -51,6 +72,10 @@ impl CommandPool {
         self.inner.handle
     }
 
+    pub fn flags(&self) -> vk::VkCommandPoolCreateFlags {
+        self.inner.flags
+    }
+
     pub fn device(&self) -> &Device {
         &self.inner.device
     }
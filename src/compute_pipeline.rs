@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use std::ffi::CString;
+use std::ptr;
+use vk;
+use spirv::ExecutionModel;
+use std_layout::BlockLayout;
+use ::{VkcResult, Device, ShaderModule, PipelineLayout, SpecializationInfo, PipelineCache,
+    AsStd140, AsStd430};
+
+
+/// Configures and creates a `ComputePipeline`.
+#[derive(Debug, Clone)]
+pub struct ComputePipelineBuilder {
+    pipeline_layout: PipelineLayout,
+    module: ShaderModule,
+    entry_point: CString,
+    specialization_info: Option<SpecializationInfo>,
+    pipeline_cache: Option<PipelineCache>,
+    block_layouts: Vec<BlockLayout>,
+}
+
+impl ComputePipelineBuilder {
+    /// Starts a compute pipeline running the function named `entry_point`
+    /// within `module`, which must be declared as a compute entry point.
+    pub fn new(pipeline_layout: &PipelineLayout, module: &ShaderModule, entry_point: &str)
+            -> VkcResult<ComputePipelineBuilder>
+    {
+        let reflection = module.reflection().ok_or("ComputePipelineBuilder::new: Shader \
+            module has no reflection data.")?;
+        let execution_model = reflection.entry_point(entry_point)
+            .ok_or_else(|| format!("ComputePipelineBuilder::new: Shader module has no entry \
+                point named '{}'.", entry_point))?
+            .execution_model;
+        if execution_model != ExecutionModel::GLCompute {
+            return Err(format!("ComputePipelineBuilder::new: Entry point '{}' is not a compute \
+                stage ({:?}).", entry_point, execution_model).into());
+        }
+
+        Ok(ComputePipelineBuilder {
+            pipeline_layout: pipeline_layout.clone(),
+            module: module.clone(),
+            entry_point: CString::new(entry_point)?,
+            specialization_info: None,
+            pipeline_cache: None,
+            block_layouts: Vec::new(),
+        })
+    }
+
+    /// Specializes the entry point with `specialization_info`.
+    pub fn specialization(mut self, specialization_info: &SpecializationInfo)
+            -> ComputePipelineBuilder
+    {
+        self.specialization_info = Some(specialization_info.clone());
+        self
+    }
+
+    /// Looks up and stores the compiled pipeline in `pipeline_cache`.
+    pub fn cache(mut self, pipeline_cache: &PipelineCache) -> ComputePipelineBuilder {
+        self.pipeline_cache = Some(pipeline_cache.clone());
+        self
+    }
+
+    /// Checks when building that the uniform or storage buffer block at
+    /// `set` and `binding` has the layout of `T::Std140`.
+    pub fn std140_block<T: AsStd140>(mut self, set: u32, binding: u32)
+            -> ComputePipelineBuilder
+    {
+        self.block_layouts.push(BlockLayout::std140::<T>(set, binding));
+        self
+    }
+
+    /// Checks when building that the uniform or storage buffer block at
+    /// `set` and `binding` has the layout of `T::Std430`.
+    pub fn std430_block<T: AsStd430>(mut self, set: u32, binding: u32)
+            -> ComputePipelineBuilder
+    {
+        self.block_layouts.push(BlockLayout::std430::<T>(set, binding));
+        self
+    }
+
+    pub fn build(self) -> VkcResult<ComputePipeline> {
+        let device = self.pipeline_layout.device().clone();
+
+        for block in &self.block_layouts {
+            block.check("ComputePipelineBuilder::build", Some(&self.module))?;
+        }
+
+        let raw_specialization_info = self.specialization_info.as_ref().map(|si| si.raw());
+
+        let stage = vk::VkPipelineShaderStageCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            stage: vk::VK_SHADER_STAGE_COMPUTE_BIT,
+            module: self.module.handle(),
+            pName: self.entry_point.as_ptr(),
+            pSpecializationInfo: raw_specialization_info.as_ref()
+                .map(|si| si as *const _).unwrap_or(ptr::null()),
+        };
+
+        let create_info = vk::VkComputePipelineCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            stage,
+            layout: self.pipeline_layout.handle(),
+            basePipelineHandle: 0,
+            basePipelineIndex: -1,
+        };
+
+        let mut handle = 0;
+        unsafe {
+            ::check(device.vk().core.vkCreateComputePipelines(device.handle(),
+                self.pipeline_cache.as_ref().map(|pc| pc.handle()).unwrap_or(0), 1,
+                &create_info, ptr::null(), &mut handle))?;
+        }
+
+        Ok(ComputePipeline {
+            inner: Arc::new(Inner {
+                handle,
+                pipeline_layout: self.pipeline_layout,
+                device,
+            })
+        })
+    }
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkPipeline,
+    pipeline_layout: PipelineLayout,
+    device: Device,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComputePipeline {
+    inner: Arc<Inner>,
+}
+
+impl ComputePipeline {
+    pub fn handle(&self) -> vk::VkPipeline {
+        self.inner.handle
+    }
+
+    pub fn pipeline_layout(&self) -> &PipelineLayout {
+        &self.inner.pipeline_layout
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.inner.device
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.vk().core.vkDestroyPipeline(self.device.handle(), self.handle, ptr::null());
+        }
+    }
+}
@@ -0,0 +1,500 @@
+//! Routing of validation layer and driver debug messages.
+//!
+//! Messages are received through `VK_EXT_debug_utils` when available,
+//! falling back to `VK_EXT_debug_report`, and are passed to a `DebugSink`
+//! (`LogSink` by default).
+
+use std::sync::Arc;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_void};
+use vk;
+use ::{VkcResult, Loader};
+
+
+pub static DEBUG_UTILS_EXTENSION: &str = "VK_EXT_debug_utils";
+pub static DEBUG_REPORT_EXTENSION: &str = "VK_EXT_debug_report";
+
+/// General events, such as loader messages.
+pub const MESSAGE_TYPE_GENERAL: u32 = 0x1;
+/// Specification violations.
+pub const MESSAGE_TYPE_VALIDATION: u32 = 0x2;
+/// Potentially non-optimal use of the API.
+pub const MESSAGE_TYPE_PERFORMANCE: u32 = 0x4;
+
+// `VK_EXT_debug_utils` is not defined by `vks`:
+const VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT: vk::VkStructureType = 1000128004;
+
+const VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT: u32 = 0x0001;
+const VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT: u32 = 0x0010;
+const VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT: u32 = 0x0100;
+const VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT: u32 = 0x1000;
+
+type VkDebugUtilsMessengerEXT = u64;
+
+type PfnDebugUtilsMessengerCallbackEXT = unsafe extern "system" fn(u32, u32,
+    *const VkDebugUtilsMessengerCallbackDataEXT, *mut c_void) -> vk::VkBool32;
+
+#[repr(C)]
+struct VkDebugUtilsLabelEXT {
+    sType: vk::VkStructureType,
+    pNext: *const c_void,
+    pLabelName: *const c_char,
+    color: [f32; 4],
+}
+
+#[repr(C)]
+struct VkDebugUtilsObjectNameInfoEXT {
+    sType: vk::VkStructureType,
+    pNext: *const c_void,
+    objectType: i32,
+    objectHandle: u64,
+    pObjectName: *const c_char,
+}
+
+#[repr(C)]
+struct VkDebugUtilsMessengerCallbackDataEXT {
+    sType: vk::VkStructureType,
+    pNext: *const c_void,
+    flags: u32,
+    pMessageIdName: *const c_char,
+    messageIdNumber: i32,
+    pMessage: *const c_char,
+    queueLabelCount: u32,
+    pQueueLabels: *const VkDebugUtilsLabelEXT,
+    cmdBufLabelCount: u32,
+    pCmdBufLabels: *const VkDebugUtilsLabelEXT,
+    objectCount: u32,
+    pObjects: *const VkDebugUtilsObjectNameInfoEXT,
+}
+
+#[repr(C)]
+struct VkDebugUtilsMessengerCreateInfoEXT {
+    sType: vk::VkStructureType,
+    pNext: *const c_void,
+    flags: u32,
+    messageSeverity: u32,
+    messageType: u32,
+    pfnUserCallback: Option<PfnDebugUtilsMessengerCallbackEXT>,
+    pUserData: *mut c_void,
+}
+
+type PfnCreateDebugUtilsMessengerEXT = unsafe extern "system" fn(vk::VkInstance,
+    *const VkDebugUtilsMessengerCreateInfoEXT, *const vk::VkAllocationCallbacks,
+    *mut VkDebugUtilsMessengerEXT) -> vk::VkResult;
+
+type PfnDestroyDebugUtilsMessengerEXT = unsafe extern "system" fn(vk::VkInstance,
+    VkDebugUtilsMessengerEXT, *const vk::VkAllocationCallbacks);
+
+
+thread_local! {
+    /// An error message received on this thread, to be raised as a panic by
+    /// the next call to `::check`.
+    static PENDING_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Panics if a debug message of `Severity::Error` was received on this thread
+/// by a messenger configured with `DebugConfig::panic_on_error`.
+///
+/// Panicking within the callback itself would unwind across the FFI
+/// boundary, so the panic is deferred to the next checked Vulkan call.
+pub fn raise_pending_error() {
+    if let Some(msg) = PENDING_ERROR.with(|pending| pending.borrow_mut().take()) {
+        panic!("Vulkan debug error: {}", msg);
+    }
+}
+
+/// Invalid UTF-8 is replaced rather than dropping the whole string.
+unsafe fn str_from_ptr<'a>(ptr: *const c_char) -> Option<Cow<'a, str>> {
+    if ptr.is_null() { None } else { Some(CStr::from_ptr(ptr).to_string_lossy()) }
+}
+
+unsafe fn labels<'a>(labels: *const VkDebugUtilsLabelEXT, count: u32) -> Vec<Cow<'a, str>> {
+    if labels.is_null() { return Vec::new(); }
+    slice::from_raw_parts(labels, count as usize).iter()
+        .filter_map(|label| str_from_ptr(label.pLabelName))
+        .collect()
+}
+
+
+/// The severity of a debug message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn from_utils_bits(bits: u32) -> Severity {
+        if bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT != 0 {
+            Severity::Error
+        } else if bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT != 0 {
+            Severity::Warning
+        } else if bits & VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT != 0 {
+            Severity::Info
+        } else {
+            Severity::Verbose
+        }
+    }
+
+    fn from_report_flags(flags: vk::VkDebugReportFlagsEXT) -> Severity {
+        if flags & vk::VK_DEBUG_REPORT_ERROR_BIT_EXT != 0 {
+            Severity::Error
+        } else if flags & (vk::VK_DEBUG_REPORT_WARNING_BIT_EXT |
+                vk::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT) != 0 {
+            Severity::Warning
+        } else if flags & vk::VK_DEBUG_REPORT_INFORMATION_BIT_EXT != 0 {
+            Severity::Info
+        } else {
+            Severity::Verbose
+        }
+    }
+
+    /// Returns the `VK_EXT_debug_utils` severity bits at or above this
+    /// severity.
+    fn utils_bits_at_least(self) -> u32 {
+        [(Severity::Verbose, VK_DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT),
+            (Severity::Info, VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT),
+            (Severity::Warning, VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT),
+            (Severity::Error, VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT)]
+            .iter().filter(|&&(sev, _)| sev >= self).fold(0, |bits, &(_, bit)| bits | bit)
+    }
+
+    /// Returns the `VK_EXT_debug_report` flags at or above this severity.
+    fn report_flags_at_least(self) -> vk::VkDebugReportFlagsEXT {
+        [(Severity::Verbose, vk::VK_DEBUG_REPORT_DEBUG_BIT_EXT),
+            (Severity::Info, vk::VK_DEBUG_REPORT_INFORMATION_BIT_EXT),
+            (Severity::Warning, vk::VK_DEBUG_REPORT_WARNING_BIT_EXT |
+                vk::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT),
+            (Severity::Error, vk::VK_DEBUG_REPORT_ERROR_BIT_EXT)]
+            .iter().filter(|&&(sev, _)| sev >= self).fold(0, |flags, &(_, flag)| flags | flag)
+    }
+}
+
+
+/// An object referenced by a debug message.
+#[derive(Debug, Clone)]
+pub struct DebugObject<'m> {
+    /// A `VkObjectType` (debug utils) or `VkDebugReportObjectTypeEXT` (debug
+    /// report) value.
+    pub object_type: i32,
+    pub handle: u64,
+    pub name: Option<Cow<'m, str>>,
+}
+
+/// A message received from a validation layer, the loader or a driver.
+#[derive(Debug, Clone)]
+pub struct DebugMessage<'m> {
+    pub severity: Severity,
+    /// A combination of the `MESSAGE_TYPE_*` bits.
+    pub message_type: u32,
+    /// The message identifier name or, with debug report, the layer prefix.
+    pub id_name: Option<Cow<'m, str>>,
+    pub id_number: i32,
+    pub message: Cow<'m, str>,
+    pub objects: Vec<DebugObject<'m>>,
+    pub queue_labels: Vec<Cow<'m, str>>,
+    pub command_buffer_labels: Vec<Cow<'m, str>>,
+}
+
+impl<'m> fmt::Display for DebugMessage<'m> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref id_name) = self.id_name {
+            write!(f, "[{}] ", id_name)?;
+        }
+        write!(f, "{}", self.message)?;
+        for obj in &self.objects {
+            write!(f, " (object type {}: {:#x}", obj.object_type, obj.handle)?;
+            if let Some(ref name) = obj.name { write!(f, " '{}'", name)?; }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+
+/// A destination for debug messages.
+///
+/// Messages may be delivered from any thread which calls into Vulkan.
+pub trait DebugSink: Send + Sync {
+    fn message(&self, message: &DebugMessage);
+}
+
+/// Forwards debug messages to the `log` crate under the `vkc::debug` target.
+///
+/// Errors and warnings are logged at their respective levels, info messages
+/// at `Debug` and verbose messages at `Trace`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogSink;
+
+impl DebugSink for LogSink {
+    fn message(&self, message: &DebugMessage) {
+        let level = match message.severity {
+            Severity::Error => ::log::Level::Error,
+            Severity::Warning => ::log::Level::Warn,
+            Severity::Info => ::log::Level::Debug,
+            Severity::Verbose => ::log::Level::Trace,
+        };
+        log!(target: "vkc::debug", level, "{}", message);
+    }
+}
+
+
+/// Debug messenger settings.
+#[derive(Clone)]
+pub struct DebugConfig {
+    sink: Arc<dyn DebugSink>,
+    min_severity: Severity,
+    panic_on_error: bool,
+}
+
+impl DebugConfig {
+    /// Returns a configuration sending all messages to `LogSink`.
+    pub fn new() -> DebugConfig {
+        DebugConfig {
+            sink: Arc::new(LogSink),
+            min_severity: Severity::Verbose,
+            panic_on_error: false,
+        }
+    }
+
+    /// Sends messages to `sink` instead of `LogSink`.
+    pub fn sink<S: DebugSink + 'static>(mut self, sink: S) -> DebugConfig {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    /// Subscribes only to messages of `severity` or above.
+    pub fn min_severity(mut self, severity: Severity) -> DebugConfig {
+        self.min_severity = severity;
+        self
+    }
+
+    /// Escalates error messages to panics, raised by the next checked Vulkan
+    /// call on the thread which received them (see `raise_pending_error`).
+    /// Intended for tests.
+    pub fn panic_on_error(mut self, panic_on_error: bool) -> DebugConfig {
+        self.panic_on_error = panic_on_error;
+        self
+    }
+
+    fn dispatch(&self, message: &DebugMessage) {
+        // Unwinding out of the callback is not allowed, so panics within the
+        // sink are discarded:
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| self.sink.message(message)));
+
+        if self.panic_on_error && message.severity == Severity::Error {
+            let msg = message.to_string();
+            PENDING_ERROR.with(|pending| *pending.borrow_mut() = Some(msg));
+        }
+    }
+}
+
+impl Default for DebugConfig {
+    fn default() -> DebugConfig {
+        DebugConfig::new()
+    }
+}
+
+impl fmt::Debug for DebugConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DebugConfig")
+            .field("min_severity", &self.min_severity)
+            .field("panic_on_error", &self.panic_on_error)
+            .finish()
+    }
+}
+
+
+unsafe extern "system" fn __debug_utils_callback(severity: u32, message_type: u32,
+        data: *const VkDebugUtilsMessengerCallbackDataEXT, user_data: *mut c_void) -> vk::VkBool32
+{
+    let config = &*(user_data as *const DebugConfig);
+    let data = &*data;
+
+    let objects = if data.pObjects.is_null() {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(data.pObjects, data.objectCount as usize).iter().map(|obj|
+            DebugObject {
+                object_type: obj.objectType,
+                handle: obj.objectHandle,
+                name: str_from_ptr(obj.pObjectName),
+            }).collect()
+    };
+
+    config.dispatch(&DebugMessage {
+        severity: Severity::from_utils_bits(severity),
+        message_type,
+        id_name: str_from_ptr(data.pMessageIdName),
+        id_number: data.messageIdNumber,
+        message: str_from_ptr(data.pMessage).unwrap_or_default(),
+        objects,
+        queue_labels: labels(data.pQueueLabels, data.queueLabelCount),
+        command_buffer_labels: labels(data.pCmdBufLabels, data.cmdBufLabelCount),
+    });
+    vk::VK_FALSE
+}
+
+unsafe extern "system" fn __debug_report_callback(flags: vk::VkDebugReportFlagsEXT,
+        obj_type: vk::VkDebugReportObjectTypeEXT, obj: u64, _location: usize, code: i32,
+        layer_prefix: *const c_char, msg: *const c_char, user_data: *mut c_void) -> vk::VkBool32
+{
+    let config = &*(user_data as *const DebugConfig);
+
+    let message_type = if flags & vk::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT != 0 {
+        MESSAGE_TYPE_PERFORMANCE
+    } else if flags & (vk::VK_DEBUG_REPORT_ERROR_BIT_EXT | vk::VK_DEBUG_REPORT_WARNING_BIT_EXT) != 0 {
+        MESSAGE_TYPE_VALIDATION
+    } else {
+        MESSAGE_TYPE_GENERAL
+    };
+
+    let objects = if obj == 0 {
+        Vec::new()
+    } else {
+        vec![DebugObject { object_type: obj_type as i32, handle: obj, name: None }]
+    };
+
+    config.dispatch(&DebugMessage {
+        severity: Severity::from_report_flags(flags),
+        message_type,
+        id_name: str_from_ptr(layer_prefix),
+        id_number: code,
+        message: str_from_ptr(msg).unwrap_or_default(),
+        objects,
+        queue_labels: Vec::new(),
+        command_buffer_labels: Vec::new(),
+    });
+    vk::VK_FALSE
+}
+
+
+#[derive(Debug)]
+enum Handle {
+    Utils(VkDebugUtilsMessengerEXT),
+    Report(vk::VkDebugReportCallbackEXT),
+}
+
+/// A debug utils messenger or debug report callback, owned by an instance.
+#[derive(Debug)]
+pub struct DebugMessenger {
+    handle: Handle,
+    // Referenced by the callback's user data pointer:
+    config: Box<DebugConfig>,
+}
+
+impl DebugMessenger {
+    /// Creates a messenger through `VK_EXT_debug_utils` if
+    /// `debug_utils_enabled`, otherwise through `VK_EXT_debug_report`.
+    ///
+    /// The extension used must have been enabled on `instance` and, for
+    /// debug report, its function pointers loaded.
+    pub unsafe fn new(loader: &Loader, instance: vk::VkInstance, debug_utils_enabled: bool,
+            config: DebugConfig) -> VkcResult<DebugMessenger>
+    {
+        let config = Box::new(config);
+        let user_data = &*config as *const DebugConfig as *mut c_void;
+
+        let handle = if debug_utils_enabled {
+            let create_info = VkDebugUtilsMessengerCreateInfoEXT {
+                sType: VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+                pNext: ptr::null(),
+                flags: 0,
+                messageSeverity: config.min_severity.utils_bits_at_least(),
+                messageType: MESSAGE_TYPE_GENERAL | MESSAGE_TYPE_VALIDATION | MESSAGE_TYPE_PERFORMANCE,
+                pfnUserCallback: Some(__debug_utils_callback),
+                pUserData: user_data,
+            };
+
+            let create: PfnCreateDebugUtilsMessengerEXT = mem::transmute(
+                proc_addr(loader, instance, b"vkCreateDebugUtilsMessengerEXT\0")?);
+            let mut messenger = 0;
+            ::check(create(instance, &create_info, ptr::null(), &mut messenger))?;
+            Handle::Utils(messenger)
+        } else {
+            let create_info = vk::VkDebugReportCallbackCreateInfoEXT {
+                sType: vk::VK_STRUCTURE_TYPE_DEBUG_REPORT_CALLBACK_CREATE_INFO_EXT,
+                pNext: ptr::null(),
+                flags: config.min_severity.report_flags_at_least(),
+                pfnCallback: Some(__debug_report_callback),
+                pUserData: user_data,
+            };
+
+            let mut callback: vk::VkDebugReportCallbackEXT = 0;
+            ::check(loader.loader().ext_debug_report.vkCreateDebugReportCallbackEXT(instance,
+                &create_info, ptr::null(), &mut callback))?;
+            Handle::Report(callback)
+        };
+
+        Ok(DebugMessenger { handle, config })
+    }
+
+    /// Destroys the messenger. Must be called before `instance` is destroyed.
+    pub unsafe fn destroy(&self, loader: &Loader, instance: vk::VkInstance) {
+        match self.handle {
+            Handle::Utils(messenger) => {
+                if let Ok(destroy) = proc_addr(loader, instance, b"vkDestroyDebugUtilsMessengerEXT\0") {
+                    let destroy: PfnDestroyDebugUtilsMessengerEXT = mem::transmute(destroy);
+                    destroy(instance, messenger, ptr::null());
+                }
+            },
+            Handle::Report(callback) => {
+                loader.loader().ext_debug_report.vkDestroyDebugReportCallbackEXT(instance,
+                    callback, ptr::null());
+            },
+        }
+    }
+}
+
+unsafe fn proc_addr(loader: &Loader, instance: vk::VkInstance, name: &[u8])
+        -> VkcResult<unsafe extern "system" fn()>
+{
+    let get_instance_proc_addr = loader.get_instance_proc_addr(instance, ptr::null())
+        .ok_or("vkGetInstanceProcAddr unavailable.")?;
+    get_instance_proc_addr(instance, name.as_ptr() as *const c_char).ok_or_else(|| format!(
+        "Unable to load '{}'.", String::from_utf8_lossy(&name[..name.len() - 1])).into())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_bits() {
+        assert_eq!(Severity::Warning.utils_bits_at_least(),
+            VK_DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT | VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT);
+        assert_eq!(Severity::Error.report_flags_at_least(), vk::VK_DEBUG_REPORT_ERROR_BIT_EXT);
+        assert_eq!(Severity::from_utils_bits(VK_DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT), Severity::Info);
+        assert_eq!(Severity::from_report_flags(vk::VK_DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT),
+            Severity::Warning);
+    }
+
+    #[test]
+    fn pending_error_raised() {
+        let config = DebugConfig::new().panic_on_error(true);
+        let message = DebugMessage {
+            severity: Severity::Error,
+            message_type: MESSAGE_TYPE_VALIDATION,
+            id_name: Some("VUID-test".into()),
+            id_number: 0,
+            message: "test error".into(),
+            objects: Vec::new(),
+            queue_labels: Vec::new(),
+            command_buffer_labels: Vec::new(),
+        };
+        config.dispatch(&message);
+        assert!(panic::catch_unwind(|| raise_pending_error()).is_err());
+        // The pending error is cleared once raised:
+        raise_pending_error();
+    }
+}
@@ -0,0 +1,196 @@
+use vk;
+use ::{VkcResult, Device, DescriptorPool, DescriptorSetLayout, DescriptorSet};
+
+
+/// The number of sets each pool holds unless otherwise specified.
+const DEFAULT_SETS_PER_POOL: u32 = 64;
+
+
+/// Returns the default number of descriptors of each type per pool, scaled
+/// to `sets_per_pool`.
+fn default_pool_sizes(sets_per_pool: u32) -> Vec<vk::VkDescriptorPoolSize> {
+    [
+        (vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER, 2),
+        (vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER, 2),
+        (vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER, 2),
+        (vk::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE, 1),
+        (vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE, 1),
+        (vk::VK_DESCRIPTOR_TYPE_SAMPLER, 1),
+    ].iter().map(|&(type_, per_set)| vk::VkDescriptorPoolSize {
+        type_,
+        descriptorCount: per_set * sets_per_pool,
+    }).collect()
+}
+
+
+/// Allocates descriptor sets from a growing list of `DescriptorPool`s,
+/// creating a new pool whenever the current one is exhausted.
+///
+/// Keep one allocator per frame in flight and call `reset` once that
+/// frame's command buffers have completed to recycle every set at once.
+#[derive(Debug)]
+pub struct DescriptorAllocator {
+    device: Device,
+    flags: vk::VkDescriptorPoolCreateFlags,
+    sets_per_pool: u32,
+    pool_sizes: Vec<vk::VkDescriptorPoolSize>,
+    pools: Vec<DescriptorPool>,
+    // The index of the pool currently being allocated from. Pools before it
+    // are full and pools after it were emptied by `reset`.
+    current_pool: usize,
+    // The number of sets, and of descriptors of each of `pool_sizes`,
+    // allocated from the current pool. Allocating beyond a pool's capacity is
+    // invalid usage without `VK_KHR_maintenance1`, so pools are switched
+    // before they overflow rather than after an allocation fails.
+    current_sets: u32,
+    current_descriptors: Vec<u32>,
+}
+
+impl DescriptorAllocator {
+    /// Creates an allocator whose pools use a default mix of descriptor
+    /// types.
+    ///
+    /// Individual sets may only be freed if `flags` contains
+    /// `VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT`.
+    pub fn new(device: Device, flags: vk::VkDescriptorPoolCreateFlags) -> DescriptorAllocator {
+        DescriptorAllocator::with_pool_sizes(device, flags, DEFAULT_SETS_PER_POOL,
+            &default_pool_sizes(DEFAULT_SETS_PER_POOL))
+    }
+
+    /// Creates an allocator whose pools each hold `sets_per_pool` sets and
+    /// the descriptors listed in `pool_sizes`.
+    pub fn with_pool_sizes(device: Device, flags: vk::VkDescriptorPoolCreateFlags,
+            sets_per_pool: u32, pool_sizes: &[vk::VkDescriptorPoolSize]) -> DescriptorAllocator
+    {
+        DescriptorAllocator {
+            device,
+            flags,
+            sets_per_pool,
+            pool_sizes: pool_sizes.to_vec(),
+            pools: Vec::new(),
+            current_pool: 0,
+            current_sets: 0,
+            current_descriptors: vec![0; pool_sizes.len()],
+        }
+    }
+
+    /// Returns the current pool, creating one if necessary.
+    fn pool(&mut self) -> VkcResult<DescriptorPool> {
+        if self.current_pool == self.pools.len() {
+            let pool = DescriptorPool::new(self.device.clone(), self.flags, self.sets_per_pool,
+                &self.pool_sizes)?;
+            debug!("Created descriptor pool {} ({} sets).", self.pools.len(), self.sets_per_pool);
+            self.pools.push(pool);
+        }
+        Ok(self.pools[self.current_pool].clone())
+    }
+
+    /// Returns the number of descriptors of each of the pool sizes required
+    /// by a set with `layout`.
+    fn required_descriptors(&self, layout: &DescriptorSetLayout) -> VkcResult<Vec<u32>> {
+        let mut required = vec![0u32; self.pool_sizes.len()];
+        for binding in layout.bindings() {
+            let pos = self.pool_sizes.iter()
+                .position(|size| size.type_ == binding.descriptor_type())
+                .ok_or_else(|| format!("DescriptorAllocator::allocate: Binding {} has a \
+                    descriptor type ({}) which the pools do not hold.", binding.binding(),
+                    binding.descriptor_type()))?;
+            required[pos] += binding.count();
+            if required[pos] > self.pool_sizes[pos].descriptorCount {
+                return Err(format!("DescriptorAllocator::allocate: The layout requires more \
+                    descriptors of type {} than each pool holds ({}).",
+                    binding.descriptor_type(), self.pool_sizes[pos].descriptorCount).into());
+            }
+        }
+        Ok(required)
+    }
+
+    /// Returns true if the current pool has room for another set requiring
+    /// `required` descriptors.
+    fn current_pool_fits(&self, required: &[u32]) -> bool {
+        self.current_sets < self.sets_per_pool &&
+            self.current_descriptors.iter().zip(required).zip(&self.pool_sizes)
+                .all(|((&used, &req), size)| used + req <= size.descriptorCount)
+    }
+
+    /// Moves on to the next pool.
+    fn next_pool(&mut self) {
+        self.current_pool += 1;
+        self.current_sets = 0;
+        for used in &mut self.current_descriptors { *used = 0; }
+    }
+
+    /// Allocates a set with `layout`, moving on to a new pool if the current
+    /// one is out of space.
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout) -> VkcResult<DescriptorSet> {
+        if self.sets_per_pool == 0 {
+            return Err("DescriptorAllocator::allocate: Pools can not hold any sets.".into());
+        }
+        let required = self.required_descriptors(layout)?;
+        if !self.current_pool_fits(&required) {
+            self.next_pool();
+        }
+
+        let set = match self.pool()?.allocate(layout) {
+            Ok(set) => set,
+            // Pools may still become fragmented when sets are freed:
+            Err(ref err) if err.vk_result_code() == Some(vk::VK_ERROR_FRAGMENTED_POOL) => {
+                self.next_pool();
+                self.pool()?.allocate(layout).map_err(|err| {
+                    err.chain("DescriptorAllocator::allocate: Unable to allocate a set from an \
+                        empty pool.")
+                })?
+            },
+            Err(err) => return Err(err),
+        };
+
+        self.current_sets += 1;
+        for (used, req) in self.current_descriptors.iter_mut().zip(required) {
+            *used += req;
+        }
+        Ok(set)
+    }
+
+    /// Returns `set` to the pool it was allocated from.
+    ///
+    /// Requires `VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT`.
+    ///
+    /// # Safety
+    ///
+    /// `set` must not be in use by any pending command buffer and no copies
+    /// of it may be used afterwards.
+    pub unsafe fn free(&mut self, set: DescriptorSet) -> VkcResult<()> {
+        if !self.pools.iter().any(|pool| pool.handle() == set.pool().handle()) {
+            return Err("DescriptorAllocator::free: Set was not allocated by this \
+                allocator.".into());
+        }
+        let pool = set.pool().clone();
+        pool.free(set)
+    }
+
+    /// Returns every set allocated so far to its pool. Pools are kept and
+    /// reused by subsequent allocations.
+    ///
+    /// # Safety
+    ///
+    /// All sets allocated so far become invalid. None may be in use by any
+    /// pending command buffer and no copies of them may be used afterwards.
+    pub unsafe fn reset(&mut self) -> VkcResult<()> {
+        for pool in &self.pools {
+            pool.reset()?;
+        }
+        self.current_pool = 0;
+        self.current_sets = 0;
+        for used in &mut self.current_descriptors { *used = 0; }
+        Ok(())
+    }
+
+    /// Returns the number of pools created.
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+}
@@ -1,12 +1,13 @@
-
 use std::sync::Arc;
 use std::ptr;
 use vk;
-use ::{util, VkcResult, Device};
+use ::{VkcResult, Device, DescriptorSetLayout, DescriptorSet};
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkDescriptorPool,
+    flags: vk::VkDescriptorPoolCreateFlags,
+    max_sets: u32,
     device: Device,
 }
 
@@ -16,26 +17,19 @@ pub struct DescriptorPool {
 }
 
 impl DescriptorPool {
-    pub fn new(device: Device) -> VkcResult<DescriptorPool> {
-        let pool_sizes = [
-            vk::VkDescriptorPoolSize {
-                type_: vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-                descriptorCount: 1,
-            },
-            vk::VkDescriptorPoolSize {
-                type_: vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-                descriptorCount: 1,
-            },
-        ];
-
+    /// Creates a pool holding at most `max_sets` sets and, in total, the
+    /// number of descriptors of each type listed in `pool_sizes`.
+    ///
+    /// Individual sets may only be freed if `flags` contains
+    /// `VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT`.
+    pub fn new(device: Device, flags: vk::VkDescriptorPoolCreateFlags, max_sets: u32,
+            pool_sizes: &[vk::VkDescriptorPoolSize]) -> VkcResult<DescriptorPool>
+    {
         let create_info = vk::VkDescriptorPoolCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
             pNext: ptr::null(),
-            // optional flag similar to command pools that determines if
-            // individual descriptor sets can be freed or not:
-            // `VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT`:
-            flags: 0,
-            maxSets: 1,
+            flags,
+            maxSets: max_sets,
             poolSizeCount: pool_sizes.len() as u32,
             pPoolSizes: pool_sizes.as_ptr(),
         };
@@ -43,17 +37,91 @@ impl DescriptorPool {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().vkCreateDescriptorPool(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(DescriptorPool {
             inner: Arc::new(Inner {
                 handle,
+                flags,
+                max_sets,
                 device,
             })
         })
     }
 
+    /// Allocates a single set with `layout`.
+    pub fn allocate(&self, layout: &DescriptorSetLayout) -> VkcResult<DescriptorSet> {
+        let layouts = [layout.handle()];
+
+        let alloc_info = vk::VkDescriptorSetAllocateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            descriptorPool: self.handle(),
+            descriptorSetCount: layouts.len() as u32,
+            pSetLayouts: layouts.as_ptr(),
+        };
+
+        let mut handle = 0;
+        unsafe {
+            ::check(self.device().vk().vkAllocateDescriptorSets(self.device().handle(),
+                &alloc_info, &mut handle))?;
+        }
+
+        Ok(unsafe { DescriptorSet::from_parts(handle, self.clone(), layout.clone()) })
+    }
+
+    /// Returns `set` to this pool.
+    ///
+    /// The pool must have been created with
+    /// `VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT`.
+    ///
+    /// # Safety
+    ///
+    /// `set` must not be in use by any pending command buffer and no copies
+    /// of it may be used afterwards.
+    pub unsafe fn free(&self, set: DescriptorSet) -> VkcResult<()> {
+        if !self.can_free_sets() {
+            return Err("DescriptorPool::free: Pool was not created with \
+                'VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT'.".into());
+        }
+        if set.pool().handle() != self.handle() {
+            return Err("DescriptorPool::free: Set was not allocated from this pool.".into());
+        }
+
+        let handles = [set.handle()];
+        ::check(self.device().vk().vkFreeDescriptorSets(self.device().handle(), self.handle(),
+            handles.len() as u32, handles.as_ptr()))?;
+        Ok(())
+    }
+
+    /// Returns every set allocated from this pool to it at once.
+    ///
+    /// # Safety
+    ///
+    /// All previously allocated sets become invalid. None may be in use by
+    /// any pending command buffer and no copies of them may be used
+    /// afterwards.
+    pub unsafe fn reset(&self) -> VkcResult<()> {
+        ::check(self.device().vk().vkResetDescriptorPool(self.device().handle(),
+            self.handle(), 0))?;
+        Ok(())
+    }
+
+    /// Returns true if sets allocated from this pool may be freed
+    /// individually.
+    pub fn can_free_sets(&self) -> bool {
+        self.inner.flags & vk::VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT != 0
+    }
+
+    pub fn flags(&self) -> vk::VkDescriptorPoolCreateFlags {
+        self.inner.flags
+    }
+
+    pub fn max_sets(&self) -> u32 {
+        self.inner.max_sets
+    }
+
     pub fn handle(&self) -> vk::VkDescriptorPool {
         self.inner.handle
     }
@@ -69,4 +137,4 @@ impl Drop for Inner {
             self.device.vk().vkDestroyDescriptorPool(self.device.handle(), self.handle, ptr::null());
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex};
+use std::ptr;
+use vk;
+use ::{VkcResult, Device, DescriptorPool, DescriptorSetLayout, Buffer, ImageView, Sampler};
+
+
+/// An object referenced by a written descriptor.
+// Only held to keep the object alive, never read.
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Resource {
+    Buffer(Buffer),
+    ImageView(ImageView),
+    Sampler(Sampler),
+}
+
+
+/// A descriptor set allocated from a `DescriptorPool`.
+///
+/// Sets are not freed when dropped. They are returned to their pool by
+/// `DescriptorPool::free`, `DescriptorAllocator::free`, or by resetting the
+/// pool, all of which are unsafe since any remaining copies must no longer
+/// be used.
+///
+/// Objects written to the set are kept alive until they are overwritten or
+/// every copy of the set has been dropped.
+#[derive(Debug, Clone)]
+pub struct DescriptorSet {
+    handle: vk::VkDescriptorSet,
+    pool: DescriptorPool,
+    layout: DescriptorSetLayout,
+    // (binding, array element, objects referenced), shared between copies.
+    resources: Arc<Mutex<Vec<(u32, u32, Vec<Resource>)>>>,
+}
+
+impl DescriptorSet {
+    /// Wraps a set allocated from `pool` with `layout`.
+    pub unsafe fn from_parts(handle: vk::VkDescriptorSet, pool: DescriptorPool,
+            layout: DescriptorSetLayout) -> DescriptorSet
+    {
+        DescriptorSet { handle, pool, layout, resources: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Returns the descriptor type of `binding`, checking that it is one of
+    /// `allowed` and has an element at `array_element`.
+    fn binding_type(&self, binding: u32, array_element: u32, allowed: &[vk::VkDescriptorType])
+            -> VkcResult<vk::VkDescriptorType>
+    {
+        let layout_binding = self.layout.binding(binding)
+            .ok_or_else(|| format!("DescriptorSet: Layout has no binding {}.", binding))?;
+        let descriptor_type = layout_binding.descriptor_type();
+        if !allowed.contains(&descriptor_type) {
+            return Err(format!("DescriptorSet: Binding {} has descriptor type {} which can not \
+                be written with this method.", binding, descriptor_type).into());
+        }
+        if array_element >= layout_binding.count() {
+            return Err(format!("DescriptorSet: Binding {} has {} elements but element {} was \
+                written.", binding, layout_binding.count(), array_element).into());
+        }
+        Ok(descriptor_type)
+    }
+
+    fn write(&self, binding: u32, array_element: u32, descriptor_type: vk::VkDescriptorType,
+            image_info: *const vk::VkDescriptorImageInfo,
+            buffer_info: *const vk::VkDescriptorBufferInfo, referenced: Vec<Resource>)
+    {
+        let write = vk::VkWriteDescriptorSet {
+            sType: vk::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+            pNext: ptr::null(),
+            dstSet: self.handle,
+            dstBinding: binding,
+            dstArrayElement: array_element,
+            descriptorCount: 1,
+            descriptorType: descriptor_type,
+            pImageInfo: image_info,
+            pBufferInfo: buffer_info,
+            pTexelBufferView: ptr::null(),
+        };
+
+        unsafe {
+            self.device().vk().vkUpdateDescriptorSets(self.device().handle(), 1, &write, 0,
+                ptr::null());
+        }
+
+        // Replaces (and so releases) whatever the element referenced before:
+        let mut resources = self.resources.lock().unwrap_or_else(|err| err.into_inner());
+        resources.retain(|&(b, e, _)| b != binding || e != array_element);
+        resources.push((binding, array_element, referenced));
+    }
+
+    /// Points element `array_element` of a uniform or storage buffer
+    /// `binding` at `range` bytes of `buffer` starting at `offset`.
+    pub fn write_buffer(&self, binding: u32, array_element: u32, buffer: &Buffer, offset: u64,
+            range: u64) -> VkcResult<()>
+    {
+        let descriptor_type = self.binding_type(binding, array_element, &[
+            vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
+            vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+            vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+            vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC,
+        ])?;
+        let in_bounds = offset < buffer.size() && (range == vk::VK_WHOLE_SIZE ||
+            (range > 0 && offset.checked_add(range).map(|end| end <= buffer.size())
+                .unwrap_or(false)));
+        if !in_bounds {
+            return Err(format!("DescriptorSet::write_buffer: Range of {} bytes at offset {} is \
+                outside of the buffer ({} bytes).", range, offset, buffer.size()).into());
+        }
+
+        let buffer_info = vk::VkDescriptorBufferInfo {
+            buffer: buffer.handle(),
+            offset,
+            range,
+        };
+        self.write(binding, array_element, descriptor_type, ptr::null(), &buffer_info,
+            vec![Resource::Buffer(buffer.clone())]);
+        Ok(())
+    }
+
+    /// Points element `array_element` of a sampled image, storage image or
+    /// input attachment `binding` at `image_view`, which will be in
+    /// `image_layout` when accessed.
+    pub fn write_image(&self, binding: u32, array_element: u32, image_view: &ImageView,
+            image_layout: vk::VkImageLayout) -> VkcResult<()>
+    {
+        let descriptor_type = self.binding_type(binding, array_element, &[
+            vk::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE,
+            vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,
+            vk::VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT,
+        ])?;
+
+        let image_info = vk::VkDescriptorImageInfo {
+            sampler: 0,
+            imageView: image_view.handle(),
+            imageLayout: image_layout,
+        };
+        self.write(binding, array_element, descriptor_type, &image_info, ptr::null(),
+            vec![Resource::ImageView(image_view.clone())]);
+        Ok(())
+    }
+
+    /// Points element `array_element` of a sampler `binding` at `sampler`.
+    pub fn write_sampler(&self, binding: u32, array_element: u32, sampler: &Sampler)
+            -> VkcResult<()>
+    {
+        let descriptor_type = self.binding_type(binding, array_element,
+            &[vk::VK_DESCRIPTOR_TYPE_SAMPLER])?;
+
+        let image_info = vk::VkDescriptorImageInfo {
+            sampler: sampler.handle(),
+            imageView: 0,
+            imageLayout: vk::VK_IMAGE_LAYOUT_UNDEFINED,
+        };
+        self.write(binding, array_element, descriptor_type, &image_info, ptr::null(),
+            vec![Resource::Sampler(sampler.clone())]);
+        Ok(())
+    }
+
+    /// Points element `array_element` of a combined image sampler `binding`
+    /// at `image_view`, which will be in `image_layout` when accessed, and
+    /// `sampler`.
+    pub fn write_combined_image_sampler(&self, binding: u32, array_element: u32,
+            image_view: &ImageView, image_layout: vk::VkImageLayout, sampler: &Sampler)
+            -> VkcResult<()>
+    {
+        let descriptor_type = self.binding_type(binding, array_element,
+            &[vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER])?;
+
+        let image_info = vk::VkDescriptorImageInfo {
+            sampler: sampler.handle(),
+            imageView: image_view.handle(),
+            imageLayout: image_layout,
+        };
+        self.write(binding, array_element, descriptor_type, &image_info, ptr::null(),
+            vec![Resource::ImageView(image_view.clone()), Resource::Sampler(sampler.clone())]);
+        Ok(())
+    }
+
+    pub fn handle(&self) -> vk::VkDescriptorSet {
+        self.handle
+    }
+
+    pub fn pool(&self) -> &DescriptorPool {
+        &self.pool
+    }
+
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    pub fn device(&self) -> &Device {
+        self.pool.device()
+    }
+}
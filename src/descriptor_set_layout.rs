@@ -1,61 +1,173 @@
-
 use std::sync::Arc;
 use std::ptr;
 use vk;
-use ::{util, VkcResult, Device};
+use ::{VkcResult, Device, Sampler};
+
+
+/// A single binding within a `DescriptorSetLayout`.
+#[derive(Debug, Clone)]
+pub struct DescriptorBinding {
+    binding: u32,
+    descriptor_type: vk::VkDescriptorType,
+    count: u32,
+    stage_flags: vk::VkShaderStageFlags,
+    immutable_samplers: Vec<Sampler>,
+}
+
+impl DescriptorBinding {
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    pub fn descriptor_type(&self) -> vk::VkDescriptorType {
+        self.descriptor_type
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn stage_flags(&self) -> vk::VkShaderStageFlags {
+        self.stage_flags
+    }
+
+    pub fn immutable_samplers(&self) -> &[Sampler] {
+        &self.immutable_samplers
+    }
+}
 
+
+/// Configures and creates a `DescriptorSetLayout` from a list of bindings.
 #[derive(Debug)]
-struct Inner {
-    handle: vk::VkDescriptorSetLayout,
+pub struct DescriptorSetLayoutBuilder {
     device: Device,
+    bindings: Vec<DescriptorBinding>,
 }
 
-#[derive(Debug, Clone)]
-pub struct DescriptorSetLayout {
-    inner: Arc<Inner>,
-}
+impl DescriptorSetLayoutBuilder {
+    pub fn new(device: Device) -> DescriptorSetLayoutBuilder {
+        DescriptorSetLayoutBuilder {
+            device,
+            bindings: Vec::new(),
+        }
+    }
 
-impl DescriptorSetLayout {
-    pub fn new(device: Device) -> VkcResult<DescriptorSetLayout> {
-        let ubo_layout_binding = vk::VkDescriptorSetLayoutBinding {
-            binding: 0,
-            descriptorType: vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER,
-            descriptorCount: 1,
-            stageFlags: vk::VK_SHADER_STAGE_VERTEX_BIT,
-            pImmutableSamplers: ptr::null(),
-        };
+    /// Adds an array of `count` descriptors of `descriptor_type` at
+    /// `binding`, accessible from the shader stages in `stage_flags`.
+    pub fn binding(mut self, binding: u32, descriptor_type: vk::VkDescriptorType, count: u32,
+            stage_flags: vk::VkShaderStageFlags) -> DescriptorSetLayoutBuilder
+    {
+        self.bindings.push(DescriptorBinding {
+            binding,
+            descriptor_type,
+            count,
+            stage_flags,
+            immutable_samplers: Vec::new(),
+        });
+        self
+    }
 
-        let sampler_layout_binding = vk::VkDescriptorSetLayoutBinding {
-            binding: 1,
-            descriptorType: vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
-            descriptorCount: 1,
-            stageFlags: vk::VK_SHADER_STAGE_FRAGMENT_BIT,
-            pImmutableSamplers: ptr::null(),
-        };
+    /// Adds a sampler or combined image sampler binding whose samplers are
+    /// fixed to `samplers`, one per array element.
+    pub fn immutable_samplers(mut self, binding: u32, descriptor_type: vk::VkDescriptorType,
+            stage_flags: vk::VkShaderStageFlags, samplers: &[&Sampler])
+            -> DescriptorSetLayoutBuilder
+    {
+        self.bindings.push(DescriptorBinding {
+            binding,
+            descriptor_type,
+            count: samplers.len() as u32,
+            stage_flags,
+            immutable_samplers: samplers.iter().map(|&s| s.clone()).collect(),
+        });
+        self
+    }
+
+    pub fn build(self) -> VkcResult<DescriptorSetLayout> {
+        let DescriptorSetLayoutBuilder { device, mut bindings } = self;
+        bindings.sort_by_key(|b| b.binding);
+
+        for pair in bindings.windows(2) {
+            if pair[0].binding == pair[1].binding {
+                return Err(format!("Unable to create descriptor set layout: binding {} is \
+                    specified more than once.", pair[0].binding).into());
+            }
+        }
+        for b in &bindings {
+            let is_sampler = b.descriptor_type == vk::VK_DESCRIPTOR_TYPE_SAMPLER ||
+                b.descriptor_type == vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER;
+            if !b.immutable_samplers.is_empty() && !is_sampler {
+                return Err(format!("Unable to create descriptor set layout: binding {} has \
+                    immutable samplers but is not a sampler binding.", b.binding).into());
+            }
+        }
+
+        let sampler_handles: Vec<Vec<vk::VkSampler>> = bindings.iter()
+            .map(|b| b.immutable_samplers.iter().map(|s| s.handle()).collect())
+            .collect();
 
-        let bindings = [ubo_layout_binding, sampler_layout_binding];
+        let raw_bindings: Vec<_> = bindings.iter().zip(sampler_handles.iter())
+            .map(|(b, samplers)| vk::VkDescriptorSetLayoutBinding {
+                binding: b.binding,
+                descriptorType: b.descriptor_type,
+                descriptorCount: b.count,
+                stageFlags: b.stage_flags,
+                pImmutableSamplers: if samplers.is_empty() {
+                    ptr::null()
+                } else {
+                    samplers.as_ptr()
+                },
+            })
+            .collect();
 
         let create_info = vk::VkDescriptorSetLayoutCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            bindingCount: bindings.len() as u32,
-            pBindings: bindings.as_ptr(),
+            bindingCount: raw_bindings.len() as u32,
+            pBindings: raw_bindings.as_ptr(),
         };
 
         let mut handle = 0;
         unsafe {
             ::check(device.vk().vkCreateDescriptorSetLayout(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(DescriptorSetLayout {
             inner: Arc::new(Inner {
                 handle,
+                bindings,
                 device,
             })
         })
     }
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkDescriptorSetLayout,
+    bindings: Vec<DescriptorBinding>,
+    device: Device,
+}
+
+#[derive(Debug, Clone)]
+pub struct DescriptorSetLayout {
+    inner: Arc<Inner>,
+}
+
+impl DescriptorSetLayout {
+    /// Returns the bindings this layout was created with, sorted by binding
+    /// number.
+    pub fn bindings(&self) -> &[DescriptorBinding] {
+        &self.inner.bindings
+    }
+
+    /// Returns the binding numbered `binding`, if any.
+    pub fn binding(&self, binding: u32) -> Option<&DescriptorBinding> {
+        self.inner.bindings.iter().find(|b| b.binding == binding)
+    }
 
     pub fn handle(&self) -> vk::VkDescriptorSetLayout {
         self.inner.handle
@@ -72,4 +184,4 @@ impl Drop for Inner {
             self.device.vk().vkDestroyDescriptorSetLayout(self.device.handle(), self.handle, ptr::null());
         }
     }
-}
\ No newline at end of file
+}
@@ -1,288 +1,512 @@
-use std::sync::Arc;
-use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::ptr;
-use std::ffi::CStr;
-use libc::c_char;
+use std::ffi::CString;
 use vk;
 use vks;
-use ::{VkcResult, Instance, Surface, SwapchainSupportDetails};
-use queue::{self, Queue};
+use ::{VkcResult, Instance, Surface, MemoryAllocator, PhysicalDevice, PhysicalDeviceSelector};
+use memory_allocator;
+use queue::{self, Queue, QueueRole};
 use instance;
 
 
-static REQUIRED_EXTENSIONS: [&[u8]; 1] = [
-    b"VK_KHR_swapchain\0",
+/// Extensions required to present to a surface.
+static SURFACE_EXTENSIONS: [&str; 1] = [
+    "VK_KHR_swapchain",
 ];
 
-// bool checkDeviceExtensionSupport(VkPhysicalDevice device) {
-//     uint32_t extensionCount;
-//     vkEnumerateDeviceExtensionProperties(device, nullptr, &extensionCount, nullptr);
-
-//     std::vector<VkExtensionProperties> availableExtensions(extensionCount);
-//     vkEnumerateDeviceExtensionProperties(device, nullptr, &extensionCount, availableExtensions.data());
-
-//     std::set<std::string> requiredExtensions(deviceExtensions.begin(), deviceExtensions.end());
-
-//     for (const auto& extension : availableExtensions) {
-//         requiredExtensions.erase(extension.extensionName);
-//     }
-
-//     return requiredExtensions.empty();
-// }
-
-fn check_device_extension_support(instance: &Instance, device: vk::VkPhysicalDevice) -> bool {
-    let mut avail_ext_count = 0u32;
-    let mut avail_exts: Vec<vk::VkExtensionProperties>;
-    unsafe {
-        ::check(instance.vk().core.vkEnumerateDeviceExtensionProperties(device, ptr::null(),
-            &mut avail_ext_count, ptr::null_mut()));
-        avail_exts = Vec::with_capacity(avail_ext_count as usize);
-        avail_exts.set_len(avail_ext_count as usize);
-        ::check(instance.vk().core.vkEnumerateDeviceExtensionProperties(device, ptr::null(),
-            &mut avail_ext_count, avail_exts.as_mut_ptr()));
-
-        // Print available:
-        for ext in &avail_exts {
-                let name = (&ext.extensionName) as *const c_char;
-                println!("Available device extension: '{}' (version: {})",
-                    CStr::from_ptr(name).to_str().unwrap(), ext.specVersion);
-        };
+/// Returns the highest scoring physical device (see
+/// `physical_device::default_score`) which supports `queue_flags` and, if a
+/// surface is specified, is able to present to it.
+///
+/// Use `PhysicalDeviceSelector` directly for additional requirements.
+pub fn choose_physical_device(instance: &Instance, surface: Option<&Surface>, queue_flags: vk::VkQueueFlags)
+        -> VkcResult<PhysicalDevice>
+{
+    let mut selector = PhysicalDeviceSelector::new(queue_flags);
+    if let Some(surface) = surface {
+        selector = selector.surface(surface);
+    }
+    let preferred_device = selector.select(instance)?;
+    info!("Preferred device: {:?}", preferred_device);
+    Ok(preferred_device)
+}
+
+/// Defines functions operating on each field of `VkPhysicalDeviceFeatures`.
+macro_rules! device_features {
+    ($($feature:ident,)+) => {
+        /// Returns a feature set with every feature disabled.
+        pub fn device_features_none() -> vk::VkPhysicalDeviceFeatures {
+            vk::VkPhysicalDeviceFeatures {
+                $($feature: vk::VK_FALSE,)+
+            }
+        }
 
-        for reqd_ext_name in &REQUIRED_EXTENSIONS[..] {
-            let mut ext_avail = false;
-            for avail_ext in &avail_exts {
-                if CStr::from_ptr(reqd_ext_name.as_ptr() as *const c_char) ==
-                    CStr::from_ptr(avail_ext.extensionName.as_ptr())
-                {
-                    println!("Required device extension available: '{}'",
-                        CStr::from_ptr(reqd_ext_name.as_ptr() as *const c_char).to_str().unwrap());
-                    ext_avail = true;
-                    break;
+        /// Returns the names of the features enabled in `requested` but not
+        /// in `available`.
+        pub fn unsupported_features(requested: &vk::VkPhysicalDeviceFeatures,
+                available: &vk::VkPhysicalDeviceFeatures) -> Vec<&'static str>
+        {
+            let mut unsupported = Vec::new();
+            $(
+                if requested.$feature != vk::VK_FALSE && available.$feature == vk::VK_FALSE {
+                    unsupported.push(stringify!($feature));
                 }
+            )+
+            unsupported
+        }
+
+        /// Returns the features enabled in both `a` and `b`.
+        pub fn features_intersection(a: &vk::VkPhysicalDeviceFeatures,
+                b: &vk::VkPhysicalDeviceFeatures) -> vk::VkPhysicalDeviceFeatures
+        {
+            vk::VkPhysicalDeviceFeatures {
+                $($feature: if a.$feature != vk::VK_FALSE && b.$feature != vk::VK_FALSE {
+                    vk::VK_TRUE } else { vk::VK_FALSE },)+
             }
-            if !ext_avail { return false; }
         }
-    }
-    true
-}
 
-unsafe fn device_is_suitable(instance: &Instance, surface: &Surface, device: vk::VkPhysicalDevice,
-        queue_flags: vk::VkQueueFlags) -> bool
-{
-    let mut device_properties: vk::VkPhysicalDeviceProperties = mem::uninitialized();
-    let mut device_features: vk::VkPhysicalDeviceFeatures = mem::uninitialized();
-    instance.vk().core.vkGetPhysicalDeviceProperties(device, &mut device_properties);
-    instance.vk().core.vkGetPhysicalDeviceFeatures(device, &mut device_features);
-
-    let extensions_supported = check_device_extension_support(instance, device);
-
-    let mut swap_chain_adequate = false;
-    if extensions_supported {
-        let swap_chain_details = SwapchainSupportDetails::new(instance, surface, device);
-        swap_chain_adequate = !swap_chain_details.formats.is_empty() &&
-            !swap_chain_details.present_modes.is_empty()
-    }
+        /// Returns the features enabled in either `a` or `b`.
+        pub fn features_union(a: &vk::VkPhysicalDeviceFeatures,
+                b: &vk::VkPhysicalDeviceFeatures) -> vk::VkPhysicalDeviceFeatures
+        {
+            vk::VkPhysicalDeviceFeatures {
+                $($feature: if a.$feature != vk::VK_FALSE || b.$feature != vk::VK_FALSE {
+                    vk::VK_TRUE } else { vk::VK_FALSE },)+
+            }
+        }
+    };
+}
 
-    queue::queue_families(instance, surface, device, queue_flags).is_complete() &&
-        extensions_supported &&
-        swap_chain_adequate &&
-        device_features.samplerAnisotropy != 0
+device_features! {
+    robustBufferAccess,
+    fullDrawIndexUint32,
+    imageCubeArray,
+    independentBlend,
+    geometryShader,
+    tessellationShader,
+    sampleRateShading,
+    dualSrcBlend,
+    logicOp,
+    multiDrawIndirect,
+    drawIndirectFirstInstance,
+    depthClamp,
+    depthBiasClamp,
+    fillModeNonSolid,
+    depthBounds,
+    wideLines,
+    largePoints,
+    alphaToOne,
+    multiViewport,
+    samplerAnisotropy,
+    textureCompressionETC2,
+    textureCompressionASTC_LDR,
+    textureCompressionBC,
+    occlusionQueryPrecise,
+    pipelineStatisticsQuery,
+    vertexPipelineStoresAndAtomics,
+    fragmentStoresAndAtomics,
+    shaderTessellationAndGeometryPointSize,
+    shaderImageGatherExtended,
+    shaderStorageImageExtendedFormats,
+    shaderStorageImageMultisample,
+    shaderStorageImageReadWithoutFormat,
+    shaderStorageImageWriteWithoutFormat,
+    shaderUniformBufferArrayDynamicIndexing,
+    shaderSampledImageArrayDynamicIndexing,
+    shaderStorageBufferArrayDynamicIndexing,
+    shaderStorageImageArrayDynamicIndexing,
+    shaderClipDistance,
+    shaderCullDistance,
+    shaderFloat64,
+    shaderInt64,
+    shaderInt16,
+    shaderResourceResidency,
+    shaderResourceMinLod,
+    sparseBinding,
+    sparseResidencyBuffer,
+    sparseResidencyImage2D,
+    sparseResidencyImage3D,
+    sparseResidency2Samples,
+    sparseResidency4Samples,
+    sparseResidency8Samples,
+    sparseResidency16Samples,
+    sparseResidencyAliased,
+    variableMultisampleRate,
+    inheritedQueries,
 }
 
-pub fn choose_physical_device(instance: &Instance, surface: &Surface, queue_flags: vk::VkQueueFlags)
-        -> VkcResult<vk::VkPhysicalDevice>
+/// Loads the function pointers of each extension in `extensions` which
+/// defines device-level commands.
+unsafe fn load_extensions(loader: &mut vks::DeviceProcAddrLoader, device: vk::VkDevice,
+        extensions: &[CString])
 {
-    let mut preferred_device = ptr::null_mut();
-
-    for &device in instance.physical_devices() {
-        if unsafe { device_is_suitable(instance, surface, device, queue_flags) } {
-            preferred_device = device;
-            break;
+    for ext in extensions {
+        match ext.to_bytes() {
+            b"VK_KHR_swapchain" => loader.load_khr_swapchain(device),
+            b"VK_KHR_display_swapchain" => loader.load_khr_display_swapchain(device),
+            b"VK_KHR_maintenance1" => loader.load_khr_maintenance1(device),
+            b"VK_EXT_debug_marker" => loader.load_ext_debug_marker(device),
+            b"VK_AMD_draw_indirect_count" => loader.load_amd_draw_indirect_count(device),
+            b"VK_NV_external_memory_win32" => loader.load_nv_external_memory_win32(device),
+            _ => (),
         }
     }
+}
 
-    if preferred_device.is_null() {
-        panic!("Failed to find a suitable device.");
-    } else {
-        println!("Preferred device: {:?}", preferred_device);
-    }
+fn to_cstring(name: &str) -> CString {
+    CString::new(name).expect("Vulkan extension names must not contain nul bytes")
+}
 
-    Ok(preferred_device)
+
+/// A request for `priorities.len()` queues supporting `flags`.
+#[derive(Debug, Clone)]
+struct QueueRequest {
+    role: QueueRole,
+    flags: vk::VkQueueFlags,
+    priorities: Vec<f32>,
 }
 
-fn device_features_none() -> vk::VkPhysicalDeviceFeatures {
-    vk::VkPhysicalDeviceFeatures {
-        robustBufferAccess: vk::VK_FALSE,
-        fullDrawIndexUint32: vk::VK_FALSE,
-        imageCubeArray: vk::VK_FALSE,
-        independentBlend: vk::VK_FALSE,
-        geometryShader: vk::VK_FALSE,
-        tessellationShader: vk::VK_FALSE,
-        sampleRateShading: vk::VK_FALSE,
-        dualSrcBlend: vk::VK_FALSE,
-        logicOp: vk::VK_FALSE,
-        multiDrawIndirect: vk::VK_FALSE,
-        drawIndirectFirstInstance: vk::VK_FALSE,
-        depthClamp: vk::VK_FALSE,
-        depthBiasClamp: vk::VK_FALSE,
-        fillModeNonSolid: vk::VK_FALSE,
-        depthBounds: vk::VK_FALSE,
-        wideLines: vk::VK_FALSE,
-        largePoints: vk::VK_FALSE,
-        alphaToOne: vk::VK_FALSE,
-        multiViewport: vk::VK_FALSE,
-        // samplerAnisotropy: vk::VK_FALSE,
-        samplerAnisotropy: vk::VK_TRUE,
-        textureCompressionETC2: vk::VK_FALSE,
-        textureCompressionASTC_LDR: vk::VK_FALSE,
-        textureCompressionBC: vk::VK_FALSE,
-        occlusionQueryPrecise: vk::VK_FALSE,
-        pipelineStatisticsQuery: vk::VK_FALSE,
-        vertexPipelineStoresAndAtomics: vk::VK_FALSE,
-        fragmentStoresAndAtomics: vk::VK_FALSE,
-        shaderTessellationAndGeometryPointSize: vk::VK_FALSE,
-        shaderImageGatherExtended: vk::VK_FALSE,
-        shaderStorageImageExtendedFormats: vk::VK_FALSE,
-        shaderStorageImageMultisample: vk::VK_FALSE,
-        shaderStorageImageReadWithoutFormat: vk::VK_FALSE,
-        shaderStorageImageWriteWithoutFormat: vk::VK_FALSE,
-        shaderUniformBufferArrayDynamicIndexing: vk::VK_FALSE,
-        shaderSampledImageArrayDynamicIndexing: vk::VK_FALSE,
-        shaderStorageBufferArrayDynamicIndexing: vk::VK_FALSE,
-        shaderStorageImageArrayDynamicIndexing: vk::VK_FALSE,
-        shaderClipDistance: vk::VK_FALSE,
-        shaderCullDistance: vk::VK_FALSE,
-        shaderFloat64: vk::VK_FALSE,
-        shaderInt64: vk::VK_FALSE,
-        shaderInt16: vk::VK_FALSE,
-        shaderResourceResidency: vk::VK_FALSE,
-        shaderResourceMinLod: vk::VK_FALSE,
-        sparseBinding: vk::VK_FALSE,
-        sparseResidencyBuffer: vk::VK_FALSE,
-        sparseResidencyImage2D: vk::VK_FALSE,
-        sparseResidencyImage3D: vk::VK_FALSE,
-        sparseResidency2Samples: vk::VK_FALSE,
-        sparseResidency4Samples: vk::VK_FALSE,
-        sparseResidency8Samples: vk::VK_FALSE,
-        sparseResidency16Samples: vk::VK_FALSE,
-        sparseResidencyAliased: vk::VK_FALSE,
-        variableMultisampleRate: vk::VK_FALSE,
-        inheritedQueries: vk::VK_FALSE,
-    }
+/// The family and index of a queue created for a particular role.
+#[derive(Debug, Clone, Copy)]
+struct QueueLocation {
+    role: QueueRole,
+    family_idx: u32,
+    idx: u32,
 }
 
 
+/// Configures and creates a `Device`.
+///
+/// Queues are requested by role. Requests for `QueueRole::Primary` use the
+/// first family supporting the requested flags while other roles use the
+/// most dedicated family available (see `queue::dedicated_queue_family`).
+/// Requests landing in the same family share its queues; any queues beyond
+/// the family's `queueCount` alias those already created. Submissions to an
+/// aliased queue are serialized and `Device::queue_is_shared` reports which
+/// roles share a queue with another request.
 #[derive(Debug)]
-struct Inner {
-    handle: vk::VkDevice,
-    physical_device: vk::VkPhysicalDevice,
-    // features: vk::VkPhysicalDeviceFeatures,
-    // queues: SmallVec<[u32; 32]>,
-    queue_family_idx: u32,
-    // vk: vk::VkDevicePointers,
+pub struct DeviceBuilder<'s> {
     instance: Instance,
-    loader: vks::DeviceProcAddrLoader,
+    surface: Option<&'s Surface>,
+    physical_device: PhysicalDevice,
+    queue_requests: Vec<QueueRequest>,
+    extensions: Vec<CString>,
+    optional_extensions: Vec<CString>,
+    features: vk::VkPhysicalDeviceFeatures,
+    optional_features: vk::VkPhysicalDeviceFeatures,
 }
 
-#[derive(Debug, Clone)]
-pub struct Device {
-    inner: Arc<Inner>,
-}
+impl<'s> DeviceBuilder<'s> {
+    pub fn new(instance: Instance, physical_device: PhysicalDevice) -> DeviceBuilder<'s> {
+        DeviceBuilder {
+            instance,
+            surface: None,
+            physical_device,
+            queue_requests: Vec::new(),
+            extensions: Vec::new(),
+            optional_extensions: Vec::new(),
+            features: device_features_none(),
+            optional_features: device_features_none(),
+        }
+    }
 
-impl Device {
-    pub fn new(instance: Instance, surface: &Surface, physical_device: vk::VkPhysicalDevice,
-            queue_familiy_flags: vk::VkQueueFlags) -> VkcResult<Device>
+    /// Requires the swapchain extension for presenting to `surface`.
+    pub fn surface(mut self, surface: &'s Surface) -> DeviceBuilder<'s> {
+        self.surface = Some(surface);
+        self
+    }
+
+    /// Requires the device extension named `name`.
+    pub fn extension(mut self, name: &str) -> DeviceBuilder<'s> {
+        self.extensions.push(to_cstring(name));
+        self
+    }
+
+    /// Enables the device extension named `name` if supported.
+    pub fn optional_extension(mut self, name: &str) -> DeviceBuilder<'s> {
+        self.optional_extensions.push(to_cstring(name));
+        self
+    }
+
+    /// Requires each feature enabled in `features`.
+    pub fn features(mut self, features: vk::VkPhysicalDeviceFeatures) -> DeviceBuilder<'s> {
+        self.features = features_union(&self.features, &features);
+        self
+    }
+
+    /// Enables each feature enabled in `features` if supported.
+    pub fn optional_features(mut self, features: vk::VkPhysicalDeviceFeatures) -> DeviceBuilder<'s> {
+        self.optional_features = features_union(&self.optional_features, &features);
+        self
+    }
+
+    /// Requests one queue supporting `flags` for `role` per entry of
+    /// `priorities` (each within [0.0, 1.0]).
+    pub fn queues(mut self, role: QueueRole, flags: vk::VkQueueFlags, priorities: &[f32])
+            -> DeviceBuilder<'s>
     {
-        let queue_family_idx = queue::queue_families(&instance, surface,
-            physical_device, queue_familiy_flags).family_idxs()[0] as u32;
+        self.queue_requests.push(QueueRequest { role, flags, priorities: priorities.to_vec() });
+        self
+    }
 
-        let queue_create_info = vk::VkDeviceQueueCreateInfo {
-            sType: vk::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
-            pNext: ptr::null(),
-            flags: 0,
-            queueFamilyIndex: queue_family_idx,
-            queueCount: 1,
-            pQueuePriorities: &1.0,
-        };
+    /// Creates the device.
+    pub fn build(self) -> VkcResult<Device> {
+        let DeviceBuilder { instance, surface, physical_device, queue_requests, mut extensions,
+            optional_extensions, features, optional_features } = self;
 
-        let features = device_features_none();
+        // Extensions and features:
+        if surface.is_some() {
+            extensions.extend(SURFACE_EXTENSIONS.iter().map(|&ext| to_cstring(ext)));
+        }
+        let missing_extensions: Vec<_> = extensions.iter()
+            .filter(|ext| !physical_device.supports_extension(&ext.to_string_lossy()))
+            .collect();
+        let missing_features = unsupported_features(&features, physical_device.features());
+
+        if !missing_extensions.is_empty() || !missing_features.is_empty() {
+            return Err(format!("Unable to create device on '{}'. Missing required extensions: \
+                {:?}; missing required features: {:?}.", physical_device.name(), missing_extensions,
+                missing_features).into());
+        }
 
-        // createInfo.enabledExtensionCount = static_cast<uint32_t>(deviceExtensions.size());
-        // createInfo.ppEnabledExtensionNames = deviceExtensions.data();
+        // Extensions may be requested more than once, including as both
+        // required and optional, but must only be enabled once:
+        let mut enabled_extensions: Vec<CString> = Vec::new();
+        let supported_optional_extensions = optional_extensions.into_iter()
+            .filter(|ext| physical_device.supports_extension(&ext.to_string_lossy()));
+        for ext in extensions.into_iter().chain(supported_optional_extensions) {
+            if !enabled_extensions.contains(&ext) {
+                enabled_extensions.push(ext);
+            }
+        }
+        let enabled_features = features_union(&features,
+            &features_intersection(&optional_features, physical_device.features()));
 
-        let enabled_layer_names = instance::enabled_layer_names(instance.loader(), false);
+        for ext in &enabled_extensions {
+            debug!("Enabling device extension: '{}'", ext.to_string_lossy());
+        }
 
-        let enabled_extension_names: Vec<_> = (&REQUIRED_EXTENSIONS[..]).iter().map(|ext_name|
-            ext_name.as_ptr() as *const c_char).collect();
+        // Queues:
+        let queue_family_props = physical_device.queue_families().to_vec();
+        // (family index, priorities, number of queues requested)
+        let mut family_priorities: Vec<(u32, Vec<f32>, u32)> = Vec::new();
+        let mut queue_locations = Vec::new();
+        let mut primary_family_idx = None;
+
+        for request in &queue_requests {
+            if request.priorities.is_empty() {
+                return Err(format!("DeviceBuilder::build: No priorities were given for {:?} \
+                    queues ({:#x}).", request.role, request.flags).into());
+            }
+            if let Some(priority) = request.priorities.iter().find(|p| !(0.0..=1.0).contains(*p)) {
+                return Err(format!("DeviceBuilder::build: Queue priority {} for {:?} queues is \
+                    not within [0.0, 1.0].", priority, request.role).into());
+            }
+
+            let family_idx = match request.role {
+                QueueRole::Primary => queue::queue_families(&instance, surface, physical_device.handle(),
+                    request.flags)?.first_family_idx()?,
+                _ => queue::dedicated_queue_family(&queue_family_props, request.flags)
+                    .ok_or_else(|| format!("No queue family supports the flags requested for \
+                        {:?} queues ({:#x}).", request.role, request.flags))?,
+            };
+            if request.role == QueueRole::Primary && primary_family_idx.is_none() {
+                primary_family_idx = Some(family_idx);
+            }
+
+            let family_queue_count = queue_family_props[family_idx as usize].queueCount;
+            let family_pos = family_priorities.iter().position(|&(idx, _, _)| idx == family_idx);
+            let (priorities, requested) = match family_pos {
+                Some(pos) => {
+                    let family = &mut family_priorities[pos];
+                    (&mut family.1, &mut family.2)
+                },
+                None => {
+                    family_priorities.push((family_idx, Vec::new(), 0));
+                    let family = family_priorities.last_mut().unwrap();
+                    (&mut family.1, &mut family.2)
+                },
+            };
+
+            // Requests beyond the number of queues in the family share
+            // them in turn.
+            for &priority in &request.priorities {
+                let idx = *requested % family_queue_count;
+                if *requested < family_queue_count {
+                    priorities.push(priority);
+                }
+                *requested += 1;
+                queue_locations.push(QueueLocation { role: request.role, family_idx, idx });
+            }
+        }
+
+        let queue_family_idx = primary_family_idx
+            .ok_or("At least one primary queue must be requested.")?;
+
+        let queue_create_infos: Vec<_> = family_priorities.iter()
+                .map(|&(family_idx, ref priorities, _)| {
+            vk::VkDeviceQueueCreateInfo {
+                sType: vk::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                pNext: ptr::null(),
+                flags: 0,
+                queueFamilyIndex: family_idx,
+                queueCount: priorities.len() as u32,
+                pQueuePriorities: priorities.as_ptr(),
+            }
+        }).collect();
+
+        let enabled_layer_names = instance.enabled_layer_names();
+        let enabled_extension_names: Vec<_> = enabled_extensions.iter().map(|ext| ext.as_ptr()).collect();
 
         let create_info = vk::VkDeviceCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            queueCreateInfoCount: 1,
-            pQueueCreateInfos: &queue_create_info,
+            queueCreateInfoCount: queue_create_infos.len() as u32,
+            pQueueCreateInfos: queue_create_infos.as_ptr(),
             enabledLayerCount: enabled_layer_names.len() as u32,
             ppEnabledLayerNames: enabled_layer_names.as_ptr(),
             enabledExtensionCount: enabled_extension_names.len() as u32,
             ppEnabledExtensionNames: enabled_extension_names.as_ptr(),
-            pEnabledFeatures: &features,
+            pEnabledFeatures: &enabled_features,
         };
 
         // Device:
         let mut handle = ptr::null_mut();
         unsafe {
-            ::check(instance.vk().core.vkCreateDevice(physical_device, &create_info, ptr::null(), &mut handle));
+            ::check(instance.vk().core.vkCreateDevice(physical_device.handle(), &create_info, ptr::null(), &mut handle))?;
         }
 
         let mut loader = vks::DeviceProcAddrLoader::from_get_device_proc_addr(instance.vk().core.pfn_vkGetDeviceProcAddr);
 
         unsafe {
             loader.load_core(handle);
-            // create_info.enabled_extensions.load_device(&mut loader, handle);
-            // instance.loader().get_enabled_extensions().load_device(&mut loader, handle);
-            // loader.load_khr_sampler_mirror_clamp_to_edge(handle);
-            // loader.load_khr_draw_parameters(handle);
-            loader.load_khr_swapchain(handle);
-            // loader.load_khr_maintenance1(handle);
-            // loader.load_amd_rasterization_order(handle);
-            // loader.load_amd_draw_indirect_count(handle);
-            // loader.load_amd_shader_ballot(handle);
-            // loader.load_amd_shader_trinary_minmax(handle);
-            // loader.load_amd_shader_explicit_vertex_parameter(handle);
-            // loader.load_amd_gcn_shader(handle);
-            // loader.load_amd_draw_indirect_count(handle);
-            // loader.load_amd_negative_viewport_height(handle);
-            // loader.load_amd_shader_info(handle);
-            // loader.load_amd_wave_limits(handle);
-            // loader.load_amd_texture_gather_bias_lod(handle);
-            // loader.load_amd_programmable_sample_locations(handle);
-            // loader.load_amd_mixed_attachment_samples(handle);
-            // loader.load_ext_shader_subgroup_vote(handle);
-            // loader.load_amd_gpa_interface(handle);
-            // loader.load_ext_shader_subgroup_ballot(handle);
+            load_extensions(&mut loader, handle, &enabled_extensions);
         }
 
+        let memory_allocator = MemoryAllocator::new(physical_device.memory_properties().clone(),
+            physical_device.limits().bufferImageGranularity,
+            physical_device.limits().nonCoherentAtomSize);
+
+        // One lock per distinct queue, since aliased queues share a handle:
+        let mut queue_locks: Vec<(u32, u32, Mutex<()>)> = Vec::new();
+        for loc in &queue_locations {
+            if !queue_locks.iter().any(|&(family_idx, idx, _)|
+                    family_idx == loc.family_idx && idx == loc.idx)
+            {
+                queue_locks.push((loc.family_idx, loc.idx, Mutex::new(())));
+            }
+        }
 
         Ok(Device {
             inner: Arc::new(Inner {
                 handle,
                 physical_device,
-                // features,
+                enabled_extensions,
+                enabled_features,
                 queue_family_idx,
+                queues: queue_locations,
+                queue_locks,
                 instance,
                 loader,
+                memory_allocator,
             }),
         })
     }
+}
 
-    #[inline]
-    pub fn queue(&self, queue_idx: u32) -> vk::VkQueue {
-        let mut queue_handle = ptr::null_mut();
-        unsafe {
-            self.vk().core.vkGetDeviceQueue(self.inner.handle, self.inner.queue_family_idx, queue_idx,
-                &mut queue_handle);
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkDevice,
+    physical_device: PhysicalDevice,
+    enabled_extensions: Vec<CString>,
+    enabled_features: vk::VkPhysicalDeviceFeatures,
+    queue_family_idx: u32,
+    queues: Vec<QueueLocation>,
+    // (family index, queue index, lock held while using the queue)
+    queue_locks: Vec<(u32, u32, Mutex<()>)>,
+    // vk: vk::VkDevicePointers,
+    instance: Instance,
+    loader: vks::DeviceProcAddrLoader,
+    memory_allocator: MemoryAllocator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    inner: Arc<Inner>,
+}
+
+impl Device {
+    /// Creates a new device with a single primary queue from the first
+    /// family supporting `queue_familiy_flags`.
+    ///
+    /// If `surface` is `None`, the device is created headless: the swapchain
+    /// extension is neither enabled nor loaded. Use `DeviceBuilder` to
+    /// request additional queues.
+    pub fn new(instance: Instance, surface: Option<&Surface>, physical_device: PhysicalDevice,
+            queue_familiy_flags: vk::VkQueueFlags) -> VkcResult<Device>
+    {
+        let mut optional_features = device_features_none();
+        optional_features.samplerAnisotropy = vk::VK_TRUE;
+
+        let mut builder = DeviceBuilder::new(instance, physical_device)
+            .queues(QueueRole::Primary, queue_familiy_flags, &[1.0])
+            .optional_features(optional_features);
+        if let Some(surface) = surface {
+            builder = builder.surface(surface);
+        }
+        builder.build()
+    }
+
+    /// Returns the queue at `queue_idx` within the primary queue family.
+    pub fn queue(&self, queue_idx: u32) -> VkcResult<Queue> {
+        let family_idx = self.inner.queue_family_idx;
+        let created = self.inner.queues.iter()
+            .any(|loc| loc.family_idx == family_idx && loc.idx == queue_idx);
+        if !created {
+            return Err(format!("Device::queue: No queue was created at index {} of the primary \
+                queue family.", queue_idx).into());
         }
-        queue_handle
+        Ok(unsafe { Queue::new(self.clone(), family_idx, queue_idx) })
+    }
+
+    /// Returns all queues created for `role`, in the order requested.
+    pub fn queues(&self, role: QueueRole) -> Vec<Queue> {
+        self.inner.queues.iter().filter(|loc| loc.role == role)
+            .map(|loc| unsafe { Queue::new(self.clone(), loc.family_idx, loc.idx) })
+            .collect()
+    }
+
+    /// Returns the first queue created for `role`, if any.
+    pub fn first_queue(&self, role: QueueRole) -> Option<Queue> {
+        self.inner.queues.iter().find(|loc| loc.role == role)
+            .map(|loc| unsafe { Queue::new(self.clone(), loc.family_idx, loc.idx) })
+    }
+
+    /// Returns true if any queue created for `role` aliases a queue created
+    /// for another request, because its family had too few queues.
+    pub fn queue_is_shared(&self, role: QueueRole) -> bool {
+        let queues = &self.inner.queues;
+        queues.iter().enumerate().filter(|&(_, loc)| loc.role == role).any(|(i, loc)| {
+            queues.iter().enumerate().any(|(j, other)| {
+                i != j && other.family_idx == loc.family_idx && other.idx == loc.idx
+            })
+        })
+    }
+
+    /// Locks the queue at `idx` within `family_idx` for external
+    /// synchronization, returning `None` if no such queue was created.
+    pub(crate) fn lock_queue(&self, family_idx: u32, idx: u32) -> Option<MutexGuard<()>> {
+        self.inner.queue_locks.iter()
+            .find(|&&(f, i, _)| f == family_idx && i == idx)
+            .map(|&(_, _, ref lock)| lock.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    /// Returns the index of the primary queue family.
+    #[inline]
+    pub fn queue_family_idx(&self) -> u32 {
+        self.inner.queue_family_idx
     }
 
     #[inline]
@@ -297,20 +521,46 @@ impl Device {
     }
 
     #[inline]
-    pub fn physical_device(&self) -> vk::VkPhysicalDevice {
-        self.inner.physical_device
+    pub fn physical_device(&self) -> &PhysicalDevice {
+        &self.inner.physical_device
     }
 
     #[inline]
     pub fn instance(&self) -> &Instance {
         &self.inner.instance
     }
+
+    /// Returns the extensions enabled on this device.
+    #[inline]
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.inner.enabled_extensions
+    }
+
+    /// Returns true if the extension named `name` is enabled.
+    pub fn is_extension_enabled(&self, name: &str) -> bool {
+        self.inner.enabled_extensions.iter().any(|ext| ext.to_bytes() == name.as_bytes())
+    }
+
+    /// Returns the features enabled on this device, including any optional
+    /// features which were supported.
+    #[inline]
+    pub fn enabled_features(&self) -> &vk::VkPhysicalDeviceFeatures {
+        &self.inner.enabled_features
+    }
+
+    /// Returns the allocator used for all buffer and image memory.
+    #[inline]
+    pub fn memory_allocator(&self) -> &MemoryAllocator {
+        &self.inner.memory_allocator
+    }
 }
 
 impl Drop for Inner {
     fn drop(&mut self) {
-        println!("Destroying device...");
+        trace!("Destroying device...");
         unsafe {
+            memory_allocator::destroy_memory_allocator(&self.memory_allocator, self.handle,
+                &self.loader);
             self.instance.vk().core.vkDestroyDevice(self.handle, ptr::null());
         }
     }
@@ -28,7 +28,7 @@ impl DeviceMemory {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkAllocateMemory(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(DeviceMemory {
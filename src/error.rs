@@ -8,6 +8,44 @@ pub enum ErrorKind {
     FromUtf8Error(::std::string::FromUtf8Error),
     UnspecifiedDimensions,
     IntoStringError(::std::ffi::IntoStringError),
+    VkResult { code: i32, name: &'static str },
+}
+
+
+/// Returns the symbolic name of a `VkResult` code.
+pub fn vk_result_name(code: i32) -> &'static str {
+    match code {
+        0 => "VK_SUCCESS",
+        1 => "VK_NOT_READY",
+        2 => "VK_TIMEOUT",
+        3 => "VK_EVENT_SET",
+        4 => "VK_EVENT_RESET",
+        5 => "VK_INCOMPLETE",
+        -1 => "VK_ERROR_OUT_OF_HOST_MEMORY",
+        -2 => "VK_ERROR_OUT_OF_DEVICE_MEMORY",
+        -3 => "VK_ERROR_INITIALIZATION_FAILED",
+        -4 => "VK_ERROR_DEVICE_LOST",
+        -5 => "VK_ERROR_MEMORY_MAP_FAILED",
+        -6 => "VK_ERROR_LAYER_NOT_PRESENT",
+        -7 => "VK_ERROR_EXTENSION_NOT_PRESENT",
+        -8 => "VK_ERROR_FEATURE_NOT_PRESENT",
+        -9 => "VK_ERROR_INCOMPATIBLE_DRIVER",
+        -10 => "VK_ERROR_TOO_MANY_OBJECTS",
+        -11 => "VK_ERROR_FORMAT_NOT_SUPPORTED",
+        -12 => "VK_ERROR_FRAGMENTED_POOL",
+        -1000000000 => "VK_ERROR_SURFACE_LOST_KHR",
+        -1000000001 => "VK_ERROR_NATIVE_WINDOW_IN_USE_KHR",
+        1000001003 => "VK_SUBOPTIMAL_KHR",
+        -1000001004 => "VK_ERROR_OUT_OF_DATE_KHR",
+        -1000003001 => "VK_ERROR_INCOMPATIBLE_DISPLAY_KHR",
+        -1000011001 => "VK_ERROR_VALIDATION_FAILED_EXT",
+        -1000012000 => "VK_ERROR_INVALID_SHADER_NV",
+        -1000069000 => "VK_ERROR_OUT_OF_POOL_MEMORY_KHR",
+        -1000072003 => "VK_ERROR_INVALID_EXTERNAL_HANDLE_KHR",
+        -1000161000 => "VK_ERROR_FRAGMENTATION_EXT",
+        -1000174001 => "VK_ERROR_NOT_PERMITTED_EXT",
+        _ => "VK_RESULT_UNKNOWN",
+    }
 }
 
 
@@ -31,6 +69,19 @@ impl self::Error {
         Error { kind: err.kind, cause: Some(Box::new(self)) }
     }
 
+    /// Returns an `Error` with the `VkResult` kind variant.
+    pub fn vk_result(code: i32) -> Error {
+        Error { kind: ErrorKind::VkResult { code, name: vk_result_name(code) }, cause: None }
+    }
+
+    /// Returns the `VkResult` code if this is a `VkResult` error.
+    pub fn vk_result_code(&self) -> Option<i32> {
+        match self.kind {
+            ErrorKind::VkResult { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
     /// Returns the error variant and contents.
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
@@ -57,6 +108,7 @@ impl self::Error {
                 ErrorKind::String(ref desc) => write!(f, "{}", desc),
                 ErrorKind::UnspecifiedDimensions => write!(f, "Cannot convert to a valid set of \
                     dimensions. Please specify some dimensions."),
+                ErrorKind::VkResult { code, name } => write!(f, "Vulkan error: {} ({})", name, code),
             }
         }
 
@@ -96,6 +148,7 @@ impl StdError for self::Error {
             ErrorKind::String(ref desc) => desc.as_str(),
             ErrorKind::UnspecifiedDimensions => "Cannot convert to a valid set of dimensions. \
                 Please specify some dimensions.",
+            ErrorKind::VkResult { name, .. } => name,
             // _ => panic!("OclErrorKind::description()"),
         }
     }
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+use std::ptr;
+use smallvec::SmallVec;
+use vk;
+use vks;
+use ::{util, VkcResult, Device};
+
+
+/// Waits on a set of fences, returning `false` if `timeout` (in nanoseconds)
+/// expires first.
+fn wait_for_fences(fences: &[&Fence], wait_all: bool, timeout: u64) -> VkcResult<bool> {
+    let device = match fences.first() {
+        Some(f) => f.device(),
+        None => return Ok(true),
+    };
+    let handles: SmallVec<[vk::VkFence; 16]> = fences.iter().map(|f| f.handle()).collect();
+
+    let res = unsafe {
+        ::check(device.vk().core.vkWaitForFences(device.handle(), handles.len() as u32,
+            handles.as_ptr(), if wait_all { vk::VK_TRUE } else { vk::VK_FALSE }, timeout))?
+    };
+    Ok(res != vk::VK_TIMEOUT)
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkFence,
+    device: Device,
+}
+
+/// A fence, used to wait on or poll for the completion of queue submissions
+/// from the host.
+#[derive(Debug, Clone)]
+pub struct Fence {
+    inner: Arc<Inner>,
+}
+
+impl Fence {
+    /// Creates a new fence, initially in the signaled state if `signaled` is
+    /// true.
+    pub fn new(device: Device, signaled: bool) -> VkcResult<Fence> {
+        let create_info = vk::VkFenceCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_FENCE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: if signaled { vk::VK_FENCE_CREATE_SIGNALED_BIT } else { 0 },
+        };
+
+        let mut handle = 0;
+        unsafe {
+            ::check(device.vk().core.vkCreateFence(device.handle(), &create_info,
+                ptr::null(), &mut handle))?;
+        }
+
+        Ok(Fence {
+            inner: Arc::new(Inner {
+                handle,
+                device,
+            })
+        })
+    }
+
+    /// Blocks until this fence is signaled or `timeout` nanoseconds have
+    /// passed. Returns `true` if the fence was signaled and `false` if the
+    /// timeout expired.
+    pub fn wait(&self, timeout: u64) -> VkcResult<bool> {
+        wait_for_fences(&[self], true, timeout)
+    }
+
+    /// Returns `true` if this fence is signaled, without blocking.
+    pub fn status(&self) -> VkcResult<bool> {
+        let res = unsafe {
+            ::check(self.device().vk().core.vkGetFenceStatus(self.device().handle(),
+                self.handle()))?
+        };
+        Ok(res == vk::VK_SUCCESS)
+    }
+
+    /// Resets this fence to the unsignaled state.
+    pub fn reset(&self) -> VkcResult<()> {
+        unsafe {
+            ::check(self.device().vk().core.vkResetFences(self.device().handle(), 1,
+                &self.inner.handle))?;
+        }
+        Ok(())
+    }
+
+    /// Blocks until all of `fences` are signaled or `timeout` nanoseconds
+    /// have passed. Returns `false` if the timeout expired.
+    ///
+    /// All fences must belong to the same device.
+    pub fn wait_all(fences: &[&Fence], timeout: u64) -> VkcResult<bool> {
+        wait_for_fences(fences, true, timeout)
+    }
+
+    /// Blocks until any of `fences` is signaled or `timeout` nanoseconds
+    /// have passed. Returns `false` if the timeout expired.
+    ///
+    /// All fences must belong to the same device.
+    pub fn wait_any(fences: &[&Fence], timeout: u64) -> VkcResult<bool> {
+        wait_for_fences(fences, false, timeout)
+    }
+
+    pub fn handle(&self) -> vk::VkFence {
+        self.inner.handle
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.inner.device
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.vk().core.vkDestroyFence(self.device.handle(), self.handle, ptr::null());
+        }
+    }
+}
@@ -46,7 +46,7 @@ impl Framebuffer {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreateFramebuffer(device.handle(), &create_info, ptr::null(),
-                &mut handle));
+                &mut handle))?;
         }
 
         Ok(Framebuffer {
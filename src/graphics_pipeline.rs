@@ -1,141 +1,575 @@
-
 use std::sync::Arc;
 use std::ptr;
-use std::ffi::CStr;
+use std::mem;
+use std::ffi::CString;
+use smallvec::SmallVec;
 use vk;
-use vks;
-use ::{util, VkcResult, Device, ShaderModule, PipelineLayout, RenderPass, Vertex};
-
+use spirv::ExecutionModel;
+use std_layout::BlockLayout;
+use ::{VkcResult, Device, ShaderModule, PipelineLayout, RenderPass, SpecializationInfo,
+    PipelineCache, VertexInput, AsStd140, AsStd430};
 
 
-#[derive(Debug)]
-struct Inner {
-    handle: vk::VkPipeline,
-    device: Device,
+/// A shader stage added to a `GraphicsPipelineBuilder`.
+#[derive(Debug, Clone)]
+struct Stage {
+    stage: vk::VkShaderStageFlagBits,
+    module: ShaderModule,
+    entry_point: CString,
+    specialization_info: Option<SpecializationInfo>,
 }
 
+
+/// Configures and creates a `GraphicsPipeline`.
+///
+/// Defaults to a triangle list with back-face culling of
+/// counter-clockwise-fronted polygons, no depth or stencil testing, one
+/// sample per pixel, and a single opaque color attachment.
 #[derive(Debug, Clone)]
-pub struct GraphicsPipeline {
-    inner: Arc<Inner>,
+pub struct GraphicsPipelineBuilder {
+    pipeline_layout: PipelineLayout,
+    render_pass: RenderPass,
+    subpass: u32,
+    stages: Vec<Stage>,
+    vertex_bindings: Vec<vk::VkVertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VkVertexInputAttributeDescription>,
+    topology: vk::VkPrimitiveTopology,
+    primitive_restart: bool,
+    patch_control_points: u32,
+    viewports: Vec<vk::VkViewport>,
+    scissors: Vec<vk::VkRect2D>,
+    depth_clamp: bool,
+    rasterizer_discard: bool,
+    polygon_mode: vk::VkPolygonMode,
+    cull_mode: vk::VkCullModeFlags,
+    front_face: vk::VkFrontFace,
+    // (constant factor, clamp, slope factor)
+    depth_bias: Option<(f32, f32, f32)>,
+    line_width: f32,
+    samples: vk::VkSampleCountFlagBits,
+    min_sample_shading: Option<f32>,
+    alpha_to_coverage: bool,
+    alpha_to_one: bool,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: vk::VkCompareOp,
+    depth_bounds: Option<(f32, f32)>,
+    // (front, back)
+    stencil: Option<(vk::VkStencilOpState, vk::VkStencilOpState)>,
+    // `None` until set, meaning a single opaque attachment.
+    blend_attachments: Option<Vec<vk::VkPipelineColorBlendAttachmentState>>,
+    logic_op: Option<vk::VkLogicOp>,
+    blend_constants: [f32; 4],
+    dynamic_states: Vec<vk::VkDynamicState>,
+    pipeline_cache: Option<PipelineCache>,
+    block_layouts: Vec<BlockLayout>,
 }
 
-impl GraphicsPipeline {
-    pub fn new(device: Device, pipeline_layout: &PipelineLayout,
-            render_pass: &RenderPass, swap_chain_extent: vk::VkExtent2D, vert_shader_code: &[u8],
-            frag_shader_code: &[u8]) -> VkcResult<GraphicsPipeline>
+impl GraphicsPipelineBuilder {
+    pub fn new(pipeline_layout: &PipelineLayout, render_pass: &RenderPass)
+            -> GraphicsPipelineBuilder
     {
-        let vert_shader_module = ShaderModule::new(device.clone(), vert_shader_code)?;
-        let frag_shader_module = ShaderModule::new(device.clone(), frag_shader_code)?;
+        GraphicsPipelineBuilder {
+            pipeline_layout: pipeline_layout.clone(),
+            render_pass: render_pass.clone(),
+            subpass: 0,
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
+            primitive_restart: false,
+            patch_control_points: 0,
+            viewports: Vec::new(),
+            scissors: Vec::new(),
+            depth_clamp: false,
+            rasterizer_discard: false,
+            polygon_mode: vk::VK_POLYGON_MODE_FILL,
+            cull_mode: vk::VK_CULL_MODE_BACK_BIT,
+            front_face: vk::VK_FRONT_FACE_COUNTER_CLOCKWISE,
+            depth_bias: None,
+            line_width: 1.0,
+            samples: vk::VK_SAMPLE_COUNT_1_BIT,
+            min_sample_shading: None,
+            alpha_to_coverage: false,
+            alpha_to_one: false,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::VK_COMPARE_OP_LESS,
+            depth_bounds: None,
+            stencil: None,
+            blend_attachments: None,
+            logic_op: None,
+            blend_constants: [0.0; 4],
+            dynamic_states: Vec::new(),
+            pipeline_cache: None,
+            block_layouts: Vec::new(),
+        }
+    }
 
-        let fn_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    /// Returns a color blend attachment state which writes all components
+    /// without blending.
+    pub fn opaque_attachment() -> vk::VkPipelineColorBlendAttachmentState {
+        vk::VkPipelineColorBlendAttachmentState {
+            blendEnable: vk::VK_FALSE,
+            srcColorBlendFactor: vk::VK_BLEND_FACTOR_ONE,
+            dstColorBlendFactor: vk::VK_BLEND_FACTOR_ZERO,
+            colorBlendOp: vk::VK_BLEND_OP_ADD,
+            srcAlphaBlendFactor: vk::VK_BLEND_FACTOR_ONE,
+            dstAlphaBlendFactor: vk::VK_BLEND_FACTOR_ZERO,
+            alphaBlendOp: vk::VK_BLEND_OP_ADD,
+            colorWriteMask: vk::VK_COLOR_COMPONENT_R_BIT | vk::VK_COLOR_COMPONENT_G_BIT |
+                vk::VK_COLOR_COMPONENT_B_BIT | vk::VK_COLOR_COMPONENT_A_BIT,
+        }
+    }
 
-        let vert_shader_stage_info = vk::VkPipelineShaderStageCreateInfo {
-            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
-            pNext: ptr::null(),
-            flags: 0,
-            stage: vk::VK_SHADER_STAGE_VERTEX_BIT,
-            module: vert_shader_module.handle(),
-            pName: fn_name.as_ptr(),
-            pSpecializationInfo: ptr::null(),
-        };
+    /// Returns a color blend attachment state which blends using source
+    /// alpha ('over' compositing).
+    pub fn alpha_blend_attachment() -> vk::VkPipelineColorBlendAttachmentState {
+        vk::VkPipelineColorBlendAttachmentState {
+            blendEnable: vk::VK_TRUE,
+            srcColorBlendFactor: vk::VK_BLEND_FACTOR_SRC_ALPHA,
+            dstColorBlendFactor: vk::VK_BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
+            .. GraphicsPipelineBuilder::opaque_attachment()
+        }
+    }
 
-        let frag_shader_stage_info = vk::VkPipelineShaderStageCreateInfo {
-            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
-            pNext: ptr::null(),
-            flags: 0,
-            stage: vk::VK_SHADER_STAGE_FRAGMENT_BIT,
-            module: frag_shader_module.handle(),
-            pName: fn_name.as_ptr(),
-            pSpecializationInfo: ptr::null(),
+    /// Adds the function named `entry_point` within `module` as a stage.
+    ///
+    /// The stage (vertex, fragment, geometry, etc.) is that of the entry
+    /// point as declared by the module.
+    pub fn stage(self, module: &ShaderModule, entry_point: &str)
+            -> VkcResult<GraphicsPipelineBuilder>
+    {
+        self.add_stage(module, entry_point, None)
+    }
+
+    /// Adds the function named `entry_point` within `module` as a stage,
+    /// specialized with `specialization_info`.
+    pub fn stage_specialized(self, module: &ShaderModule, entry_point: &str,
+            specialization_info: &SpecializationInfo) -> VkcResult<GraphicsPipelineBuilder>
+    {
+        self.add_stage(module, entry_point, Some(specialization_info.clone()))
+    }
+
+    fn add_stage(mut self, module: &ShaderModule, entry_point: &str,
+            specialization_info: Option<SpecializationInfo>) -> VkcResult<GraphicsPipelineBuilder>
+    {
+        let reflection = module.reflection().ok_or("GraphicsPipelineBuilder::stage: Shader \
+            module has no reflection data.")?;
+        let execution_model = reflection.entry_point(entry_point)
+            .ok_or_else(|| format!("GraphicsPipelineBuilder::stage: Shader module has no entry \
+                point named '{}'.", entry_point))?
+            .execution_model;
+        let stage = match execution_model {
+            ExecutionModel::Vertex | ExecutionModel::TessellationControl |
+                ExecutionModel::TessellationEvaluation | ExecutionModel::Geometry |
+                ExecutionModel::Fragment => execution_model.stage_flags(),
+            _ => return Err(format!("GraphicsPipelineBuilder::stage: Entry point '{}' is not a \
+                graphics stage ({:?}).", entry_point, execution_model).into()),
         };
+        if self.stages.iter().any(|s| s.stage == stage) {
+            return Err(format!("GraphicsPipelineBuilder::stage: A {:?} stage has already been \
+                added.", execution_model).into());
+        }
 
-        let shader_stages = [vert_shader_stage_info, frag_shader_stage_info];
+        self.stages.push(Stage {
+            stage,
+            module: module.clone(),
+            entry_point: CString::new(entry_point)?,
+            specialization_info,
+        });
+        Ok(self)
+    }
 
-        let binding_description = Vertex::binding_description();
-        let attribute_descriptions = Vertex::attribute_descriptions();
+    /// Adds a vertex buffer binding whose elements are of type `V`.
+    pub fn vertex_binding<V>(self, binding: u32, input_rate: vk::VkVertexInputRate)
+            -> GraphicsPipelineBuilder
+    {
+        self.vertex_binding_description(vk::VkVertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<V>() as u32,
+            inputRate: input_rate,
+        })
+    }
+
+    /// Adds a vertex buffer binding whose elements are of type `V`, along
+    /// with the attributes `V` describes.
+    pub fn vertex_input<V: VertexInput>(self, binding: u32, input_rate: vk::VkVertexInputRate)
+            -> GraphicsPipelineBuilder
+    {
+        self.vertex_binding_description(V::binding_description(binding, input_rate))
+            .vertex_attribute_descriptions(&V::attribute_descriptions(binding))
+    }
+
+    pub fn vertex_binding_description(mut self,
+            description: vk::VkVertexInputBindingDescription) -> GraphicsPipelineBuilder
+    {
+        self.vertex_bindings.push(description);
+        self
+    }
+
+    /// Adds a vertex attribute read from `offset` bytes into each element
+    /// of `binding`.
+    pub fn vertex_attribute(self, location: u32, binding: u32, format: vk::VkFormat,
+            offset: u32) -> GraphicsPipelineBuilder
+    {
+        self.vertex_attribute_descriptions(&[vk::VkVertexInputAttributeDescription {
+            location,
+            binding,
+            format,
+            offset,
+        }])
+    }
+
+    pub fn vertex_attribute_descriptions(mut self,
+            descriptions: &[vk::VkVertexInputAttributeDescription]) -> GraphicsPipelineBuilder
+    {
+        self.vertex_attributes.extend_from_slice(descriptions);
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::VkPrimitiveTopology) -> GraphicsPipelineBuilder {
+        self.topology = topology;
+        self
+    }
+
+    pub fn primitive_restart(mut self, enable: bool) -> GraphicsPipelineBuilder {
+        self.primitive_restart = enable;
+        self
+    }
+
+    /// Sets the number of control points per patch. Required when
+    /// tessellation stages are present.
+    pub fn patch_control_points(mut self, count: u32) -> GraphicsPipelineBuilder {
+        self.patch_control_points = count;
+        self
+    }
+
+    pub fn viewport(mut self, viewport: vk::VkViewport) -> GraphicsPipelineBuilder {
+        self.viewports.push(viewport);
+        self
+    }
+
+    pub fn scissor(mut self, scissor: vk::VkRect2D) -> GraphicsPipelineBuilder {
+        self.scissors.push(scissor);
+        self
+    }
+
+    /// Adds a viewport and scissor both covering `extent`.
+    pub fn viewport_extent(self, extent: vk::VkExtent2D) -> GraphicsPipelineBuilder {
+        self.viewport(vk::VkViewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            minDepth: 0.0,
+            maxDepth: 1.0,
+        }).scissor(vk::VkRect2D {
+            offset: vk::VkOffset2D { x: 0, y: 0 },
+            extent,
+        })
+    }
+
+    pub fn depth_clamp(mut self, enable: bool) -> GraphicsPipelineBuilder {
+        self.depth_clamp = enable;
+        self
+    }
+
+    pub fn rasterizer_discard(mut self, enable: bool) -> GraphicsPipelineBuilder {
+        self.rasterizer_discard = enable;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::VkPolygonMode) -> GraphicsPipelineBuilder {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::VkCullModeFlags) -> GraphicsPipelineBuilder {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::VkFrontFace) -> GraphicsPipelineBuilder {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth_bias(mut self, constant_factor: f32, clamp: f32, slope_factor: f32)
+            -> GraphicsPipelineBuilder
+    {
+        self.depth_bias = Some((constant_factor, clamp, slope_factor));
+        self
+    }
+
+    pub fn line_width(mut self, line_width: f32) -> GraphicsPipelineBuilder {
+        self.line_width = line_width;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::VkSampleCountFlagBits) -> GraphicsPipelineBuilder {
+        self.samples = samples;
+        self
+    }
+
+    /// Enables sample shading with at least `min_sample_shading` (within
+    /// [0.0, 1.0]) of samples shaded individually.
+    pub fn sample_shading(mut self, min_sample_shading: f32) -> GraphicsPipelineBuilder {
+        self.min_sample_shading = Some(min_sample_shading);
+        self
+    }
+
+    pub fn alpha_to_coverage(mut self, enable: bool) -> GraphicsPipelineBuilder {
+        self.alpha_to_coverage = enable;
+        self
+    }
+
+    pub fn alpha_to_one(mut self, enable: bool) -> GraphicsPipelineBuilder {
+        self.alpha_to_one = enable;
+        self
+    }
+
+    /// Enables depth testing with `compare_op`, writing passing fragments'
+    /// depth if `write` is true.
+    pub fn depth_test(mut self, compare_op: vk::VkCompareOp, write: bool)
+            -> GraphicsPipelineBuilder
+    {
+        self.depth_test = true;
+        self.depth_write = write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    pub fn depth_bounds(mut self, min: f32, max: f32) -> GraphicsPipelineBuilder {
+        self.depth_bounds = Some((min, max));
+        self
+    }
+
+    /// Enables stencil testing with separate state for front and back
+    /// facing polygons.
+    pub fn stencil(mut self, front: vk::VkStencilOpState, back: vk::VkStencilOpState)
+            -> GraphicsPipelineBuilder
+    {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    /// Adds the blend state for the next color attachment of the subpass.
+    pub fn blend_attachment(mut self, state: vk::VkPipelineColorBlendAttachmentState)
+            -> GraphicsPipelineBuilder
+    {
+        self.blend_attachments.get_or_insert_with(Vec::new).push(state);
+        self
+    }
+
+    /// Sets the blend state of every color attachment of the subpass. Pass
+    /// an empty slice for subpasses without color attachments.
+    pub fn blend_attachments(mut self, states: &[vk::VkPipelineColorBlendAttachmentState])
+            -> GraphicsPipelineBuilder
+    {
+        self.blend_attachments = Some(states.to_vec());
+        self
+    }
+
+    pub fn logic_op(mut self, logic_op: vk::VkLogicOp) -> GraphicsPipelineBuilder {
+        self.logic_op = Some(logic_op);
+        self
+    }
+
+    pub fn blend_constants(mut self, constants: [f32; 4]) -> GraphicsPipelineBuilder {
+        self.blend_constants = constants;
+        self
+    }
+
+    /// Makes `state` dynamic, to be set while recording command buffers
+    /// rather than fixed at creation.
+    pub fn dynamic_state(mut self, state: vk::VkDynamicState) -> GraphicsPipelineBuilder {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    /// Sets the index of the render pass subpass the pipeline is used in.
+    pub fn subpass(mut self, subpass: u32) -> GraphicsPipelineBuilder {
+        self.subpass = subpass;
+        self
+    }
+
+    /// Looks up and stores the compiled pipeline in `pipeline_cache`.
+    pub fn cache(mut self, pipeline_cache: &PipelineCache) -> GraphicsPipelineBuilder {
+        self.pipeline_cache = Some(pipeline_cache.clone());
+        self
+    }
+
+    /// Checks when building that the uniform or storage buffer block at
+    /// `set` and `binding` has the layout of `T::Std140`.
+    pub fn std140_block<T: AsStd140>(mut self, set: u32, binding: u32)
+            -> GraphicsPipelineBuilder
+    {
+        self.block_layouts.push(BlockLayout::std140::<T>(set, binding));
+        self
+    }
+
+    /// Checks when building that the uniform or storage buffer block at
+    /// `set` and `binding` has the layout of `T::Std430`.
+    pub fn std430_block<T: AsStd430>(mut self, set: u32, binding: u32)
+            -> GraphicsPipelineBuilder
+    {
+        self.block_layouts.push(BlockLayout::std430::<T>(set, binding));
+        self
+    }
+
+    pub fn build(self) -> VkcResult<GraphicsPipeline> {
+        let device = self.pipeline_layout.device().clone();
+        let has_stage = |stage| self.stages.iter().any(|s| s.stage == stage);
+
+        if !has_stage(vk::VK_SHADER_STAGE_VERTEX_BIT) {
+            return Err("GraphicsPipelineBuilder::build: A vertex stage is required.".into());
+        }
+        let tessellation = has_stage(vk::VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT) ||
+            has_stage(vk::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT);
+        if tessellation && self.patch_control_points == 0 {
+            return Err("GraphicsPipelineBuilder::build: Tessellation stages require the number \
+                of patch control points to be set.".into());
+        }
+
+        for (i, binding) in self.vertex_bindings.iter().enumerate() {
+            if self.vertex_bindings[..i].iter().any(|b| b.binding == binding.binding) {
+                return Err(format!("GraphicsPipelineBuilder::build: Vertex binding {} is added \
+                    more than once.", binding.binding).into());
+            }
+        }
+
+        for (i, attribute) in self.vertex_attributes.iter().enumerate() {
+            if self.vertex_attributes[..i].iter().any(|a| a.location == attribute.location) {
+                return Err(format!("GraphicsPipelineBuilder::build: Vertex attribute location {} \
+                    is used more than once.", attribute.location).into());
+            }
+            if !self.vertex_bindings.iter().any(|b| b.binding == attribute.binding) {
+                return Err(format!("GraphicsPipelineBuilder::build: Vertex attribute location {} \
+                    reads from binding {} which has not been added.", attribute.location,
+                    attribute.binding).into());
+            }
+        }
+
+        for block in &self.block_layouts {
+            block.check("GraphicsPipelineBuilder::build", self.stages.iter().map(|s| &s.module))?;
+        }
+
+        // Viewports and scissors may be left unspecified only when dynamic,
+        // in which case their count is taken from the other. Vulkan requires
+        // both counts to match.
+        if !self.viewports.is_empty() && !self.scissors.is_empty() &&
+                self.viewports.len() != self.scissors.len()
+        {
+            return Err(format!("GraphicsPipelineBuilder::build: The number of viewports ({}) \
+                does not match the number of scissors ({}).", self.viewports.len(),
+                self.scissors.len()).into());
+        }
+        let viewport_count = self.viewports.len().max(self.scissors.len()).max(1) as u32;
+        if !self.rasterizer_discard {
+            if self.viewports.is_empty() &&
+                    !self.dynamic_states.contains(&vk::VK_DYNAMIC_STATE_VIEWPORT)
+            {
+                return Err("GraphicsPipelineBuilder::build: No viewport specified and the \
+                    viewport state is not dynamic.".into());
+            }
+            if self.scissors.is_empty() &&
+                    !self.dynamic_states.contains(&vk::VK_DYNAMIC_STATE_SCISSOR)
+            {
+                return Err("GraphicsPipelineBuilder::build: No scissor specified and the \
+                    scissor state is not dynamic.".into());
+            }
+        }
+
+        let raw_specialization_infos: SmallVec<[_; 5]> = self.stages.iter()
+            .map(|s| s.specialization_info.as_ref().map(|si| si.raw()))
+            .collect();
+        let shader_stages: SmallVec<[_; 5]> = self.stages.iter()
+            .zip(raw_specialization_infos.iter())
+            .map(|(s, si)| vk::VkPipelineShaderStageCreateInfo {
+                sType: vk::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                pNext: ptr::null(),
+                flags: 0,
+                stage: s.stage,
+                module: s.module.handle(),
+                pName: s.entry_point.as_ptr(),
+                pSpecializationInfo: si.as_ref().map(|si| si as *const _).unwrap_or(ptr::null()),
+            })
+            .collect();
 
         let vertex_input_info = vk::VkPipelineVertexInputStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            vertexBindingDescriptionCount: 1,
-            pVertexBindingDescriptions: &binding_description,
-            vertexAttributeDescriptionCount: attribute_descriptions.len() as u32,
-            pVertexAttributeDescriptions: attribute_descriptions.as_ptr(),
+            vertexBindingDescriptionCount: self.vertex_bindings.len() as u32,
+            pVertexBindingDescriptions: self.vertex_bindings.as_ptr(),
+            vertexAttributeDescriptionCount: self.vertex_attributes.len() as u32,
+            pVertexAttributeDescriptions: self.vertex_attributes.as_ptr(),
         };
 
         let input_assembly = vk::VkPipelineInputAssemblyStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            // * VK_PRIMITIVE_TOPOLOGY_POINT_LIST: points from vertices
-            // * VK_PRIMITIVE_TOPOLOGY_LINE_LIST: line from every 2 vertices
-            //   without reuse
-            // * VK_PRIMITIVE_TOPOLOGY_LINE_STRIP: the end vertex of every
-            //   line is used as start vertex for the next line
-            // * VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST: triangle from every 3
-            //   vertices without reuse
-            // * VK_PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP: the second and third
-            //   vertex of every triangle are used as first two vertices of
-            //   the next triangle
-            topology: vk::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
-            primitiveRestartEnable: vk::VK_FALSE,
-        };
-
-        let viewport = vk::VkViewport {
-            x: 0.0f32,
-            y: 0.0f32,
-            width: swap_chain_extent.width as f32,
-            height: swap_chain_extent.height as f32,
-            minDepth: 0.0f32,
-            maxDepth: 1.0f32,
+            topology: self.topology,
+            primitiveRestartEnable: self.primitive_restart as vk::VkBool32,
         };
 
-        let scissor = vk::VkRect2D {
-            offset: vk::VkOffset2D {
-                x: 0,
-                y: 0,
-            },
-            extent: swap_chain_extent,
+        let tessellation_state = vk::VkPipelineTessellationStateCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_TESSELLATION_STATE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            patchControlPoints: self.patch_control_points,
         };
 
         let viewport_state = vk::VkPipelineViewportStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            viewportCount: 1,
-            pViewports: &viewport,
-            scissorCount: 1,
-            pScissors: &scissor,
+            viewportCount: viewport_count,
+            pViewports: if self.viewports.is_empty() {
+                ptr::null()
+            } else {
+                self.viewports.as_ptr()
+            },
+            scissorCount: viewport_count,
+            pScissors: if self.scissors.is_empty() {
+                ptr::null()
+            } else {
+                self.scissors.as_ptr()
+            },
         };
 
+        let (depth_bias_constant, depth_bias_clamp, depth_bias_slope) =
+            self.depth_bias.unwrap_or((0.0, 0.0, 0.0));
         let rasterizer = vk::VkPipelineRasterizationStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            depthClampEnable: vk::VK_FALSE,
-            rasterizerDiscardEnable: vk::VK_FALSE,
-            polygonMode: vk::VK_POLYGON_MODE_FILL,
-            cullMode: vk::VK_CULL_MODE_BACK_BIT,
-            // frontFace: vk::VK_FRONT_FACE_CLOCKWISE,
-            frontFace: vk::VK_FRONT_FACE_COUNTER_CLOCKWISE,
-            depthBiasEnable: vk::VK_FALSE,
-            depthBiasConstantFactor: 0.0f32,
-            depthBiasClamp: 0.0f32,
-            depthBiasSlopeFactor: 0.0f32,
-            lineWidth: 1.0f32,
+            depthClampEnable: self.depth_clamp as vk::VkBool32,
+            rasterizerDiscardEnable: self.rasterizer_discard as vk::VkBool32,
+            polygonMode: self.polygon_mode,
+            cullMode: self.cull_mode,
+            frontFace: self.front_face,
+            depthBiasEnable: self.depth_bias.is_some() as vk::VkBool32,
+            depthBiasConstantFactor: depth_bias_constant,
+            depthBiasClamp: depth_bias_clamp,
+            depthBiasSlopeFactor: depth_bias_slope,
+            lineWidth: self.line_width,
         };
 
         let multisampling = vk::VkPipelineMultisampleStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            rasterizationSamples: vk::VK_SAMPLE_COUNT_1_BIT,
-            sampleShadingEnable: vk::VK_FALSE,
-            minSampleShading: 1.0f32,
+            rasterizationSamples: self.samples,
+            sampleShadingEnable: self.min_sample_shading.is_some() as vk::VkBool32,
+            minSampleShading: self.min_sample_shading.unwrap_or(1.0),
             pSampleMask: ptr::null(),
-            alphaToCoverageEnable: vk::VK_FALSE,
-            alphaToOneEnable: vk::VK_FALSE,
+            alphaToCoverageEnable: self.alpha_to_coverage as vk::VkBool32,
+            alphaToOneEnable: self.alpha_to_one as vk::VkBool32,
         };
 
         let stencil_op_state = vk::VkStencilOpState {
@@ -147,112 +581,123 @@ impl GraphicsPipeline {
             writeMask: 0,
             reference: 0,
         };
+        let (stencil_front, stencil_back) = self.stencil.clone()
+            .unwrap_or((stencil_op_state.clone(), stencil_op_state));
+        let (min_depth_bounds, max_depth_bounds) = self.depth_bounds.unwrap_or((0.0, 1.0));
 
         let depth_stencil = vk::VkPipelineDepthStencilStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            depthTestEnable: vk::VK_TRUE,
-            depthWriteEnable: vk::VK_TRUE,
-            depthCompareOp: vk::VK_COMPARE_OP_LESS,
-            depthBoundsTestEnable: vk::VK_FALSE,
-            stencilTestEnable: vk::VK_FALSE,
-            front: stencil_op_state.clone(),
-            back: stencil_op_state,
-            minDepthBounds: 0.0,
-            maxDepthBounds: 1.0,
+            depthTestEnable: self.depth_test as vk::VkBool32,
+            depthWriteEnable: self.depth_write as vk::VkBool32,
+            depthCompareOp: self.depth_compare_op,
+            depthBoundsTestEnable: self.depth_bounds.is_some() as vk::VkBool32,
+            stencilTestEnable: self.stencil.is_some() as vk::VkBool32,
+            front: stencil_front,
+            back: stencil_back,
+            minDepthBounds: min_depth_bounds,
+            maxDepthBounds: max_depth_bounds,
         };
 
-        let color_blend_attachment = vk::VkPipelineColorBlendAttachmentState {
-            blendEnable: vk::VK_FALSE,
-            srcColorBlendFactor: vk::VK_BLEND_FACTOR_ONE,
-            dstColorBlendFactor: vk::VK_BLEND_FACTOR_ZERO,
-            colorBlendOp: vk::VK_BLEND_OP_ADD,
-            srcAlphaBlendFactor: vk::VK_BLEND_FACTOR_ONE,
-            dstAlphaBlendFactor: vk::VK_BLEND_FACTOR_ZERO,
-            alphaBlendOp: vk::VK_BLEND_OP_ADD,
-            colorWriteMask: vk::VK_COLOR_COMPONENT_R_BIT | vk::VK_COLOR_COMPONENT_G_BIT |
-                vk::VK_COLOR_COMPONENT_B_BIT | vk::VK_COLOR_COMPONENT_A_BIT,
-        };
-
-        // ///////////////////////////////////////////////
-        // /////////// KEEPME (ALPHA BLENDING) ///////////
-        // let color_blend_attachment = vk::VkPipelineColorBlendAttachmentState {
-        //     blendEnable: vk::VK_FALSE,
-        //     srcColorBlendFactor: vk::VK_BLEND_FACTOR_SRC_ALPHA,
-        //     dstColorBlendFactor: vk::VK_BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
-        //     colorBlendOp: vk::VK_BLEND_OP_ADD,
-        //     srcAlphaBlendFactor: vk::VK_BLEND_FACTOR_ONE,
-        //     dstAlphaBlendFactor: vk::VK_BLEND_FACTOR_ZERO,
-        //     alphaBlendOp: vk::VK_BLEND_OP_ADD,
-        //     colorWriteMask: vk::VK_COLOR_COMPONENT_R_BIT | vk::VK_COLOR_COMPONENT_G_BIT | vk::VK_COLOR_COMPONENT_B_BIT | vk::VK_COLOR_COMPONENT_A_BIT,
-        // }; ////////////////////////////////////////////
-        // ///////////////////////////////////////////////
-
+        let blend_attachments = self.blend_attachments.clone()
+            .unwrap_or_else(|| vec![GraphicsPipelineBuilder::opaque_attachment()]);
         let color_blending = vk::VkPipelineColorBlendStateCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            logicOpEnable: vk::VK_FALSE,
-            logicOp: vk::VK_LOGIC_OP_COPY,
-            attachmentCount: 1,
-            pAttachments: &color_blend_attachment,
-            blendConstants: [0.0f32; 4],
+            logicOpEnable: self.logic_op.is_some() as vk::VkBool32,
+            logicOp: self.logic_op.unwrap_or(vk::VK_LOGIC_OP_COPY),
+            attachmentCount: blend_attachments.len() as u32,
+            pAttachments: blend_attachments.as_ptr(),
+            blendConstants: self.blend_constants,
         };
 
-        // ///////////////////////////////////////////////
-        // /////////// KEEPME (DYNAMIC STATES) ///////////
-        // let dynamic_states = [vk::VK_DYNAMIC_STATE_VIEWPORT, vk::VK_DYNAMIC_STATE_LINE_WIDTH];
-        // let dynamic_state = vk::VkPipelineDynamicStateCreateInfo {
-        //     sType: vk::VK_STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
-        //     pNext: ptr::null(),
-        //     flags: 0,
-        //     dynamicStateCount: 2,
-        //     pDynamicStates: dynamic_states.as_ptr(),
-        // }; ////////////////////////////////////////////
-        // ///////////////////////////////////////////////
+        let dynamic_state = vk::VkPipelineDynamicStateCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            dynamicStateCount: self.dynamic_states.len() as u32,
+            pDynamicStates: self.dynamic_states.as_ptr(),
+        };
 
         let create_info = vk::VkGraphicsPipelineCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            stageCount: 2,
+            stageCount: shader_stages.len() as u32,
             pStages: shader_stages.as_ptr(),
             pVertexInputState: &vertex_input_info,
             pInputAssemblyState: &input_assembly,
-            pTessellationState: ptr::null(),
+            pTessellationState: if tessellation { &tessellation_state } else { ptr::null() },
             pViewportState: &viewport_state,
             pRasterizationState: &rasterizer,
             pMultisampleState: &multisampling,
             pDepthStencilState: &depth_stencil,
             pColorBlendState: &color_blending,
-            // pDynamicState: &dynamic_state,
-            pDynamicState: ptr::null(),
-            layout: pipeline_layout.handle(),
-            renderPass: render_pass.handle(),
-            subpass: 0,
+            pDynamicState: if self.dynamic_states.is_empty() {
+                ptr::null()
+            } else {
+                &dynamic_state
+            },
+            layout: self.pipeline_layout.handle(),
+            renderPass: self.render_pass.handle(),
+            subpass: self.subpass,
             basePipelineHandle: 0,
             basePipelineIndex: -1,
         };
 
         let mut handle = 0;
         unsafe {
-            ::check(device.vk().core.vkCreateGraphicsPipelines(device.handle(), 0, 1, &create_info,
-                ptr::null(), &mut handle));
+            ::check(device.vk().core.vkCreateGraphicsPipelines(device.handle(),
+                self.pipeline_cache.as_ref().map(|pc| pc.handle()).unwrap_or(0), 1, &create_info,
+                ptr::null(), &mut handle))?;
         }
 
         Ok(GraphicsPipeline {
             inner: Arc::new(Inner {
                 handle,
+                pipeline_layout: self.pipeline_layout.clone(),
+                render_pass: self.render_pass.clone(),
+                subpass: self.subpass,
                 device,
             })
         })
     }
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkPipeline,
+    pipeline_layout: PipelineLayout,
+    render_pass: RenderPass,
+    subpass: u32,
+    device: Device,
+}
 
+#[derive(Debug, Clone)]
+pub struct GraphicsPipeline {
+    inner: Arc<Inner>,
+}
+
+impl GraphicsPipeline {
     pub fn handle(&self) -> vk::VkPipeline {
         self.inner.handle
     }
 
+    pub fn pipeline_layout(&self) -> &PipelineLayout {
+        &self.inner.pipeline_layout
+    }
+
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.inner.render_pass
+    }
+
+    pub fn subpass(&self) -> u32 {
+        self.inner.subpass
+    }
+
     pub fn device(&self) -> &Device {
         &self.inner.device
     }
@@ -264,4 +709,4 @@ impl Drop for Inner {
             self.device.vk().core.vkDestroyPipeline(self.device.handle(), self.handle, ptr::null());
         }
     }
-}
\ No newline at end of file
+}
@@ -4,12 +4,12 @@ use std::sync::Arc;
 use std::ptr;
 use std::mem;
 use vk;
-use ::{util, VkcResult, Device, DeviceMemory};
+use ::{util, VkcResult, Device, Allocation, AllocationKind};
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkImage,
-    device_memory: DeviceMemory,
+    allocation: Allocation,
     device: Device,
 }
 
@@ -47,7 +47,7 @@ impl Image {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().vkCreateImage(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         // Memory Requirements:
@@ -58,30 +58,32 @@ impl Image {
                 &mut mem_requirements);
         }
 
-        let memory_type_index = ::find_memory_type(&device, mem_requirements.memoryTypeBits,
-            memory_properties);
-
-        let alloc_info = vk::VkMemoryAllocateInfo {
-            sType: vk::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
-            pNext: ptr::null(),
-            allocationSize: mem_requirements.size,
-            memoryTypeIndex: memory_type_index,
+        let kind = if tiling == vk::VK_IMAGE_TILING_LINEAR {
+            AllocationKind::Linear
+        } else {
+            AllocationKind::Optimal
         };
 
-        println!("Image: {:?}", mem_requirements);
-
-        let device_memory = DeviceMemory::new(device.clone(), mem_requirements.size,
-            memory_type_index)?;
-
-        unsafe {
-            ::check(device.vk().vkBindImageMemory(device.handle(), handle,
-                device_memory.handle(), 0));
-        }
+        let allocation = Allocation::new(device.clone(), &mem_requirements, memory_properties,
+            kind).and_then(|allocation| {
+                unsafe {
+                    ::check(device.vk().vkBindImageMemory(device.handle(), handle,
+                        allocation.memory(), allocation.offset()))?;
+                }
+                Ok(allocation)
+            });
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.vk().vkDestroyImage(device.handle(), handle, ptr::null()); }
+                return Err(err);
+            },
+        };
 
         Ok(Image {
             inner: Arc::new(Inner {
                 handle,
-                device_memory,
+                allocation,
                 device,
             })
         })
@@ -91,8 +93,9 @@ impl Image {
         self.inner.handle
     }
 
-    pub fn device_memory(&self) -> &DeviceMemory {
-        &self.inner.device_memory
+    /// Returns the memory allocation this image is bound to.
+    pub fn allocation(&self) -> &Allocation {
+        &self.inner.allocation
     }
 
     pub fn device(&self) -> &Device {
@@ -50,7 +50,7 @@ impl ImageView {
 
         unsafe {
             ::check(device.vk().core.vkCreateImageView(device.handle(),
-                &create_info, ptr::null(), &mut handle));
+                &create_info, ptr::null(), &mut handle))?;
         }
 
         Ok(ImageView {
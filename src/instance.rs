@@ -1,153 +1,343 @@
 use std::sync::Arc;
-use std::ffi::{self, CStr};
+use std::ffi::{CStr, CString};
 use std::ptr;
-use std::mem;
-use libc::{c_char, c_void};
+use std::fmt::Write;
+use libc::c_char;
 use vk;
-use ::{VkcResult, Loader, ENABLE_VALIDATION_LAYERS};
+use ::{VkcResult, Version, Loader, PhysicalDevice, ENABLE_VALIDATION_LAYERS};
+use debug::{self, DebugConfig, DebugMessenger};
+use surface;
+use winit;
 
 
-static VALIDATION_LAYERS: [&[u8]; 1] = [
-    b"VK_LAYER_LUNARG_standard_validation\0"
+static VALIDATION_LAYERS: [&str; 1] = [
+    "VK_LAYER_LUNARG_standard_validation",
 ];
 
-static REQUIRED_EXTENSIONS: [&[u8]; 2] = [
-    b"VK_KHR_surface",
-    b"VK_KHR_win32_surface",
+/// Platform-specific extensions able to create surfaces on this platform.
+#[cfg(windows)]
+static PLATFORM_SURFACE_EXTENSIONS: [&str; 1] = [
+    surface::WIN32_SURFACE_EXTENSION,
 ];
 
-unsafe extern "system" fn __debug_callback(_flags: vk::VkDebugReportFlagsEXT,
-        _obj_type: vk::VkDebugReportObjectTypeEXT, _obj: u64, _location: usize, _code: i32,
-        _layer_prefix: *const c_char, msg: *const c_char, _user_data: *mut c_void) -> u32
-{
-    println!("{}", CStr::from_ptr(msg).to_str().unwrap());
-    vk::VK_FALSE
-}
+/// Platform-specific extensions able to create surfaces on this platform.
+#[cfg(all(unix, not(target_os = "android"), not(target_os = "macos"), not(target_os = "ios")))]
+static PLATFORM_SURFACE_EXTENSIONS: [&str; 3] = [
+    surface::XLIB_SURFACE_EXTENSION,
+    surface::XCB_SURFACE_EXTENSION,
+    surface::WAYLAND_SURFACE_EXTENSION,
+];
 
-// fn create_debug_report_callback_ext(instance: &Instance,
-//         create_info: &vk::VkDebugReportCallbackCreateInfoEXT, allocator: vk::VkDebugReportCallbackEXT)
-// {
-//     let create_drcb = instance.get_instance_proc_addr(b"vkCreateDebugReportCallbackEXT".as_ptr() as *const i8);
-// }
+/// Window system integration extensions, none of which are enabled by a
+/// headless instance.
+static WSI_EXTENSIONS: [&[u8]; 13] = [
+    b"VK_KHR_surface\0",
+    b"VK_KHR_win32_surface\0",
+    b"VK_KHR_xlib_surface\0",
+    b"VK_KHR_xcb_surface\0",
+    b"VK_KHR_wayland_surface\0",
+    b"VK_KHR_mir_surface\0",
+    b"VK_KHR_android_surface\0",
+    b"VK_KHR_display\0",
+    b"VK_KHR_get_surface_capabilities2\0",
+    b"VK_EXT_direct_mode_display\0",
+    b"VK_EXT_acquire_xlib_display\0",
+    b"VK_EXT_display_surface_counter\0",
+    b"VK_EXT_swapchain_colorspace\0",
+];
 
-fn check_validation_layer_support(loader: &Loader, print: bool) -> bool {
+fn enumerate_instance_layer_properties(loader: &Loader) -> VkcResult<Vec<vk::VkLayerProperties>> {
     let mut layer_count = 0u32;
     let mut available_layers: Vec<vk::VkLayerProperties>;
     unsafe {
         ::check(loader.core_global().vkEnumerateInstanceLayerProperties(&mut layer_count,
-            ptr::null_mut()));
+            ptr::null_mut()))?;
         available_layers = Vec::with_capacity(layer_count as usize);
         available_layers.set_len(layer_count as usize);
         ::check(loader.core_global().vkEnumerateInstanceLayerProperties(&mut layer_count,
-            available_layers.as_mut_ptr()));
-
-        // Print available layers:
-        if print {
-            for layer_props in &available_layers {
-                println!("Available layer: '{}'",
-                    CStr::from_ptr(layer_props.layerName.as_ptr()).to_str().unwrap());
-            }
-        }
-
-        // Verify that validation layer is available:
-        for &layer_name in (&VALIDATION_LAYERS[..]).iter() {
-            let mut layer_found = false;
-            for layer_props in &available_layers {
-                if CStr::from_ptr(layer_name.as_ptr() as *const c_char) ==
-                    CStr::from_ptr(layer_props.layerName.as_ptr())
-                {
-                    if print { println!("Layer validated: '{}'",
-                        CStr::from_ptr(layer_name.as_ptr() as *const c_char).to_str().unwrap()); }
-                    layer_found = true;
-                    break;
-                }
-            }
-            if !layer_found { return false; }
-        }
+            available_layers.as_mut_ptr()))?;
     }
-    true
+    Ok(available_layers)
 }
 
-/// Currently returns all available extensions.
-fn enumerate_instance_extension_properties(loader: &Loader) -> Vec<vk::VkExtensionProperties> {
+fn enumerate_instance_extension_properties(loader: &Loader) -> VkcResult<Vec<vk::VkExtensionProperties>> {
     let mut avail_ext_count = 0u32;
     let mut avail_exts: Vec<vk::VkExtensionProperties>;
     unsafe {
         ::check(loader.core_global().vkEnumerateInstanceExtensionProperties(ptr::null(),
-            &mut avail_ext_count, ptr::null_mut()));
+            &mut avail_ext_count, ptr::null_mut()))?;
 
         avail_exts = Vec::with_capacity(avail_ext_count as usize);
         avail_exts.set_len(avail_ext_count as usize);
         ::check(loader.core_global().vkEnumerateInstanceExtensionProperties(ptr::null(),
-            &mut avail_ext_count, avail_exts.as_mut_ptr()));
-
-        // Print available:
-        for ext in &avail_exts {
-            let name = (&ext.extensionName) as *const c_char;
-            println!("Available instance extension: '{}' (version: {})",
-                CStr::from_ptr(name).to_str().unwrap(), ext.specVersion);
-        }
+            &mut avail_ext_count, avail_exts.as_mut_ptr()))?;
     }
 
-    avail_exts
+    Ok(avail_exts)
 }
 
-unsafe fn extension_names<'a>(extensions: &'a [vk::VkExtensionProperties]) -> Vec<*const c_char> {
-    extensions.iter().map(|ext| {
-        let name = (&ext.extensionName) as *const c_char;
-        println!("Enabling instance extension: '{}' (version: {})",
-            CStr::from_ptr(name).to_str().unwrap(), ext.specVersion);
-        name
-        }).collect()
+/// Returns true if `extension` is a window system integration extension.
+pub fn is_wsi_extension(extension: &CStr) -> bool {
+    WSI_EXTENSIONS.iter().any(|&wsi_name| &wsi_name[..wsi_name.len() - 1] == extension.to_bytes())
 }
 
-unsafe fn enumerate_physical_devices(instance: vk::VkInstance, loader: &vk::InstanceProcAddrLoader) -> Vec<vk::VkPhysicalDevice> {
+/// Splits `requested` into those names present in `available` and those
+/// missing from it.
+fn partition_available(requested: &[CString], available: &[&CStr]) -> (Vec<CString>, Vec<CString>) {
+    requested.iter().cloned().partition(|name| available.contains(&name.as_c_str()))
+}
+
+/// Loads the function pointers of each extension in `extensions` which
+/// defines instance-level commands.
+unsafe fn load_extensions(loader: &mut vk::InstanceProcAddrLoader, instance: vk::VkInstance,
+        extensions: &[CString])
+{
+    for ext in extensions {
+        match ext.to_bytes() {
+            b"VK_KHR_surface" => loader.load_khr_surface(instance),
+            b"VK_KHR_display" => loader.load_khr_display(instance),
+            b"VK_KHR_win32_surface" => loader.load_khr_win32_surface(instance),
+            b"VK_KHR_xlib_surface" => loader.load_khr_xlib_surface(instance),
+            b"VK_KHR_xcb_surface" => loader.load_khr_xcb_surface(instance),
+            b"VK_KHR_wayland_surface" => loader.load_khr_wayland_surface(instance),
+            b"VK_KHR_mir_surface" => loader.load_khr_mir_surface(instance),
+            b"VK_KHR_android_surface" => loader.load_khr_android_surface(instance),
+            b"VK_KHR_get_physical_device_properties2" =>
+                loader.load_khr_get_physical_device_properties2(instance),
+            b"VK_KHR_external_memory_capabilities" =>
+                loader.load_khr_external_memory_capabilities(instance),
+            b"VK_NV_external_memory_capabilities" =>
+                loader.load_nv_external_memory_capabilities(instance),
+            b"VK_EXT_debug_report" => loader.load_ext_debug_report(instance),
+            _ => (),
+        }
+    }
+}
+
+unsafe fn enumerate_physical_devices(instance: vk::VkInstance, loader: &vk::InstanceProcAddrLoader)
+        -> VkcResult<Vec<vk::VkPhysicalDevice>>
+{
     let mut device_count = 0;
-    ::check(loader.core.vkEnumeratePhysicalDevices(instance, &mut device_count, ptr::null_mut()));
-    if device_count == 0 { panic!("No physical devices found."); }
+    ::check(loader.core.vkEnumeratePhysicalDevices(instance, &mut device_count, ptr::null_mut()))?;
+    if device_count == 0 { return Err("No physical devices found.".into()); }
     let mut devices = Vec::with_capacity(device_count as usize);
     devices.set_len(device_count as usize);
-    ::check(loader.core.vkEnumeratePhysicalDevices(instance, &mut device_count, devices.as_mut_ptr()));
-    println!("Available devices: {:?}", devices);
-    devices
+    ::check(loader.core.vkEnumeratePhysicalDevices(instance, &mut device_count, devices.as_mut_ptr()))?;
+    debug!("Available devices: {:?}", devices);
+    Ok(devices)
 }
 
-pub fn enabled_layer_names(loader: &Loader, print: bool) -> Vec<*const c_char> {
-    if ENABLE_VALIDATION_LAYERS && !check_validation_layer_support(loader, print) {
-        panic!("Unable to enable validation layers.");
-    }
-    if ENABLE_VALIDATION_LAYERS {
-         (&VALIDATION_LAYERS[..]).iter().map(|lyr_name|
-            lyr_name.as_ptr() as *const c_char).collect()
-    } else {
-        Vec::new()
-    }
+fn to_cstring(name: &str) -> VkcResult<CString> {
+    CString::new(name).map_err(|_| format!("InstanceBuilder::build: Name {:?} contains a nul \
+        byte.", name).into())
 }
 
-
-#[derive(Debug)]
-struct Inner {
-    handle: vk::VkInstance,
-    loader: Loader,
-    debug_callback: Option<vk::VkDebugReportCallbackEXT>,
-    physical_devices: Vec<vk::VkPhysicalDevice>,
+/// Converts `names` to C strings, dropping any repeated name.
+fn to_unique_cstrings<'n, I>(names: I) -> VkcResult<Vec<CString>>
+        where I: IntoIterator<Item = &'n String>
+{
+    let mut cstrings: Vec<CString> = Vec::new();
+    for name in names {
+        let cstring = to_cstring(name)?;
+        if !cstrings.contains(&cstring) {
+            cstrings.push(cstring);
+        }
+    }
+    Ok(cstrings)
 }
 
+
+/// Configures and creates an `Instance`.
+///
+/// Creation fails if any required extension or layer is unavailable.
+/// Optional extensions and layers are enabled only if available. Function
+/// pointers are loaded only for the extensions actually enabled.
 #[derive(Debug, Clone)]
-pub struct Instance {
-    inner: Arc<Inner>,
+pub struct InstanceBuilder {
+    application_name: String,
+    application_version: Version,
+    engine_name: String,
+    engine_version: Version,
+    api_version: Version,
+    extensions: Vec<String>,
+    optional_extensions: Vec<String>,
+    layers: Vec<String>,
+    optional_layers: Vec<String>,
+    debug_config: DebugConfig,
 }
 
-impl Instance {
-    pub unsafe fn new(app_info: &vk::VkApplicationInfo) -> VkcResult<Instance> {
+impl InstanceBuilder {
+    /// Returns a builder requesting Vulkan 1.0 with no extensions or layers.
+    pub fn new() -> InstanceBuilder {
+        InstanceBuilder {
+            application_name: String::new(),
+            application_version: Version::new(0, 0, 0),
+            engine_name: String::new(),
+            engine_version: Version::new(0, 0, 0),
+            api_version: Version::new(1, 0, 0),
+            extensions: Vec::new(),
+            optional_extensions: Vec::new(),
+            layers: Vec::new(),
+            optional_layers: Vec::new(),
+            debug_config: DebugConfig::new(),
+        }
+    }
+
+    pub fn application_name(mut self, name: &str) -> InstanceBuilder {
+        self.application_name = name.to_owned();
+        self
+    }
+
+    pub fn application_version(mut self, version: Version) -> InstanceBuilder {
+        self.application_version = version;
+        self
+    }
+
+    pub fn engine_name(mut self, name: &str) -> InstanceBuilder {
+        self.engine_name = name.to_owned();
+        self
+    }
+
+    pub fn engine_version(mut self, version: Version) -> InstanceBuilder {
+        self.engine_version = version;
+        self
+    }
+
+    pub fn api_version(mut self, version: Version) -> InstanceBuilder {
+        self.api_version = version;
+        self
+    }
+
+    /// Requires the instance extension named `name`.
+    pub fn extension(mut self, name: &str) -> InstanceBuilder {
+        self.extensions.push(name.to_owned());
+        self
+    }
+
+    /// Enables the instance extension named `name` if available.
+    pub fn optional_extension(mut self, name: &str) -> InstanceBuilder {
+        self.optional_extensions.push(name.to_owned());
+        self
+    }
+
+    /// Requires the layer named `name`.
+    pub fn layer(mut self, name: &str) -> InstanceBuilder {
+        self.layers.push(name.to_owned());
+        self
+    }
+
+    /// Enables the layer named `name` if available.
+    pub fn optional_layer(mut self, name: &str) -> InstanceBuilder {
+        self.optional_layers.push(name.to_owned());
+        self
+    }
+
+    /// Requires `VK_KHR_surface` and enables each platform-specific surface
+    /// extension available (on Linux, any of Xlib, XCB and Wayland).
+    ///
+    /// Prefer `surface_extensions_for` when the window is known.
+    pub fn surface_extensions(mut self) -> InstanceBuilder {
+        self.extensions.push(surface::SURFACE_EXTENSION.to_owned());
+        for ext in &PLATFORM_SURFACE_EXTENSIONS[..] {
+            self.optional_extensions.push(ext.to_string());
+        }
+        self
+    }
+
+    /// Requires `VK_KHR_surface` and only the platform-specific extension
+    /// matching the backend of `window`.
+    pub fn surface_extensions_for(mut self, window: &winit::Window) -> InstanceBuilder {
+        self.extensions.push(surface::SURFACE_EXTENSION.to_owned());
+        self.extensions.push(surface::window_surface_extension(window).to_owned());
+        self
+    }
+
+    /// Enables the validation layers and a debug messenger, if available.
+    ///
+    /// Messages are received through `VK_EXT_debug_utils` or, if
+    /// unavailable, `VK_EXT_debug_report`, and are handled according to the
+    /// configuration set with `debug_config`.
+    pub fn validation(mut self) -> InstanceBuilder {
+        for layer in &VALIDATION_LAYERS[..] {
+            self.optional_layers.push(layer.to_string());
+        }
+        self.optional_extensions.push(debug::DEBUG_UTILS_EXTENSION.to_owned());
+        self.optional_extensions.push(debug::DEBUG_REPORT_EXTENSION.to_owned());
+        self
+    }
+
+    /// Sets the sink, minimum severity and error handling for debug
+    /// messages (`debug::LogSink` by default).
+    pub fn debug_config(mut self, debug_config: DebugConfig) -> InstanceBuilder {
+        self.debug_config = debug_config;
+        self
+    }
+
+    /// Creates the instance.
+    pub fn build(self) -> VkcResult<Instance> {
+        let application_name = to_cstring(&self.application_name)?;
+        let engine_name = to_cstring(&self.engine_name)?;
+        let app_info = vk::VkApplicationInfo {
+            sType: vk::VK_STRUCTURE_TYPE_APPLICATION_INFO,
+            pNext: ptr::null(),
+            pApplicationName: application_name.as_ptr(),
+            applicationVersion: self.application_version.into(),
+            pEngineName: engine_name.as_ptr(),
+            engineVersion: self.engine_version.into(),
+            apiVersion: self.api_version.into(),
+        };
+        unsafe { self.build_with(&app_info) }
+    }
+
+    unsafe fn build_with(&self, app_info: &vk::VkApplicationInfo) -> VkcResult<Instance> {
+        // Names may be requested more than once, including as both required
+        // and optional, but must only be enabled once:
+        let layers = to_unique_cstrings(&self.layers)?;
+        let optional_layers = to_unique_cstrings(self.optional_layers.iter()
+            .filter(|name| !self.layers.contains(name)))?;
+        let extensions = to_unique_cstrings(&self.extensions)?;
+        let optional_extensions = to_unique_cstrings(self.optional_extensions.iter()
+            .filter(|name| !self.extensions.contains(name)))?;
+
         let mut loader = Loader::new()?;
 
         // Layers:
-        let enabled_layer_names = enabled_layer_names(&loader, true);
+        let available_layers = enumerate_instance_layer_properties(&loader)?;
+        let available_layer_names: Vec<&CStr> = available_layers.iter()
+            .map(|layer| CStr::from_ptr(layer.layerName.as_ptr())).collect();
+        let (mut enabled_layers, missing_layers) = partition_available(&layers,
+            &available_layer_names);
+        enabled_layers.extend(partition_available(&optional_layers, &available_layer_names).0);
 
         // Extensions:
-        let extensions = enumerate_instance_extension_properties(&loader);
-        let extension_names = extension_names(extensions.as_slice());
+        let available_extensions = enumerate_instance_extension_properties(&loader)?;
+        let available_extension_names: Vec<&CStr> = available_extensions.iter()
+            .map(|ext| CStr::from_ptr(ext.extensionName.as_ptr())).collect();
+        let (mut enabled_extensions, missing_extensions) = partition_available(&extensions,
+            &available_extension_names);
+        enabled_extensions.extend(partition_available(&optional_extensions,
+            &available_extension_names).0);
+
+        // Debug report is only needed as a fallback for debug utils:
+        let is_enabled = |exts: &[CString], name: &str|
+            exts.iter().any(|ext| ext.to_bytes() == name.as_bytes());
+        let debug_utils_enabled = is_enabled(&enabled_extensions, debug::DEBUG_UTILS_EXTENSION);
+        if debug_utils_enabled && !is_enabled(&extensions, debug::DEBUG_REPORT_EXTENSION) {
+            enabled_extensions.retain(|ext|
+                ext.to_bytes() != debug::DEBUG_REPORT_EXTENSION.as_bytes());
+        }
+        let debug_report_enabled = is_enabled(&enabled_extensions, debug::DEBUG_REPORT_EXTENSION);
+
+        if !missing_extensions.is_empty() || !missing_layers.is_empty() {
+            return Err(format!("Unable to create instance. Missing required extensions: {:?}; \
+                missing required layers: {:?}.", missing_extensions, missing_layers).into());
+        }
+
+        for ext in &enabled_extensions {
+            debug!("Enabling instance extension: '{}'", ext.to_string_lossy());
+        }
+        let layer_name_ptrs: Vec<_> = enabled_layers.iter().map(|l| l.as_ptr()).collect();
+        let extension_name_ptrs: Vec<_> = enabled_extensions.iter().map(|e| e.as_ptr()).collect();
 
         // Instance:
         let create_info = vk::VkInstanceCreateInfo {
@@ -155,57 +345,87 @@ impl Instance {
             pNext: ptr::null(),
             flags: 0,
             pApplicationInfo: app_info,
-            enabledLayerCount: enabled_layer_names.len() as u32,
-            ppEnabledLayerNames: enabled_layer_names.as_ptr(),
-            enabledExtensionCount: extension_names.len() as u32,
-            ppEnabledExtensionNames:extension_names.as_ptr(),
+            enabledLayerCount: layer_name_ptrs.len() as u32,
+            ppEnabledLayerNames: layer_name_ptrs.as_ptr(),
+            enabledExtensionCount: extension_name_ptrs.len() as u32,
+            ppEnabledExtensionNames: extension_name_ptrs.as_ptr(),
         };
 
         let mut handle = ptr::null_mut();
-        ::check(loader.core_global().vkCreateInstance(&create_info, ptr::null(), &mut handle));
-        // create_info.enabled_extensions.load_instance(&mut loader, handle); // DACITE WAY
+        ::check(loader.core_global().vkCreateInstance(&create_info, ptr::null(), &mut handle))?;
 
-        // [FIXME: do this properly] Load extension function pointers:
+        // Load function pointers for the core API and enabled extensions:
         loader.loader_mut().load_core(handle);
-        loader.loader_mut().load_khr_surface(handle);
-        loader.loader_mut().load_khr_win32_surface(handle);
-        loader.loader_mut().load_khr_get_physical_device_properties2(handle);
-        loader.loader_mut().load_khr_external_memory_capabilities(handle);
-        if ENABLE_VALIDATION_LAYERS { loader.loader_mut().load_ext_debug_report(handle); }
-
-        let debug_callback = if ENABLE_VALIDATION_LAYERS {
-            let create_info = vk::VkDebugReportCallbackCreateInfoEXT {
-                sType:  vk::VK_STRUCTURE_TYPE_DEBUG_REPORT_CALLBACK_CREATE_INFO_EXT,
-                pNext: ptr::null(),
-                flags: vk::VK_DEBUG_REPORT_ERROR_BIT_EXT | vk::VK_DEBUG_REPORT_WARNING_BIT_EXT,
-                pfnCallback: Some(__debug_callback),
-                pUserData: ptr::null_mut(),
-            };
-
-            let mut callback: vk::VkDebugReportCallbackEXT = 0;
-            if loader.loader().ext_debug_report.vkCreateDebugReportCallbackEXT(handle,
-                    &create_info, ptr::null(), &mut callback) != vk::VK_SUCCESS
-            {
-                panic!("failed to set up debug callback");
-            } else {
-                println!("Debug report callback initialized.");
-            }
-            Some(callback)
-        } else {
-            None
+        load_extensions(loader.loader_mut(), handle, &enabled_extensions);
+
+        let headless = !enabled_extensions.iter().any(|ext| is_wsi_extension(ext));
+
+        // Owning the handle from here on destroys the instance (and debug
+        // messenger) should any of the remaining steps fail:
+        let mut inner = Inner {
+            handle,
+            loader,
+            api_version: Version::from(app_info.apiVersion),
+            debug_messenger: None,
+            physical_devices: Vec::new(),
+            enabled_extensions,
+            enabled_layers,
+            headless,
         };
 
+        if debug_utils_enabled || debug_report_enabled {
+            inner.debug_messenger = Some(DebugMessenger::new(&inner.loader, handle,
+                debug_utils_enabled, self.debug_config.clone())?);
+        }
+
         // Device:
-        let physical_devices = enumerate_physical_devices(handle, loader.loader());
+        inner.physical_devices = enumerate_physical_devices(handle, inner.loader.loader())?;
 
-        Ok(Instance {
-            inner: Arc::new(Inner {
-                handle,
-                loader,
-                debug_callback,
-                physical_devices,
-            }),
-        })
+        Ok(Instance { inner: Arc::new(inner) })
+    }
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkInstance,
+    loader: Loader,
+    api_version: Version,
+    debug_messenger: Option<DebugMessenger>,
+    physical_devices: Vec<vk::VkPhysicalDevice>,
+    enabled_extensions: Vec<CString>,
+    enabled_layers: Vec<CString>,
+    headless: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instance {
+    inner: Arc<Inner>,
+}
+
+impl Instance {
+    /// Creates an instance with the window system integration (surface)
+    /// extensions needed to present to `window` enabled.
+    ///
+    /// Validation layers are enabled if available and
+    /// `ENABLE_VALIDATION_LAYERS` is set. Use `InstanceBuilder` for finer
+    /// control.
+    pub unsafe fn new(app_info: &vk::VkApplicationInfo, window: &winit::Window)
+            -> VkcResult<Instance>
+    {
+        let mut builder = InstanceBuilder::new().surface_extensions_for(window);
+        if ENABLE_VALIDATION_LAYERS { builder = builder.validation(); }
+        builder.build_with(app_info)
+    }
+
+    /// Creates an instance without any window system integration extensions,
+    /// suitable for compute-only use on machines without a display.
+    ///
+    /// Validation layers are enabled only if they are available.
+    pub unsafe fn headless(app_info: &vk::VkApplicationInfo) -> VkcResult<Instance> {
+        let mut builder = InstanceBuilder::new();
+        if ENABLE_VALIDATION_LAYERS { builder = builder.validation(); }
+        builder.build_with(app_info)
     }
 
     #[inline]
@@ -235,17 +455,85 @@ impl Instance {
     pub fn loader(&self) -> &Loader {
         &self.inner.loader
     }
+
+    /// Returns the API version requested when creating this instance.
+    #[inline]
+    pub fn api_version(&self) -> Version {
+        self.inner.api_version
+    }
+
+    /// Returns the extensions enabled on this instance.
+    #[inline]
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.inner.enabled_extensions
+    }
+
+    /// Returns true if the extension named `name` is enabled.
+    pub fn is_extension_enabled(&self, name: &str) -> bool {
+        self.inner.enabled_extensions.iter().any(|ext| ext.to_bytes() == name.as_bytes())
+    }
+
+    /// Returns the layers enabled on this instance.
+    #[inline]
+    pub fn enabled_layers(&self) -> &[CString] {
+        &self.inner.enabled_layers
+    }
+
+    /// Returns the names of the layers enabled on this instance (to be
+    /// enabled on devices as well).
+    #[inline]
+    pub fn enabled_layer_names(&self) -> Vec<*const c_char> {
+        self.inner.enabled_layers.iter().map(|layer| layer.as_ptr()).collect()
+    }
+
+    /// Returns a human-readable report of the available and enabled layers
+    /// and extensions, followed by the capabilities of each physical device.
+    ///
+    /// Nothing is logged unless this is called.
+    pub fn capability_report(&self) -> VkcResult<String> {
+        let loader = &self.inner.loader;
+        let mut report = String::new();
+
+        for layer in enumerate_instance_layer_properties(loader)? {
+            let name = unsafe { CStr::from_ptr(layer.layerName.as_ptr()) };
+            writeln!(report, "Available layer: '{}' (spec version: {}, implementation \
+                version: {})", name.to_string_lossy(), Version::from(layer.specVersion),
+                layer.implementationVersion).unwrap();
+        }
+        for ext in enumerate_instance_extension_properties(loader)? {
+            let name = unsafe { CStr::from_ptr(ext.extensionName.as_ptr()) };
+            writeln!(report, "Available instance extension: '{}' (version: {})",
+                name.to_string_lossy(), ext.specVersion).unwrap();
+        }
+        for layer in &self.inner.enabled_layers {
+            writeln!(report, "Enabled layer: '{}'", layer.to_string_lossy()).unwrap();
+        }
+        for ext in &self.inner.enabled_extensions {
+            writeln!(report, "Enabled instance extension: '{}'", ext.to_string_lossy()).unwrap();
+        }
+        for device in PhysicalDevice::enumerate(self)? {
+            report.push_str(&device.report());
+        }
+        Ok(report)
+    }
+
+    /// Returns true if this instance was created without window system
+    /// integration extensions.
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        self.inner.headless
+    }
 }
 
 impl Drop for Inner {
     fn drop(&mut self) {
         unsafe {
-            println!("Destroying debug callback...");
-            if let Some(callback) = self.debug_callback {
-                self.loader.loader().ext_debug_report.vkDestroyDebugReportCallbackEXT(self.handle, callback, ptr::null());
+            trace!("Destroying debug messenger...");
+            if let Some(ref messenger) = self.debug_messenger {
+                messenger.destroy(&self.loader, self.handle);
             }
 
-            println!("Destroying instance...");
+            trace!("Destroying instance...");
             self.loader.loader().core.vkDestroyInstance(self.handle, ptr::null());
         }
     }
@@ -10,6 +10,11 @@ extern crate vks;
 extern crate libc;
 extern crate tobj;
 extern crate ordered_float;
+extern crate image as image_crate;
+#[macro_use] extern crate log;
+extern crate vkc_derive;
+// Allows derives expanding to `::vkc::` paths to be used within this crate.
+extern crate self as vkc;
 pub extern crate winit;
 
 mod error;
@@ -22,18 +27,27 @@ mod pipeline_layout;
 mod shader_module;
 mod render_pass;
 mod graphics_pipeline;
+mod compute_pipeline;
+mod pipeline_cache;
+mod specialization_info;
 mod framebuffer;
 mod surface;
 mod queue;
 mod command_pool;
 mod command_buffers;
 mod semaphore;
+mod fence;
 mod buffer;
 mod image;
 mod sampler;
 mod device_memory;
+mod memory_allocator;
 mod descriptor_set_layout;
 mod descriptor_pool;
+mod descriptor_set;
+mod descriptor_allocator;
+mod render_target;
+mod vertex_input;
 
 pub mod vk {
     pub use vks::*;
@@ -61,7 +75,11 @@ pub mod vk {
 }
 
 // pub mod vulkan_h;
+pub mod debug;
 pub mod device;
+pub mod physical_device;
+pub mod spirv;
+pub mod std_layout;
 pub mod util;
 
 use std::ffi::OsStr;
@@ -76,28 +94,44 @@ use ordered_float::OrderedFloat;
 // pub use vulkan_h as vk;
 // pub use vks::core as vkscore;
 // use vk::*;
-pub use error::Error;
+pub use error::{Error, ErrorKind, vk_result_name};
 pub use version::Version;
-pub use instance::Instance;
-pub use device::Device;
-pub use surface::Surface;
-pub use queue::{queue_families, Queue};
+pub use instance::{Instance, InstanceBuilder};
+pub use debug::DebugConfig;
+pub use device::{Device, DeviceBuilder};
+pub use physical_device::{PhysicalDevice, PhysicalDeviceSelector};
+pub use surface::{Surface, window_surface_extension};
+pub use queue::{queue_families, Queue, QueueRole, SubmitInfo};
 pub use swapchain::{Swapchain, SwapchainSupportDetails};
 pub use image_view::{create_image_views, ImageView};
 pub use shader_module::ShaderModule;
 pub use pipeline_layout::PipelineLayout;
 pub use render_pass::RenderPass;
-pub use graphics_pipeline::GraphicsPipeline;
+pub use graphics_pipeline::{GraphicsPipeline, GraphicsPipelineBuilder};
+pub use compute_pipeline::{ComputePipeline, ComputePipelineBuilder};
+pub use pipeline_cache::PipelineCache;
+pub use specialization_info::SpecializationInfo;
 pub use framebuffer::{create_framebuffers, Framebuffer};
 pub use command_pool::CommandPool;
-pub use command_buffers::create_command_buffers;
+pub use command_buffers::{create_command_buffers, CommandBuffer, BufferBarrier, ImageBarrier};
 pub use semaphore::Semaphore;
-pub use buffer::Buffer;
+pub use fence::Fence;
+pub use buffer::{Buffer, MappedBuffer, Pod};
 pub use image::Image;
 pub use sampler::Sampler;
 pub use device_memory::DeviceMemory;
-pub use descriptor_set_layout::DescriptorSetLayout;
+pub use memory_allocator::{MemoryAllocator, Allocation, AllocationKind, MemoryStats, MemoryUsage};
+pub use descriptor_set_layout::{DescriptorSetLayout, DescriptorSetLayoutBuilder,
+    DescriptorBinding};
 pub use descriptor_pool::DescriptorPool;
+pub use descriptor_set::DescriptorSet;
+pub use descriptor_allocator::DescriptorAllocator;
+pub use render_target::RenderTarget;
+pub use vertex_input::{VertexInput, VertexFormat, NormalizedVertexFormat};
+pub use vkc_derive::VertexInput;
+pub use std_layout::{AsStd140, AsStd430, Std140ArrayElement, Std430ArrayElement, Align16,
+    BlockLayout};
+pub use vkc_derive::{AsStd140, AsStd430};
 
 
 pub type VkcResult<T> = Result<T, Error>;
@@ -117,11 +151,13 @@ macro_rules! offset_of {
 
 
 
-pub fn find_memory_type(device: &Device, type_filter: u32, properties: vk::VkMemoryPropertyFlags) -> u32 {
+pub fn find_memory_type(device: &Device, type_filter: u32, properties: vk::VkMemoryPropertyFlags)
+        -> VkcResult<u32>
+{
     let mut mem_properties: vk::VkPhysicalDeviceMemoryProperties;
     unsafe {
         mem_properties = mem::uninitialized();
-        device.instance().vk().core.vkGetPhysicalDeviceMemoryProperties(device.physical_device(),
+        device.instance().vk().core.vkGetPhysicalDeviceMemoryProperties(device.physical_device().handle(),
             &mut mem_properties);
     }
 
@@ -129,15 +165,15 @@ pub fn find_memory_type(device: &Device, type_filter: u32, properties: vk::VkMem
         if (type_filter & (1 << i)) != 0 &&
             (mem_properties.memoryTypes[i as usize].propertyFlags & properties) == properties
         {
-            return i;
+            return Ok(i);
         }
     }
-    panic!("Failed to find suitable memory type.");
+    Err("Failed to find suitable memory type.".into())
 }
 
 
 
-#[derive(Clone)]
+#[derive(Clone, Copy, VertexInput)]
 #[repr(C)]
 pub struct Vertex {
     pub pos: [f32; 3],
@@ -145,43 +181,6 @@ pub struct Vertex {
     pub tex_coord: [f32; 2],
 }
 
-impl Vertex {
-    pub fn binding_description() -> vk::VkVertexInputBindingDescription {
-        vk::VkVertexInputBindingDescription {
-            binding: 0,
-            stride: mem::size_of::<Vertex>() as u32,
-            // * VERTEX_INPUT_RATE_VERTEX: Move to the next data entry
-            //   after each vertex
-            // * VERTEX_INPUT_RATE_INSTANCE: Move to the next data entry
-            //   after each instance
-            inputRate: vk::VK_VERTEX_INPUT_RATE_VERTEX,
-        }
-    }
-
-    pub fn attribute_descriptions() -> [vk::VkVertexInputAttributeDescription; 3] {
-        [
-            vk::VkVertexInputAttributeDescription {
-                binding: 0,
-                location: 0,
-                format: vk::VK_FORMAT_R32G32B32_SFLOAT,
-                offset: offset_of!(Vertex, pos),
-            },
-            vk::VkVertexInputAttributeDescription {
-                binding: 0,
-                location: 1,
-                format: vk::VK_FORMAT_R32G32B32_SFLOAT,
-                offset: offset_of!(Vertex, color),
-            },
-            vk::VkVertexInputAttributeDescription {
-                binding: 0,
-                location: 2,
-                format: vk::VK_FORMAT_R32G32_SFLOAT,
-                offset: offset_of!(Vertex, tex_coord),
-            },
-        ]
-    }
-}
-
 impl Hash for Vertex {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let pos = [OrderedFloat(self.pos[0]), OrderedFloat(self.pos[1]),
@@ -203,6 +202,8 @@ impl PartialEq for Vertex {
 
 impl Eq for Vertex {}
 
+unsafe impl Pod for Vertex {}
+
 // #[derive(Debug)]
 // pub struct UniformBufferObject {
 //     pub model: Matrix4<f32>,
@@ -210,7 +211,7 @@ impl Eq for Vertex {}
 //     pub proj: Matrix4<f32>,
 // }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, AsStd140)]
 #[repr(C)]
 pub struct UniformBufferObject {
     pub model: [[f32; 4]; 4],
@@ -245,7 +246,7 @@ pub struct UniformBufferObject {
 //         let mut handle = 0;
 //         unsafe {
 //             ::check(device.vk().vkCreateAbstractTemplate(device.handle(), &create_info,
-//                 ptr::null(), &mut handle));
+//                 ptr::null(), &mut handle))?;
 //         }
 
 //         Ok(AbstractTemplate {
@@ -278,15 +279,42 @@ pub struct UniformBufferObject {
 
 
 
-pub fn check(code: i32) {
-    if code != vk::VK_SUCCESS { panic!("Error code: {}", code); }
+/// Converts a `VkResult` code into a `VkcResult`.
+///
+/// Error codes (negative values) are returned as an `ErrorKind::VkResult`
+/// error. Success codes, including status codes such as `VK_INCOMPLETE`,
+/// `VK_TIMEOUT` or `VK_SUBOPTIMAL_KHR`, are passed through as `Ok` so that
+/// callers can handle them separately.
+///
+/// Also raises any error reported to a debug messenger configured with
+/// `DebugConfig::panic_on_error` on this thread.
+pub fn check(code: i32) -> VkcResult<i32> {
+    debug::raise_pending_error();
+    if code < 0 {
+        Err(Error::vk_result(code))
+    } else {
+        Ok(code)
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use vk;
+
     #[test]
     fn it_works() {
 
     }
+
+    #[test]
+    fn check_result_codes() {
+        assert_eq!(::check(vk::VK_SUCCESS).unwrap(), vk::VK_SUCCESS);
+        assert_eq!(::check(vk::VK_INCOMPLETE).unwrap(), vk::VK_INCOMPLETE);
+        assert_eq!(::check(vk::VK_SUBOPTIMAL_KHR).unwrap(), vk::VK_SUBOPTIMAL_KHR);
+
+        let err = ::check(vk::VK_ERROR_DEVICE_LOST).unwrap_err();
+        assert_eq!(err.vk_result_code(), Some(vk::VK_ERROR_DEVICE_LOST));
+        assert_eq!(::vk_result_name(vk::VK_ERROR_DEVICE_LOST), "VK_ERROR_DEVICE_LOST");
+    }
 }
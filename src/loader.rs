@@ -24,12 +24,14 @@ impl Loader {
         } else {
             unimplemented!("macos not implemented");
         };
-        let vk_lib = lib::Library::new(lib_filename).unwrap();
+        let vk_lib = lib::Library::new(lib_filename).map_err(|err|
+            format!("Unable to load the Vulkan library ('{}'): {}", lib_filename, err))?;
 
         let vk_get_instance_proc_addr = unsafe {
             let fn_name = "vkGetInstanceProcAddr";
 
-            let get_proc_addr: lib::Symbol<vk::PFN_vkGetInstanceProcAddr> = vk_lib.get(fn_name.as_bytes()).unwrap();
+            let get_proc_addr: lib::Symbol<vk::PFN_vkGetInstanceProcAddr> = vk_lib.get(fn_name.as_bytes())
+                .map_err(|err| format!("Unable to load '{}': {}", fn_name, err))?;
             *get_proc_addr
         };
 
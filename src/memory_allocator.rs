@@ -0,0 +1,578 @@
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use vk;
+use vks;
+use ::{VkcResult, Device};
+
+
+/// The preferred size of each block of device memory.
+///
+/// Blocks are capped to an eighth of the size of their heap.
+const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+
+fn align_up(offset: u64, alignment: u64) -> u64 {
+    if alignment <= 1 { return offset; }
+    (offset + alignment - 1) / alignment * alignment
+}
+
+fn align_down(offset: u64, alignment: u64) -> u64 {
+    if alignment <= 1 { return offset; }
+    offset / alignment * alignment
+}
+
+
+/// The kind of resource an allocation is bound to.
+///
+/// Buffers and linearly tiled images are 'linear' resources and optimally
+/// tiled images are 'non-linear'. Linear and non-linear resources are never
+/// placed within the same block when `bufferImageGranularity` is greater
+/// than one, which guarantees that they never share a granularity 'page'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationKind {
+    Linear,
+    Optimal,
+}
+
+
+/// Memory usage statistics for a memory type or for all memory types.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryUsage {
+    /// The number of `VkDeviceMemory` blocks allocated.
+    pub block_count: usize,
+    /// The number of live suballocations.
+    pub allocation_count: usize,
+    /// The total size of all blocks in bytes.
+    pub bytes_allocated: u64,
+    /// The total size of all live suballocations in bytes.
+    pub bytes_used: u64,
+}
+
+impl MemoryUsage {
+    fn add(&mut self, other: &MemoryUsage) {
+        self.block_count += other.block_count;
+        self.allocation_count += other.allocation_count;
+        self.bytes_allocated += other.bytes_allocated;
+        self.bytes_used += other.bytes_used;
+    }
+}
+
+
+/// Memory usage statistics for an allocator.
+#[derive(Debug, Clone)]
+pub struct MemoryStats {
+    pub total: MemoryUsage,
+    /// Usage per memory type, indexed by memory type index.
+    pub memory_types: Vec<MemoryUsage>,
+}
+
+
+/// A range of a block.
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    offset: u64,
+    size: u64,
+}
+
+
+/// A single `VkDeviceMemory` allocation from which suballocations are made.
+#[derive(Debug)]
+struct Block {
+    id: u64,
+    memory: vk::VkDeviceMemory,
+    size: u64,
+    kind: AllocationKind,
+    dedicated: bool,
+    // Sorted by offset, never adjacent:
+    free_ranges: Vec<Range>,
+    allocation_count: usize,
+    // The whole block is mapped while any allocation within it is:
+    map_count: usize,
+    mapped_ptr: *mut u8,
+}
+
+impl Block {
+    fn new(id: u64, memory: vk::VkDeviceMemory, size: u64, kind: AllocationKind, dedicated: bool)
+            -> Block
+    {
+        Block {
+            id,
+            memory,
+            size,
+            kind,
+            dedicated,
+            free_ranges: vec![Range { offset: 0, size }],
+            allocation_count: 0,
+            map_count: 0,
+            mapped_ptr: ptr::null_mut(),
+        }
+    }
+
+    /// Finds the first free range able to hold `size` bytes at `alignment`,
+    /// removes the space from the free list and returns its offset.
+    fn suballocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let mut found = None;
+        for (i, range) in self.free_ranges.iter().enumerate() {
+            let offset = align_up(range.offset, alignment);
+            if offset.checked_add(size).map(|end| end <= range.offset + range.size)
+                    .unwrap_or(false)
+            {
+                found = Some((i, offset));
+                break;
+            }
+        }
+
+        let (i, offset) = found?;
+        let range = self.free_ranges.remove(i);
+        let range_end = range.offset + range.size;
+        let alloc_end = offset + size;
+
+        // Return any leftover space on either side to the free list:
+        if alloc_end < range_end {
+            self.free_ranges.insert(i, Range { offset: alloc_end, size: range_end - alloc_end });
+        }
+        if offset > range.offset {
+            self.free_ranges.insert(i, Range { offset: range.offset, size: offset - range.offset });
+        }
+
+        self.allocation_count += 1;
+        Some(offset)
+    }
+
+    /// Returns a range to the free list, coalescing it with its neighbors.
+    fn free(&mut self, offset: u64, size: u64) {
+        let i = self.free_ranges.iter().position(|r| r.offset > offset)
+            .unwrap_or(self.free_ranges.len());
+        self.free_ranges.insert(i, Range { offset, size });
+
+        if i + 1 < self.free_ranges.len() &&
+                self.free_ranges[i].offset + self.free_ranges[i].size == self.free_ranges[i + 1].offset
+        {
+            self.free_ranges[i].size += self.free_ranges[i + 1].size;
+            self.free_ranges.remove(i + 1);
+        }
+        if i > 0 &&
+                self.free_ranges[i - 1].offset + self.free_ranges[i - 1].size == self.free_ranges[i].offset
+        {
+            self.free_ranges[i - 1].size += self.free_ranges[i].size;
+            self.free_ranges.remove(i);
+        }
+
+        self.allocation_count -= 1;
+    }
+
+    fn bytes_free(&self) -> u64 {
+        self.free_ranges.iter().map(|r| r.size).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.allocation_count == 0
+    }
+}
+
+
+#[derive(Debug)]
+struct Pools {
+    // Indexed by memory type index:
+    blocks: Vec<Vec<Block>>,
+    next_block_id: u64,
+}
+
+impl Pools {
+    fn block_mut(&mut self, memory_type_index: u32, block_id: u64) -> Option<&mut Block> {
+        self.blocks.get_mut(memory_type_index as usize)?.iter_mut().find(|b| b.id == block_id)
+    }
+}
+
+
+/// A device memory allocator which carves suballocations out of large
+/// blocks of device memory, one set of blocks per memory type.
+///
+/// Each `Device` owns an allocator, accessible with
+/// `Device::memory_allocator`. Suballocations are made by creating an
+/// `Allocation` and are returned to their block when dropped.
+#[derive(Debug)]
+pub struct MemoryAllocator {
+    memory_properties: vk::VkPhysicalDeviceMemoryProperties,
+    buffer_image_granularity: u64,
+    non_coherent_atom_size: u64,
+    pools: Mutex<Pools>,
+}
+
+impl MemoryAllocator {
+    pub fn new(memory_properties: vk::VkPhysicalDeviceMemoryProperties,
+            buffer_image_granularity: u64, non_coherent_atom_size: u64) -> MemoryAllocator
+    {
+        let blocks = (0..memory_properties.memoryTypeCount).map(|_| Vec::new()).collect();
+
+        MemoryAllocator {
+            memory_properties,
+            buffer_image_granularity,
+            non_coherent_atom_size,
+            pools: Mutex::new(Pools { blocks, next_block_id: 0 }),
+        }
+    }
+
+    /// Returns true if the memory type is host visible but not host
+    /// coherent, requiring explicit flushes and invalidations.
+    pub fn is_non_coherent(&self, memory_type_index: u32) -> bool {
+        let flags = self.memory_type_properties(memory_type_index);
+        (flags & vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT) != 0 &&
+            (flags & vk::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT) == 0
+    }
+
+    /// Returns the index of the first memory type allowed by `type_filter`
+    /// which has all of `properties`.
+    pub fn find_memory_type(&self, type_filter: u32, properties: vk::VkMemoryPropertyFlags)
+            -> VkcResult<u32>
+    {
+        for i in 0..self.memory_properties.memoryTypeCount {
+            if (type_filter & (1 << i)) != 0 &&
+                (self.memory_properties.memoryTypes[i as usize].propertyFlags & properties) == properties
+            {
+                return Ok(i);
+            }
+        }
+        Err("Failed to find suitable memory type.".into())
+    }
+
+    /// Returns the property flags of a memory type.
+    pub fn memory_type_properties(&self, memory_type_index: u32) -> vk::VkMemoryPropertyFlags {
+        self.memory_properties.memoryTypes[memory_type_index as usize].propertyFlags
+    }
+
+    /// Returns the size of the blocks allocated for a memory type.
+    pub fn block_size(&self, memory_type_index: u32) -> u64 {
+        let heap_idx = self.memory_properties.memoryTypes[memory_type_index as usize].heapIndex;
+        let heap_size = self.memory_properties.memoryHeaps[heap_idx as usize].size;
+        if heap_size / 8 < DEFAULT_BLOCK_SIZE { heap_size / 8 } else { DEFAULT_BLOCK_SIZE }
+    }
+
+    fn pools(&self) -> VkcResult<MutexGuard<Pools>> {
+        self.pools.lock()
+            .map_err(|_| "MemoryAllocator: Lock poisoned by a panicking thread.".into())
+    }
+
+    /// Returns current usage statistics.
+    pub fn stats(&self) -> VkcResult<MemoryStats> {
+        let pools = self.pools()?;
+        let mut total = MemoryUsage::default();
+
+        let memory_types = pools.blocks.iter().map(|blocks| {
+            let mut usage = MemoryUsage::default();
+            for block in blocks {
+                usage.block_count += 1;
+                usage.allocation_count += block.allocation_count;
+                usage.bytes_allocated += block.size;
+                usage.bytes_used += block.size - block.bytes_free();
+            }
+            total.add(&usage);
+            usage
+        }).collect();
+
+        Ok(MemoryStats { total, memory_types })
+    }
+
+    fn allocate(&self, device: &Device, size: u64, alignment: u64, memory_type_index: u32,
+            kind: AllocationKind) -> VkcResult<(vk::VkDeviceMemory, u64, u64)>
+    {
+        // Kinds only need to be kept apart when the granularity is coarse:
+        let kind = if self.buffer_image_granularity > 1 { kind } else { AllocationKind::Linear };
+        if size == 0 {
+            return Err("MemoryAllocator::allocate: Size must be greater than zero.".into());
+        }
+        if alignment != 0 && !alignment.is_power_of_two() {
+            return Err(format!("MemoryAllocator::allocate: Alignment ({}) must be a power of \
+                two.", alignment).into());
+        }
+        if memory_type_index >= self.memory_properties.memoryTypeCount {
+            return Err(format!("MemoryAllocator::allocate: Invalid memory type index ({}).",
+                memory_type_index).into());
+        }
+        let block_size = self.block_size(memory_type_index);
+        let mut pools = self.pools()?;
+
+        for block in pools.blocks[memory_type_index as usize].iter_mut() {
+            if block.kind != kind || block.dedicated { continue; }
+            if let Some(offset) = block.suballocate(size, alignment) {
+                return Ok((block.memory, block.id, offset));
+            }
+        }
+
+        // Large allocations get a block of their own:
+        let dedicated = size > block_size / 2;
+        let new_block_size = if dedicated { size } else { block_size };
+
+        let alloc_info = vk::VkMemoryAllocateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: new_block_size,
+            memoryTypeIndex: memory_type_index,
+        };
+
+        let mut memory = 0;
+        unsafe {
+            ::check(device.vk().core.vkAllocateMemory(device.handle(), &alloc_info,
+                ptr::null(), &mut memory))?;
+        }
+
+        let id = pools.next_block_id;
+        pools.next_block_id += 1;
+        let mut block = Block::new(id, memory, new_block_size, kind, dedicated);
+        let offset = match block.suballocate(size, alignment) {
+            Some(offset) => offset,
+            None => {
+                unsafe { device.vk().core.vkFreeMemory(device.handle(), memory, ptr::null()); }
+                return Err(format!("MemoryAllocator::allocate: Unable to fit {} bytes at \
+                    alignment {} in a new block of {} bytes.", size, alignment, new_block_size)
+                    .into());
+            },
+        };
+        pools.blocks[memory_type_index as usize].push(block);
+        Ok((memory, id, offset))
+    }
+
+    /// Maps the block, if not already mapped, and returns a pointer to its
+    /// start.
+    fn map(&self, device: &Device, memory_type_index: u32, block_id: u64) -> VkcResult<*mut u8> {
+        let mut pools = self.pools()?;
+        let block = Pools::block_mut(&mut pools, memory_type_index, block_id)
+            .ok_or("MemoryAllocator::map: Unknown memory block.")?;
+
+        if block.map_count == 0 {
+            let mut data = ptr::null_mut();
+            unsafe {
+                ::check(device.vk().core.vkMapMemory(device.handle(), block.memory, 0,
+                    vk::VK_WHOLE_SIZE, 0, &mut data))?;
+            }
+            block.mapped_ptr = data as *mut u8;
+        }
+        block.map_count += 1;
+        Ok(block.mapped_ptr)
+    }
+
+    fn unmap(&self, device: &Device, memory_type_index: u32, block_id: u64) -> VkcResult<()> {
+        let mut pools = self.pools()?;
+        let block = Pools::block_mut(&mut pools, memory_type_index, block_id)
+            .ok_or("MemoryAllocator::unmap: Unknown memory block.")?;
+        if block.map_count == 0 {
+            return Err("MemoryAllocator::unmap: Memory block is not mapped.".into());
+        }
+
+        block.map_count -= 1;
+        if block.map_count == 0 {
+            unsafe { device.vk().core.vkUnmapMemory(device.handle(), block.memory); }
+            block.mapped_ptr = ptr::null_mut();
+        }
+        Ok(())
+    }
+
+    fn free(&self, device: &Device, memory_type_index: u32, block_id: u64, offset: u64, size: u64)
+            -> VkcResult<()>
+    {
+        let mut pools = self.pools()?;
+        let blocks = pools.blocks.get_mut(memory_type_index as usize)
+            .ok_or("MemoryAllocator::free: Invalid memory type index.")?;
+        let block_idx = blocks.iter().position(|b| b.id == block_id)
+            .ok_or("MemoryAllocator::free: Unknown memory block.")?;
+        blocks[block_idx].free(offset, size);
+
+        // Release empty blocks, keeping one block of each kind around to
+        // avoid thrashing:
+        if blocks[block_idx].is_empty() {
+            let kind = blocks[block_idx].kind;
+            let keep = !blocks[block_idx].dedicated &&
+                !blocks.iter().any(|b| b.id != block_id && b.kind == kind && !b.dedicated);
+            if !keep {
+                let block = blocks.remove(block_idx);
+                unsafe {
+                    device.vk().core.vkFreeMemory(device.handle(), block.memory, ptr::null());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Frees all remaining blocks. Must only be called when the device is being
+/// destroyed.
+pub unsafe fn destroy_memory_allocator(allocator: &MemoryAllocator, device: vk::VkDevice,
+        loader: &vks::DeviceProcAddrLoader)
+{
+    // Memory must be freed regardless of a panic elsewhere:
+    let mut pools = allocator.pools.lock().unwrap_or_else(|err| err.into_inner());
+    for blocks in pools.blocks.iter_mut() {
+        for block in blocks.drain(..) {
+            debug_assert!(block.is_empty());
+            loader.core.vkFreeMemory(device, block.memory, ptr::null());
+        }
+    }
+}
+
+
+/// A suballocation of device memory, returned to its allocator when dropped.
+#[derive(Debug)]
+pub struct Allocation {
+    memory: vk::VkDeviceMemory,
+    memory_type_index: u32,
+    block_id: u64,
+    offset: u64,
+    size: u64,
+    device: Device,
+}
+
+impl Allocation {
+    /// Allocates memory satisfying `requirements` from the first memory type
+    /// with all of `memory_properties`.
+    pub fn new(device: Device, requirements: &vk::VkMemoryRequirements,
+            memory_properties: vk::VkMemoryPropertyFlags, kind: AllocationKind)
+            -> VkcResult<Allocation>
+    {
+        let memory_type_index = device.memory_allocator().find_memory_type(
+            requirements.memoryTypeBits, memory_properties)?;
+
+        // Non-coherent allocations are padded out to whole atoms so that
+        // flushes and invalidations never touch a neighboring allocation:
+        let (size, alignment) = if device.memory_allocator().is_non_coherent(memory_type_index) {
+            let atom_size = device.memory_allocator().non_coherent_atom_size;
+            (align_up(requirements.size, atom_size), align_up(requirements.alignment, atom_size))
+        } else {
+            (requirements.size, requirements.alignment)
+        };
+
+        let (memory, block_id, offset) = device.memory_allocator().allocate(&device,
+            size, alignment, memory_type_index, kind)?;
+
+        Ok(Allocation {
+            memory,
+            memory_type_index,
+            block_id,
+            offset,
+            size,
+            device,
+        })
+    }
+
+    /// Returns the handle of the block of memory this allocation is within.
+    pub fn memory(&self) -> vk::VkDeviceMemory {
+        self.memory
+    }
+
+    /// Returns the offset of this allocation within its block of memory.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn memory_type_index(&self) -> u32 {
+        self.memory_type_index
+    }
+
+    /// Returns the property flags of the memory type of this allocation.
+    pub fn memory_properties(&self) -> vk::VkMemoryPropertyFlags {
+        self.device.memory_allocator().memory_type_properties(self.memory_type_index)
+    }
+
+    /// Returns true if the memory of this allocation is host visible but not
+    /// host coherent.
+    pub fn is_non_coherent(&self) -> bool {
+        self.device.memory_allocator().is_non_coherent(self.memory_type_index)
+    }
+
+    /// Maps this allocation into host memory and returns a pointer to its
+    /// start.
+    ///
+    /// Calls to `map` are reference counted and must each be paired with a
+    /// call to `unmap`.
+    pub fn map(&self) -> VkcResult<*mut u8> {
+        let block_ptr = self.device.memory_allocator().map(&self.device, self.memory_type_index,
+            self.block_id)?;
+        Ok(unsafe { block_ptr.offset(self.offset as isize) })
+    }
+
+    pub fn unmap(&self) -> VkcResult<()> {
+        self.device.memory_allocator().unmap(&self.device, self.memory_type_index, self.block_id)
+    }
+
+    /// Returns the atom-aligned range of `size` bytes at `offset` (relative to
+    /// the start of this allocation).
+    fn mapped_range(&self, offset: u64, size: u64) -> vk::VkMappedMemoryRange {
+        let atom_size = self.device.memory_allocator().non_coherent_atom_size;
+        let start = align_down(self.offset + offset, atom_size);
+        let end = align_up(self.offset + offset + size, atom_size);
+
+        vk::VkMappedMemoryRange {
+            sType: vk::VK_STRUCTURE_TYPE_MAPPED_MEMORY_RANGE,
+            pNext: ptr::null(),
+            memory: self.memory,
+            offset: start,
+            size: end - start,
+        }
+    }
+
+    /// Flushes host writes to a mapped range of this allocation, making them
+    /// visible to the device. Does nothing for host coherent memory.
+    pub fn flush(&self, offset: u64, size: u64) -> VkcResult<()> {
+        if !self.is_non_coherent() { return Ok(()); }
+        let range = self.mapped_range(offset, size);
+        unsafe {
+            ::check(self.device.vk().core.vkFlushMappedMemoryRanges(self.device.handle(), 1,
+                &range))?;
+        }
+        Ok(())
+    }
+
+    /// Invalidates a mapped range of this allocation, making device writes
+    /// visible to the host. Does nothing for host coherent memory.
+    pub fn invalidate(&self, offset: u64, size: u64) -> VkcResult<()> {
+        if !self.is_non_coherent() { return Ok(()); }
+        let range = self.mapped_range(offset, size);
+        unsafe {
+            ::check(self.device.vk().core.vkInvalidateMappedMemoryRanges(self.device.handle(), 1,
+                &range))?;
+        }
+        Ok(())
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Err(err) = self.device.memory_allocator().free(&self.device,
+                self.memory_type_index, self.block_id, self.offset, self.size)
+        {
+            error!("Error freeing memory allocation: {}", err);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, AllocationKind};
+
+    #[test]
+    fn block_suballocation() {
+        let mut block = Block::new(0, 0, 1024, AllocationKind::Linear, false);
+        assert_eq!(block.suballocate(100, 1), Some(0));
+        assert_eq!(block.suballocate(100, 256), Some(256));
+        assert_eq!(block.suballocate(100, 1), Some(100));
+        assert_eq!(block.bytes_free(), 1024 - 300);
+        assert_eq!(block.suballocate(1024, 1), None);
+        assert_eq!(block.suballocate(u64::max_value(), 1), None);
+
+        block.free(256, 100);
+        block.free(0, 100);
+        block.free(100, 100);
+        assert!(block.is_empty());
+        assert_eq!(block.free_ranges.len(), 1);
+        assert_eq!(block.suballocate(1024, 1), Some(0));
+    }
+}
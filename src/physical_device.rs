@@ -0,0 +1,458 @@
+use std::sync::Arc;
+use std::cmp::Reverse;
+use std::ffi::CStr;
+use std::fmt::{self, Write};
+use std::mem;
+use std::ptr;
+use libc::{c_char, c_void};
+use vk;
+use ::{VkcResult, Version, Instance, Surface, SwapchainSupportDetails};
+use queue;
+
+
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2: vk::VkStructureType = 1000059001;
+const VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES: vk::VkStructureType = 1000094000;
+
+static SWAPCHAIN_EXTENSION: &str = "VK_KHR_swapchain";
+static GET_PHYSICAL_DEVICE_PROPERTIES_2_EXTENSION: &str =
+    "VK_KHR_get_physical_device_properties2";
+
+// Not defined by `vks`, which predates Vulkan 1.1:
+#[repr(C)]
+struct VkPhysicalDeviceProperties2 {
+    sType: vk::VkStructureType,
+    pNext: *mut c_void,
+    properties: vk::VkPhysicalDeviceProperties,
+}
+
+#[repr(C)]
+struct VkPhysicalDeviceSubgroupProperties {
+    sType: vk::VkStructureType,
+    pNext: *mut c_void,
+    subgroupSize: u32,
+    supportedStages: vk::VkShaderStageFlags,
+    supportedOperations: u32,
+    quadOperationsInAllStages: vk::VkBool32,
+}
+
+type PfnGetPhysicalDeviceProperties2 = unsafe extern "system" fn(vk::VkPhysicalDevice,
+    *mut VkPhysicalDeviceProperties2);
+
+
+/// Queries the subgroup size of `handle` through
+/// `vkGetPhysicalDeviceProperties2[KHR]`. Returns `None` if the device does
+/// not support Vulkan 1.1 or the instance neither targets Vulkan 1.1 nor has
+/// `VK_KHR_get_physical_device_properties2` enabled.
+unsafe fn query_subgroup_size(instance: &Instance, handle: vk::VkPhysicalDevice,
+        properties: &vk::VkPhysicalDeviceProperties) -> Option<u32>
+{
+    if Version::from(properties.apiVersion) < Version::new(1, 1, 0) { return None; }
+
+    // The entry point may only be used if it is part of the instance's API
+    // version or provided by an enabled extension:
+    let name: &[u8] = if instance.api_version() >= Version::new(1, 1, 0) {
+        b"vkGetPhysicalDeviceProperties2\0"
+    } else if instance.is_extension_enabled(GET_PHYSICAL_DEVICE_PROPERTIES_2_EXTENSION) {
+        b"vkGetPhysicalDeviceProperties2KHR\0"
+    } else {
+        return None;
+    };
+
+    let get_instance_proc_addr = instance.get_instance_proc_addr(ptr::null())?;
+    let pfn = get_instance_proc_addr(instance.handle(), name.as_ptr() as *const c_char)?;
+    let pfn: PfnGetPhysicalDeviceProperties2 = mem::transmute(pfn);
+
+    let mut subgroup_properties: VkPhysicalDeviceSubgroupProperties = mem::zeroed();
+    subgroup_properties.sType = VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES;
+    let mut properties2: VkPhysicalDeviceProperties2 = mem::zeroed();
+    properties2.sType = VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2;
+    properties2.pNext = &mut subgroup_properties as *mut _ as *mut c_void;
+
+    pfn(handle, &mut properties2);
+    if subgroup_properties.subgroupSize == 0 { None } else { Some(subgroup_properties.subgroupSize) }
+}
+
+unsafe fn enumerate_device_extension_properties(instance: &Instance, handle: vk::VkPhysicalDevice)
+        -> VkcResult<Vec<vk::VkExtensionProperties>>
+{
+    let mut ext_count = 0u32;
+    ::check(instance.vk().core.vkEnumerateDeviceExtensionProperties(handle, ptr::null(),
+        &mut ext_count, ptr::null_mut()))?;
+    let mut exts = Vec::with_capacity(ext_count as usize);
+    exts.set_len(ext_count as usize);
+    ::check(instance.vk().core.vkEnumerateDeviceExtensionProperties(handle, ptr::null(),
+        &mut ext_count, exts.as_mut_ptr()))?;
+    Ok(exts)
+}
+
+/// Returns the default score for `device`: discrete GPUs are preferred over
+/// integrated, virtual and CPU devices, then devices with more device-local
+/// memory.
+pub fn default_score(device: &PhysicalDevice) -> i64 {
+    let type_rank = match device.device_type() {
+        vk::VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU => 4,
+        vk::VK_PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU => 3,
+        vk::VK_PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU => 2,
+        vk::VK_PHYSICAL_DEVICE_TYPE_CPU => 1,
+        _ => 0,
+    };
+    (type_rank << 40) + (device.device_local_memory() >> 20) as i64
+}
+
+
+struct Inner {
+    handle: vk::VkPhysicalDevice,
+    instance: Instance,
+    properties: vk::VkPhysicalDeviceProperties,
+    features: vk::VkPhysicalDeviceFeatures,
+    memory_properties: vk::VkPhysicalDeviceMemoryProperties,
+    queue_families: Vec<vk::VkQueueFamilyProperties>,
+    extensions: Vec<vk::VkExtensionProperties>,
+    subgroup_size: Option<u32>,
+}
+
+/// A physical device along with its properties, features, memory heaps,
+/// queue families and supported extensions, queried once on creation.
+#[derive(Clone)]
+pub struct PhysicalDevice {
+    inner: Arc<Inner>,
+}
+
+impl PhysicalDevice {
+    /// Queries the properties of `handle`, which must be one of
+    /// `instance.physical_devices()`.
+    pub fn new(instance: Instance, handle: vk::VkPhysicalDevice) -> VkcResult<PhysicalDevice> {
+        // Zeroed rather than left uninitialized; the queries overwrite them:
+        let mut properties: vk::VkPhysicalDeviceProperties;
+        let mut features: vk::VkPhysicalDeviceFeatures;
+        let mut memory_properties: vk::VkPhysicalDeviceMemoryProperties;
+        let extensions;
+        let subgroup_size;
+        unsafe {
+            properties = mem::zeroed();
+            features = mem::zeroed();
+            memory_properties = mem::zeroed();
+            instance.vk().core.vkGetPhysicalDeviceProperties(handle, &mut properties);
+            instance.vk().core.vkGetPhysicalDeviceFeatures(handle, &mut features);
+            instance.vk().core.vkGetPhysicalDeviceMemoryProperties(handle, &mut memory_properties);
+            extensions = enumerate_device_extension_properties(&instance, handle)?;
+            subgroup_size = query_subgroup_size(&instance, handle, &properties);
+        }
+        let queue_families = queue::queue_family_properties(&instance, handle);
+
+        Ok(PhysicalDevice {
+            inner: Arc::new(Inner {
+                handle,
+                instance,
+                properties,
+                features,
+                memory_properties,
+                queue_families,
+                extensions,
+                subgroup_size,
+            }),
+        })
+    }
+
+    /// Returns all physical devices available to `instance`, in enumeration
+    /// order.
+    pub fn enumerate(instance: &Instance) -> VkcResult<Vec<PhysicalDevice>> {
+        instance.physical_devices().iter()
+            .map(|&handle| PhysicalDevice::new(instance.clone(), handle))
+            .collect()
+    }
+
+    /// Returns the device name reported by the driver.
+    pub fn name(&self) -> String {
+        let name = unsafe { CStr::from_ptr(self.inner.properties.deviceName.as_ptr()) };
+        name.to_string_lossy().into_owned()
+    }
+
+    #[inline]
+    pub fn device_type(&self) -> vk::VkPhysicalDeviceType {
+        self.inner.properties.deviceType
+    }
+
+    #[inline]
+    pub fn api_version(&self) -> Version {
+        self.inner.properties.apiVersion.into()
+    }
+
+    #[inline]
+    pub fn properties(&self) -> &vk::VkPhysicalDeviceProperties {
+        &self.inner.properties
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &vk::VkPhysicalDeviceLimits {
+        &self.inner.properties.limits
+    }
+
+    #[inline]
+    pub fn features(&self) -> &vk::VkPhysicalDeviceFeatures {
+        &self.inner.features
+    }
+
+    #[inline]
+    pub fn memory_properties(&self) -> &vk::VkPhysicalDeviceMemoryProperties {
+        &self.inner.memory_properties
+    }
+
+    /// Returns the memory heaps of this device.
+    pub fn memory_heaps(&self) -> &[vk::VkMemoryHeap] {
+        let props = &self.inner.memory_properties;
+        &props.memoryHeaps[..props.memoryHeapCount as usize]
+    }
+
+    /// Returns the total size, in bytes, of all device-local heaps.
+    pub fn device_local_memory(&self) -> u64 {
+        self.memory_heaps().iter()
+            .filter(|heap| heap.flags & vk::VK_MEMORY_HEAP_DEVICE_LOCAL_BIT != 0)
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    #[inline]
+    pub fn queue_families(&self) -> &[vk::VkQueueFamilyProperties] {
+        &self.inner.queue_families
+    }
+
+    #[inline]
+    pub fn extensions(&self) -> &[vk::VkExtensionProperties] {
+        &self.inner.extensions
+    }
+
+    /// Returns true if the extension named `name` is supported.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        self.inner.extensions.iter().any(|ext| unsafe {
+            CStr::from_ptr(ext.extensionName.as_ptr()).to_bytes() == name.as_bytes()
+        })
+    }
+
+    /// Returns the default subgroup size, if the device supports Vulkan 1.1
+    /// and the instance targets Vulkan 1.1 or has
+    /// `VK_KHR_get_physical_device_properties2` enabled.
+    #[inline]
+    pub fn subgroup_size(&self) -> Option<u32> {
+        self.inner.subgroup_size
+    }
+
+    /// Returns a human-readable summary of this device's properties, key
+    /// limits, memory heaps, queue families and extensions.
+    pub fn report(&self) -> String {
+        let props = &self.inner.properties;
+        let limits = &props.limits;
+        let mut report = String::new();
+
+        writeln!(report, "Physical device '{}' (type: {}, API version: {}, driver version: {:#x}, \
+            vendor: {:#x}, device: {:#x})", self.name(), props.deviceType, self.api_version(),
+            props.driverVersion, props.vendorID, props.deviceID).unwrap();
+        writeln!(report, "  Subgroup size: {:?}", self.subgroup_size()).unwrap();
+        writeln!(report, "  Limits: maxImageDimension2D: {}, maxComputeSharedMemorySize: {}, \
+            maxComputeWorkGroupCount: {:?}, maxComputeWorkGroupSize: {:?}, \
+            maxComputeWorkGroupInvocations: {}, maxBoundDescriptorSets: {}, \
+            maxPushConstantsSize: {}, maxMemoryAllocationCount: {}, bufferImageGranularity: {}, \
+            nonCoherentAtomSize: {}", limits.maxImageDimension2D,
+            limits.maxComputeSharedMemorySize, limits.maxComputeWorkGroupCount,
+            limits.maxComputeWorkGroupSize, limits.maxComputeWorkGroupInvocations,
+            limits.maxBoundDescriptorSets, limits.maxPushConstantsSize,
+            limits.maxMemoryAllocationCount, limits.bufferImageGranularity,
+            limits.nonCoherentAtomSize).unwrap();
+
+        for (i, heap) in self.memory_heaps().iter().enumerate() {
+            writeln!(report, "  Memory heap {}: {} MiB (flags: {:#x})", i, heap.size >> 20,
+                heap.flags).unwrap();
+        }
+        let mem_props = &self.inner.memory_properties;
+        let mem_types = &mem_props.memoryTypes[..mem_props.memoryTypeCount as usize];
+        for (i, mem_type) in mem_types.iter().enumerate() {
+            writeln!(report, "  Memory type {}: heap {} (flags: {:#x})", i, mem_type.heapIndex,
+                mem_type.propertyFlags).unwrap();
+        }
+        for (i, family) in self.inner.queue_families.iter().enumerate() {
+            writeln!(report, "  Queue family {}: {} queue(s) (flags: {:#x})", i, family.queueCount,
+                family.queueFlags).unwrap();
+        }
+        for ext in &self.inner.extensions {
+            let name = unsafe { CStr::from_ptr(ext.extensionName.as_ptr()) };
+            writeln!(report, "  Extension: '{}' (version: {})", name.to_string_lossy(),
+                ext.specVersion).unwrap();
+        }
+        report
+    }
+
+    #[inline]
+    pub fn handle(&self) -> vk::VkPhysicalDevice {
+        self.inner.handle
+    }
+
+    #[inline]
+    pub fn instance(&self) -> &Instance {
+        &self.inner.instance
+    }
+}
+
+impl fmt::Debug for PhysicalDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PhysicalDevice")
+            .field("handle", &self.inner.handle)
+            .field("name", &self.name())
+            .field("device_type", &self.device_type())
+            .field("api_version", &self.api_version())
+            .finish()
+    }
+}
+
+
+/// Selects a physical device satisfying a set of requirements, ranking the
+/// remaining candidates by score.
+///
+/// Candidates with equal scores are ranked in enumeration order, so
+/// selection is deterministic on a given machine.
+pub struct PhysicalDeviceSelector<'s> {
+    queue_flags: vk::VkQueueFlags,
+    surface: Option<&'s Surface>,
+    extensions: Vec<String>,
+    min_device_local_memory: u64,
+    min_subgroup_size: Option<u32>,
+    requirements: Vec<Box<Fn(&PhysicalDevice) -> bool + 's>>,
+    score: Box<Fn(&PhysicalDevice) -> i64 + 's>,
+}
+
+impl<'s> PhysicalDeviceSelector<'s> {
+    /// Creates a selector requiring a queue family supporting `queue_flags`.
+    pub fn new(queue_flags: vk::VkQueueFlags) -> PhysicalDeviceSelector<'s> {
+        PhysicalDeviceSelector {
+            queue_flags,
+            surface: None,
+            extensions: Vec::new(),
+            min_device_local_memory: 0,
+            min_subgroup_size: None,
+            requirements: Vec::new(),
+            score: Box::new(default_score),
+        }
+    }
+
+    /// Requires swapchain support and the ability to present to `surface`.
+    pub fn surface(mut self, surface: &'s Surface) -> PhysicalDeviceSelector<'s> {
+        self.surface = Some(surface);
+        self
+    }
+
+    /// Requires support for the device extension named `name`.
+    pub fn extension(mut self, name: &str) -> PhysicalDeviceSelector<'s> {
+        self.extensions.push(name.to_owned());
+        self
+    }
+
+    /// Requires at least `bytes` of device-local memory.
+    pub fn min_device_local_memory(mut self, bytes: u64) -> PhysicalDeviceSelector<'s> {
+        self.min_device_local_memory = bytes;
+        self
+    }
+
+    /// Requires a subgroup size of at least `size` (implies Vulkan 1.1).
+    pub fn min_subgroup_size(mut self, size: u32) -> PhysicalDeviceSelector<'s> {
+        self.min_subgroup_size = Some(size);
+        self
+    }
+
+    /// Adds an arbitrary requirement.
+    pub fn require<F>(mut self, requirement: F) -> PhysicalDeviceSelector<'s>
+            where F: Fn(&PhysicalDevice) -> bool + 's
+    {
+        self.requirements.push(Box::new(requirement));
+        self
+    }
+
+    /// Replaces the scoring function (`default_score` by default). Higher
+    /// scores are preferred.
+    pub fn score<F>(mut self, score: F) -> PhysicalDeviceSelector<'s>
+            where F: Fn(&PhysicalDevice) -> i64 + 's
+    {
+        self.score = Box::new(score);
+        self
+    }
+
+    /// Returns the reason `device` fails to meet the requirements, if any.
+    fn unmet_requirement(&self, instance: &Instance, device: &PhysicalDevice)
+            -> VkcResult<Option<String>>
+    {
+        let families = queue::queue_families(instance, self.surface, device.handle(),
+            self.queue_flags)?;
+        if !families.is_complete() {
+            return Ok(Some(format!("no queue family supports flags {:#x}", self.queue_flags)));
+        }
+
+        if let Some(surface) = self.surface {
+            if families.presentation_support_idxs.is_empty() {
+                return Ok(Some("no queue family can present to the surface".to_owned()));
+            }
+            if !device.supports_extension(SWAPCHAIN_EXTENSION) {
+                return Ok(Some(format!("extension '{}' unsupported", SWAPCHAIN_EXTENSION)));
+            }
+            let details = SwapchainSupportDetails::new(instance, surface, device.handle())?;
+            if details.formats.is_empty() || details.present_modes.is_empty() {
+                return Ok(Some("inadequate swapchain support".to_owned()));
+            }
+        }
+
+        if let Some(ext) = self.extensions.iter().find(|ext| !device.supports_extension(ext)) {
+            return Ok(Some(format!("extension '{}' unsupported", ext)));
+        }
+
+        if device.device_local_memory() < self.min_device_local_memory {
+            return Ok(Some(format!("{} bytes of device-local memory (need {})",
+                device.device_local_memory(), self.min_device_local_memory)));
+        }
+
+        if let Some(min_size) = self.min_subgroup_size {
+            match device.subgroup_size() {
+                Some(size) if size >= min_size => (),
+                size => return Ok(Some(format!("subgroup size {:?} (need at least {})",
+                    size, min_size))),
+            }
+        }
+
+        if !self.requirements.iter().all(|req| req(device)) {
+            return Ok(Some("rejected by a custom requirement".to_owned()));
+        }
+
+        Ok(None)
+    }
+
+    /// Returns all devices meeting the requirements, best first.
+    pub fn candidates(&self, instance: &Instance) -> VkcResult<Vec<PhysicalDevice>> {
+        let mut candidates = Vec::new();
+        for device in PhysicalDevice::enumerate(instance)? {
+            if self.unmet_requirement(instance, &device)?.is_none() {
+                candidates.push(device);
+            }
+        }
+        // Stable, so equal scores keep their enumeration order:
+        candidates.sort_by_key(|device| Reverse((self.score)(device)));
+        Ok(candidates)
+    }
+
+    /// Returns the highest scoring device meeting the requirements or an
+    /// error listing why each device was rejected.
+    pub fn select(&self, instance: &Instance) -> VkcResult<PhysicalDevice> {
+        let mut best: Option<(i64, PhysicalDevice)> = None;
+        let mut rejections = Vec::new();
+
+        for device in PhysicalDevice::enumerate(instance)? {
+            match self.unmet_requirement(instance, &device)? {
+                Some(reason) => rejections.push(format!("'{}': {}", device.name(), reason)),
+                None => {
+                    let score = (self.score)(&device);
+                    if best.as_ref().map(|&(best_score, _)| score > best_score).unwrap_or(true) {
+                        best = Some((score, device));
+                    }
+                },
+            }
+        }
+
+        best.map(|(_, device)| device).ok_or_else(|| format!("Failed to find a suitable device \
+            ({}).", rejections.join("; ")).into())
+    }
+}
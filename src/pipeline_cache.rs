@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use std::ptr;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use libc::c_void;
+use smallvec::SmallVec;
+use vk;
+use ::{util, VkcResult, Device, PhysicalDevice};
+
+
+/// The size of a `VK_PIPELINE_CACHE_HEADER_VERSION_ONE` header in bytes.
+const HEADER_SIZE: usize = 32;
+
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    // Header fields are always written least significant byte first.
+    data[offset] as u32 |
+        (data[offset + 1] as u32) << 8 |
+        (data[offset + 2] as u32) << 16 |
+        (data[offset + 3] as u32) << 24
+}
+
+/// Checks that the header of serialized cache `data` was written by a
+/// device with the given vendor and device IDs and cache UUID, returning a
+/// description of the first mismatch otherwise.
+fn check_header(data: &[u8], vendor_id: u32, device_id: u32, uuid: &[u8])
+        -> Result<(), &'static str>
+{
+    if data.len() < HEADER_SIZE {
+        return Err("data is too short to contain a header");
+    }
+    let header_size = read_u32(data, 0) as usize;
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err("header length is invalid");
+    }
+    if read_u32(data, 4) != vk::VK_PIPELINE_CACHE_HEADER_VERSION_ONE as u32 {
+        return Err("header version is unsupported");
+    }
+    if read_u32(data, 8) != vendor_id {
+        return Err("vendor ID does not match");
+    }
+    if read_u32(data, 12) != device_id {
+        return Err("device ID does not match");
+    }
+    if &data[16..HEADER_SIZE] != uuid {
+        return Err("pipeline cache UUID does not match");
+    }
+    Ok(())
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    handle: vk::VkPipelineCache,
+    device: Device,
+}
+
+/// A cache of compiled pipeline state which can be passed to pipeline
+/// constructors and persisted between runs to reduce startup time.
+#[derive(Debug, Clone)]
+pub struct PipelineCache {
+    inner: Arc<Inner>,
+}
+
+impl PipelineCache {
+    /// Creates an empty cache.
+    pub fn new(device: Device) -> VkcResult<PipelineCache> {
+        unsafe { PipelineCache::from_raw_data(device, &[]) }
+    }
+
+    /// Creates a cache seeded with `data` previously returned by `data`.
+    ///
+    /// If `data` was written by a different device or driver it is
+    /// discarded and the cache starts out empty.
+    pub fn with_data(device: Device, data: &[u8]) -> VkcResult<PipelineCache> {
+        let data = match PipelineCache::validate(device.physical_device(), data) {
+            Ok(()) => data,
+            Err(reason) => {
+                warn!("Discarding pipeline cache data: {}.", reason);
+                &[]
+            },
+        };
+        unsafe { PipelineCache::from_raw_data(device, data) }
+    }
+
+    /// Creates a cache seeded with the contents of the file at `path`.
+    ///
+    /// The cache starts out empty if the file does not exist or holds data
+    /// written by a different device or driver.
+    pub fn load<P: AsRef<Path>>(device: Device, path: P) -> VkcResult<PipelineCache> {
+        let path = path.as_ref();
+        if !path.exists() {
+            debug!("No pipeline cache found at {}.", path.display());
+            return PipelineCache::new(device);
+        }
+        let data = util::read_file(path)?;
+        PipelineCache::with_data(device, &data)
+    }
+
+    /// Creates a cache from `data` without checking its header.
+    ///
+    /// Implementations are required to reject incompatible data but some
+    /// are known to crash instead.
+    pub unsafe fn from_raw_data(device: Device, data: &[u8]) -> VkcResult<PipelineCache> {
+        let create_info = vk::VkPipelineCacheCreateInfo {
+            sType: vk::VK_STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            initialDataSize: data.len(),
+            pInitialData: data.as_ptr() as *const c_void,
+        };
+
+        let mut handle = 0;
+        ::check(device.vk().core.vkCreatePipelineCache(device.handle(), &create_info,
+            ptr::null(), &mut handle))?;
+
+        Ok(PipelineCache {
+            inner: Arc::new(Inner {
+                handle,
+                device,
+            })
+        })
+    }
+
+    /// Checks that serialized cache `data` was written by
+    /// `physical_device` with its current driver.
+    pub fn validate(physical_device: &PhysicalDevice, data: &[u8]) -> Result<(), &'static str> {
+        let properties = physical_device.properties();
+        check_header(data, properties.vendorID, properties.deviceID,
+            &properties.pipelineCacheUUID)
+    }
+
+    /// Returns the serialized contents of the cache.
+    pub fn data(&self) -> VkcResult<Vec<u8>> {
+        let mut data = Vec::new();
+        loop {
+            let mut size = 0;
+            unsafe {
+                ::check(self.device().vk().core.vkGetPipelineCacheData(self.device().handle(),
+                    self.handle(), &mut size, ptr::null_mut()))?;
+            }
+            data.resize(size, 0);
+
+            let result = unsafe {
+                ::check(self.device().vk().core.vkGetPipelineCacheData(self.device().handle(),
+                    self.handle(), &mut size, data.as_mut_ptr() as *mut c_void))?
+            };
+            // The cache may have grown between the two calls.
+            if result != vk::VK_INCOMPLETE {
+                data.truncate(size);
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Writes the serialized contents of the cache to the file at `path`.
+    ///
+    /// The data is written to a temporary file first, which then replaces
+    /// `path`, so an interrupted save never leaves a truncated cache behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> VkcResult<()> {
+        let path = path.as_ref();
+        let data = self.data()?;
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        debug!("Wrote {} bytes of pipeline cache to {}.", data.len(), path.display());
+        Ok(())
+    }
+
+    /// Merges the contents of each of `sources` into this cache.
+    pub fn merge(&self, sources: &[&PipelineCache]) -> VkcResult<()> {
+        if sources.iter().any(|src| src.handle() == self.handle()) {
+            return Err("PipelineCache::merge: A cache can not be merged into itself.".into());
+        }
+        let handles: SmallVec<[vk::VkPipelineCache; 8]> = sources.iter()
+            .map(|src| src.handle())
+            .collect();
+
+        unsafe {
+            ::check(self.device().vk().core.vkMergePipelineCaches(self.device().handle(),
+                self.handle(), handles.len() as u32, handles.as_ptr()))?;
+        }
+        Ok(())
+    }
+
+    pub fn handle(&self) -> vk::VkPipelineCache {
+        self.inner.handle
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.inner.device
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.vk().core.vkDestroyPipelineCache(self.device.handle(), self.handle,
+                ptr::null());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{check_header, HEADER_SIZE};
+
+    const UUID: [u8; 16] = [7; 16];
+
+    fn header(vendor_id: u32, device_id: u32, uuid: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &word in &[HEADER_SIZE as u32, 1, vendor_id, device_id] {
+            data.extend_from_slice(&[word as u8, (word >> 8) as u8, (word >> 16) as u8,
+                (word >> 24) as u8]);
+        }
+        data.extend_from_slice(uuid);
+        data
+    }
+
+    #[test]
+    fn matching_header() {
+        let mut data = header(0x10de, 0x1234, &UUID);
+        assert_eq!(check_header(&data, 0x10de, 0x1234, &UUID), Ok(()));
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(check_header(&data, 0x10de, 0x1234, &UUID), Ok(()));
+    }
+
+    #[test]
+    fn stale_header() {
+        let data = header(0x10de, 0x1234, &UUID);
+        assert!(check_header(&data, 0x1002, 0x1234, &UUID).is_err());
+        assert!(check_header(&data, 0x10de, 0x4321, &UUID).is_err());
+        assert!(check_header(&data, 0x10de, 0x1234, &[8; 16]).is_err());
+        assert!(check_header(&data[..HEADER_SIZE - 1], 0x10de, 0x1234, &UUID).is_err());
+        assert!(check_header(&[], 0x10de, 0x1234, &UUID).is_err());
+
+        let mut bad_version = data.clone();
+        bad_version[4] = 2;
+        assert!(check_header(&bad_version, 0x10de, 0x1234, &UUID).is_err());
+    }
+}
@@ -1,15 +1,19 @@
 use std::sync::Arc;
 use std::ffi::CStr;
 use std::ptr;
+use std::collections::BTreeMap;
 use vk;
 use vks;
 use smallvec::SmallVec;
-use ::{util, VkcResult, Device, ShaderModule, DescriptorSetLayout};
+use spirv::{DescriptorBindingInfo, PushConstantRangeInfo};
+use ::{util, VkcResult, Device, ShaderModule, DescriptorSetLayout, DescriptorSetLayoutBuilder};
 
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkPipelineLayout,
+    set_layouts: Vec<DescriptorSetLayout>,
+    push_constant_ranges: Vec<PushConstantRangeInfo>,
     device: Device,
 }
 
@@ -22,35 +26,103 @@ impl PipelineLayout {
     pub fn new(device: Device, descriptor_set_layout: Option<&DescriptorSetLayout>)
             -> VkcResult<PipelineLayout>
     {
-        let mut layouts = SmallVec::<[_; 16]>::new();
-        if let Some(dsl) = descriptor_set_layout {
-            layouts.push(dsl.handle());
+        PipelineLayout::with_layouts(device, descriptor_set_layout.into_iter().cloned().collect(),
+            Vec::new())
+    }
+
+    /// Creates a pipeline layout covering every descriptor binding and push
+    /// constant range used by `shader_modules`, as found by reflection.
+    ///
+    /// Bindings declared by more than one module must agree on descriptor
+    /// type and are made visible to the stages of each. A descriptor set
+    /// layout is created for each set number up to the highest one used.
+    pub fn from_modules(device: Device, shader_modules: &[&ShaderModule])
+            -> VkcResult<PipelineLayout>
+    {
+        let mut bindings: BTreeMap<(u32, u32), DescriptorBindingInfo> = BTreeMap::new();
+        let mut push_constant_ranges: Vec<PushConstantRangeInfo> = Vec::new();
+
+        for module in shader_modules {
+            let reflection = module.reflection().ok_or("PipelineLayout::from_modules: Shader \
+                module has no reflection data.")?;
+
+            for binding in &reflection.descriptor_bindings {
+                if binding.count == 0 {
+                    return Err(format!("PipelineLayout::from_modules: Binding {} of set {} ('{}') \
+                        is a runtime-sized array, which is unsupported.", binding.binding,
+                        binding.set, binding.name).into());
+                }
+                let merged = bindings.entry((binding.set, binding.binding))
+                    .or_insert_with(|| binding.clone());
+                if merged.descriptor_type != binding.descriptor_type {
+                    return Err(format!("PipelineLayout::from_modules: Binding {} of set {} is \
+                        declared with conflicting descriptor types ({} and {}).", binding.binding,
+                        binding.set, merged.descriptor_type, binding.descriptor_type).into());
+                }
+                merged.stage_flags |= binding.stage_flags;
+                merged.count = merged.count.max(binding.count);
+            }
+
+            for range in &reflection.push_constant_ranges {
+                merge_push_constant_range(&mut push_constant_ranges, *range);
+            }
+        }
+
+        let set_count = bindings.keys().map(|&(set, _)| set + 1).max().unwrap_or(0);
+        let mut set_layouts = Vec::with_capacity(set_count as usize);
+        for set in 0..set_count {
+            let mut builder = DescriptorSetLayoutBuilder::new(device.clone());
+            for (_, binding) in bindings.range((set, 0)..(set + 1, 0)) {
+                builder = builder.binding(binding.binding, binding.descriptor_type, binding.count,
+                    binding.stage_flags);
+            }
+            set_layouts.push(builder.build()?);
         }
 
+        PipelineLayout::with_layouts(device, set_layouts, push_constant_ranges)
+    }
+
+    fn with_layouts(device: Device, set_layouts: Vec<DescriptorSetLayout>,
+            push_constant_ranges: Vec<PushConstantRangeInfo>) -> VkcResult<PipelineLayout>
+    {
+        let layouts: SmallVec<[_; 16]> = set_layouts.iter().map(|dsl| dsl.handle()).collect();
+        let ranges: SmallVec<[_; 4]> = push_constant_ranges.iter().map(|r| r.raw()).collect();
+
         let pipeline_layout_info = vk::VkPipelineLayoutCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
             setLayoutCount: layouts.len() as u32,
             pSetLayouts: layouts.as_ptr(),
-            pushConstantRangeCount: 0,
-            pPushConstantRanges: ptr::null(),
+            pushConstantRangeCount: ranges.len() as u32,
+            pPushConstantRanges: ranges.as_ptr(),
         };
 
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreatePipelineLayout(device.handle(),
-                &pipeline_layout_info, ptr::null(), &mut handle));
+                &pipeline_layout_info, ptr::null(), &mut handle))?;
         }
 
         Ok(PipelineLayout {
             inner: Arc::new(Inner {
                 handle,
+                set_layouts,
+                push_constant_ranges,
                 device,
             })
         })
     }
 
+    /// Returns the descriptor set layouts, indexed by set number.
+    pub fn set_layouts(&self) -> &[DescriptorSetLayout] {
+        &self.inner.set_layouts
+    }
+
+    pub fn push_constant_ranges(&self) -> &[PushConstantRangeInfo] {
+        &self.inner.push_constant_ranges
+    }
+
     pub fn handle(&self) -> vk::VkPipelineLayout {
         self.inner.handle
     }
@@ -61,10 +133,73 @@ impl PipelineLayout {
 }
 
 
+/// Adds `range` to `ranges`, which must not share stages with one another.
+///
+/// Each stage may appear in only one range, so every range sharing a stage
+/// with `range` (or covering the same bytes) is folded into it, repeating
+/// until none is left.
+fn merge_push_constant_range(ranges: &mut Vec<PushConstantRangeInfo>,
+        mut range: PushConstantRangeInfo)
+{
+    while let Some(pos) = ranges.iter().position(|r| r.stage_flags & range.stage_flags != 0 ||
+            (r.offset == range.offset && r.size == range.size))
+    {
+        let other = ranges.swap_remove(pos);
+        let end = (other.offset + other.size).max(range.offset + range.size);
+        range.offset = other.offset.min(range.offset);
+        range.size = end - range.offset;
+        range.stage_flags |= other.stage_flags;
+    }
+    ranges.push(range);
+}
+
+
 impl Drop for Inner {
     fn drop(&mut self) {
         unsafe {
             self.device.vk().core.vkDestroyPipelineLayout(self.device.handle(), self.handle, ptr::null());
         }
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use vk;
+    use spirv::PushConstantRangeInfo;
+    use super::merge_push_constant_range;
+
+    fn range(offset: u32, size: u32, stage_flags: vk::VkShaderStageFlags)
+            -> PushConstantRangeInfo
+    {
+        PushConstantRangeInfo { offset, size, stage_flags }
+    }
+
+    #[test]
+    fn push_constant_ranges() {
+        let vert = vk::VK_SHADER_STAGE_VERTEX_BIT;
+        let frag = vk::VK_SHADER_STAGE_FRAGMENT_BIT;
+        let geom = vk::VK_SHADER_STAGE_GEOMETRY_BIT;
+
+        // Identical ranges share stage flags:
+        let mut ranges = Vec::new();
+        merge_push_constant_range(&mut ranges, range(0, 16, vert));
+        merge_push_constant_range(&mut ranges, range(0, 16, frag));
+        assert_eq!(ranges, vec![range(0, 16, vert | frag)]);
+
+        // A range spanning stages in two existing ranges joins both of them:
+        let mut ranges = Vec::new();
+        merge_push_constant_range(&mut ranges, range(0, 16, vert));
+        merge_push_constant_range(&mut ranges, range(32, 16, frag));
+        assert_eq!(ranges.len(), 2);
+        merge_push_constant_range(&mut ranges, range(16, 8, vert | frag));
+        assert_eq!(ranges, vec![range(0, 48, vert | frag)]);
+
+        // Stages without overlap stay separate:
+        merge_push_constant_range(&mut ranges, range(64, 4, geom));
+        assert_eq!(ranges, vec![range(0, 48, vert | frag), range(64, 4, geom)]);
+        for stage in &[vert, frag, geom] {
+            assert_eq!(ranges.iter().filter(|r| r.stage_flags & stage != 0).count(), 1);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::ptr;
 use smallvec::SmallVec;
 use vk;
 use vks;
-use ::{VkcResult, Instance, Device, Surface};
+use ::{VkcResult, Instance, Device, Surface, Semaphore, Fence, Swapchain, CommandBuffer};
 
 pub struct QueueFamilyIndices {
     // family_idx: i32,
@@ -27,6 +27,13 @@ impl QueueFamilyIndices {
         self.flag_idxs.len() > 0
     }
 
+    /// Returns the index of the first queue family supporting the requested
+    /// flags.
+    pub fn first_family_idx(&self) -> VkcResult<u32> {
+        self.flag_idxs.first().map(|&idx| idx as u32)
+            .ok_or_else(|| "No queue family supports the requested queue flags.".into())
+    }
+
     pub fn family_idxs(&self) -> &[i32] {
         &self.flag_idxs
 
@@ -44,10 +51,10 @@ impl QueueFamilyIndices {
     }
 }
 
-pub fn queue_families(instance: &Instance, surface: &Surface, device: vk::VkPhysicalDevice,
-        queue_flags: vk::VkQueueFlags) -> QueueFamilyIndices
+/// Returns the properties of each queue family of `device`.
+pub fn queue_family_properties(instance: &Instance, device: vk::VkPhysicalDevice)
+        -> Vec<vk::VkQueueFamilyProperties>
 {
-    let mut indices = QueueFamilyIndices::new(device, queue_flags);
     let mut queue_family_count = 0u32;
     let mut queue_families: Vec<vk::VkQueueFamilyProperties>;
 
@@ -57,6 +64,32 @@ pub fn queue_families(instance: &Instance, surface: &Surface, device: vk::VkPhys
         queue_families.set_len(queue_family_count as usize);
         instance.vk().core.vkGetPhysicalDeviceQueueFamilyProperties(device, &mut queue_family_count, queue_families.as_mut_ptr());
     }
+    queue_families
+}
+
+/// Returns the index of the queue family supporting `queue_flags` with the
+/// fewest other graphics, compute or transfer capabilities, preferring
+/// families dedicated to the requested work (e.g. an async-compute or
+/// transfer-only family).
+pub fn dedicated_queue_family(queue_families: &[vk::VkQueueFamilyProperties],
+        queue_flags: vk::VkQueueFlags) -> Option<u32>
+{
+    let capability_flags = vk::VK_QUEUE_GRAPHICS_BIT | vk::VK_QUEUE_COMPUTE_BIT |
+        vk::VK_QUEUE_TRANSFER_BIT;
+
+    queue_families.iter().enumerate()
+        .filter(|&(_, family)| family.queueCount > 0 && family.queueFlags & queue_flags == queue_flags)
+        .min_by_key(|&(_, family)| (family.queueFlags & capability_flags & !queue_flags).count_ones())
+        .map(|(idx, _)| idx as u32)
+}
+
+/// Returns the indices of queue families supporting `queue_flags` and, if a
+/// surface is specified, those which can present to it.
+pub fn queue_families(instance: &Instance, surface: Option<&Surface>, device: vk::VkPhysicalDevice,
+        queue_flags: vk::VkQueueFlags) -> VkcResult<QueueFamilyIndices>
+{
+    let mut indices = QueueFamilyIndices::new(device, queue_flags);
+    let queue_families = queue_family_properties(instance, device);
 
     let mut i = 0i32;
     for queue_family in &queue_families {
@@ -64,13 +97,15 @@ pub fn queue_families(instance: &Instance, surface: &Surface, device: vk::VkPhys
             indices.flag_idxs.push(i);
         }
 
-        let mut presentation_support: vk::VkBool32 = vk::VK_FALSE;
-        unsafe {
-            ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceSupportKHR(device, i as u32, surface.handle(),
-                &mut presentation_support));
-        }
-        if queue_family.queueCount > 0 && presentation_support != 0 {
-            indices.presentation_support_idxs.push(i);
+        if let Some(surface) = surface {
+            let mut presentation_support: vk::VkBool32 = vk::VK_FALSE;
+            unsafe {
+                ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceSupportKHR(device, i as u32, surface.handle(),
+                    &mut presentation_support))?;
+            }
+            if queue_family.queueCount > 0 && presentation_support != 0 {
+                indices.presentation_support_idxs.push(i);
+            }
         }
 
         if indices.is_complete() {
@@ -78,10 +113,83 @@ pub fn queue_families(instance: &Instance, surface: &Surface, device: vk::VkPhys
         }
         i += 1;
     }
-    indices
+    Ok(indices)
 }
 
 
+/// The purpose a device queue was requested for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueRole {
+    /// General purpose queues, from the first family supporting the queue
+    /// flags passed to the device (and used by `Device::queue`).
+    Primary,
+    /// Asynchronous compute queues, from a dedicated compute family if one
+    /// exists.
+    Compute,
+    /// Upload/download queues, from a transfer-only family if one exists.
+    Transfer,
+}
+
+
+/// A batch of command buffers to be submitted to a queue, along with the
+/// semaphores to wait on before and signal after their execution.
+///
+/// The referenced objects must remain alive until the submission completes.
+#[derive(Debug, Clone, Default)]
+pub struct SubmitInfo {
+    wait_semaphores: SmallVec<[vk::VkSemaphore; 4]>,
+    wait_stages: SmallVec<[vk::VkPipelineStageFlags; 4]>,
+    command_buffers: SmallVec<[vk::VkCommandBuffer; 4]>,
+    signal_semaphores: SmallVec<[vk::VkSemaphore; 4]>,
+}
+
+impl SubmitInfo {
+    pub fn new() -> SubmitInfo {
+        SubmitInfo::default()
+    }
+
+    /// Waits on `semaphore` before executing the `stage` stages of the
+    /// command buffers in this batch.
+    pub fn wait(mut self, semaphore: &Semaphore, stage: vk::VkPipelineStageFlags) -> SubmitInfo {
+        self.wait_semaphores.push(semaphore.handle());
+        self.wait_stages.push(stage);
+        self
+    }
+
+    pub fn command_buffer(mut self, command_buffer: &CommandBuffer) -> SubmitInfo {
+        self.command_buffers.push(command_buffer.handle());
+        self
+    }
+
+    /// Signals `semaphore` once all command buffers in this batch have
+    /// completed.
+    pub fn signal(mut self, semaphore: &Semaphore) -> SubmitInfo {
+        self.signal_semaphores.push(semaphore.handle());
+        self
+    }
+
+    fn raw(&self) -> vk::VkSubmitInfo {
+        vk::VkSubmitInfo {
+            sType: vk::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            pNext: ptr::null(),
+            waitSemaphoreCount: self.wait_semaphores.len() as u32,
+            pWaitSemaphores: self.wait_semaphores.as_ptr(),
+            pWaitDstStageMask: self.wait_stages.as_ptr(),
+            commandBufferCount: self.command_buffers.len() as u32,
+            pCommandBuffers: self.command_buffers.as_ptr(),
+            signalSemaphoreCount: self.signal_semaphores.len() as u32,
+            pSignalSemaphores: self.signal_semaphores.as_ptr(),
+        }
+    }
+}
+
+
+/// A device queue.
+///
+/// Submitting, presenting and waiting are serialized with any other `Queue`
+/// referring to the same queue, as is the case for aliased queues (see
+/// `DeviceBuilder`).
+#[derive(Debug, Clone)]
 pub struct Queue {
     handle: vk::VkQueue,
     device: Device,
@@ -96,15 +204,86 @@ impl Queue {
     // QUEUE_GRAPHICS_BIT
     // QUEUE_SPARSE_BINDING_BIT
     // QUEUE_TRANSFER_BIT
-    pub unsafe fn new(device: Device, queue_family_index: u32, queue_index: u32) -> VkcResult<Queue> {
+    pub unsafe fn new(device: Device, queue_family_index: u32, queue_index: u32) -> Queue {
         let mut handle = ptr::null_mut();
         device.vk().core.vkGetDeviceQueue(device.handle(), queue_family_index, queue_index, &mut handle);
 
-        Ok(Queue {
+        Queue {
             handle,
             device,
             family_idx: queue_family_index,
             idx: queue_index,
-        })
+        }
     }
-}
\ No newline at end of file
+
+    /// Submits batches of command buffers for execution, signaling `fence`
+    /// (if specified) once all of them have completed.
+    pub fn submit(&self, submits: &[SubmitInfo], fence: Option<&Fence>) -> VkcResult<()> {
+        let raw_submits: SmallVec<[vk::VkSubmitInfo; 8]> = submits.iter().map(|s| s.raw()).collect();
+        let _lock = self.device.lock_queue(self.family_idx, self.idx);
+        unsafe {
+            ::check(self.device.vk().core.vkQueueSubmit(self.handle, raw_submits.len() as u32,
+                raw_submits.as_ptr(), fence.map(|f| f.handle()).unwrap_or(0)))?;
+        }
+        Ok(())
+    }
+
+    /// Blocks until all work submitted to this queue has completed.
+    pub fn wait_idle(&self) -> VkcResult<()> {
+        let _lock = self.device.lock_queue(self.family_idx, self.idx);
+        unsafe { ::check(self.device.vk().core.vkQueueWaitIdle(self.handle))?; }
+        Ok(())
+    }
+
+    /// Queues the swapchain image at `image_index` for presentation after
+    /// waiting on `wait_semaphores`.
+    ///
+    /// Returns `true` if the swapchain no longer matches the surface exactly
+    /// (`VK_SUBOPTIMAL_KHR`) but was still presented to.
+    pub fn present(&self, swapchain: &Swapchain, image_index: u32, wait_semaphores: &[&Semaphore])
+            -> VkcResult<bool>
+    {
+        let wait_semaphores: SmallVec<[vk::VkSemaphore; 4]> = wait_semaphores.iter()
+            .map(|s| s.handle()).collect();
+        let swapchain_handle = swapchain.handle();
+
+        let present_info = vk::VkPresentInfoKHR {
+            sType: vk::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
+            pNext: ptr::null(),
+            waitSemaphoreCount: wait_semaphores.len() as u32,
+            pWaitSemaphores: wait_semaphores.as_ptr(),
+            swapchainCount: 1,
+            pSwapchains: &swapchain_handle,
+            pImageIndices: &image_index,
+            pResults: ptr::null_mut(),
+        };
+
+        let _lock = self.device.lock_queue(self.family_idx, self.idx);
+        let res = unsafe {
+            ::check(self.device.vk().khr_swapchain.vkQueuePresentKHR(self.handle, &present_info))?
+        };
+        Ok(res == vk::VK_SUBOPTIMAL_KHR)
+    }
+
+    #[inline]
+    pub fn handle(&self) -> vk::VkQueue {
+        self.handle
+    }
+
+    #[inline]
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the index of the queue family this queue belongs to.
+    #[inline]
+    pub fn family_idx(&self) -> u32 {
+        self.family_idx
+    }
+
+    /// Returns the index of this queue within its family.
+    #[inline]
+    pub fn idx(&self) -> u32 {
+        self.idx
+    }
+}
@@ -18,19 +18,34 @@ pub struct RenderPass {
 }
 
 impl RenderPass {
+    /// Creates a render pass with a color attachment ready for presentation
+    /// once the pass ends.
     pub fn new(device: Device, swapchain_image_format: vk::VkFormat,
             depth_image_format: vk::VkFormat) -> VkcResult<RenderPass>
+    {
+        RenderPass::with_final_layout(device, swapchain_image_format, depth_image_format,
+            vk::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR)
+    }
+
+    /// Creates a render pass with a color attachment transitioned to
+    /// `color_final_layout` once the pass ends.
+    ///
+    /// With `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`, color attachment writes
+    /// are made available to transfer reads recorded after the pass.
+    pub fn with_final_layout(device: Device, color_format: vk::VkFormat,
+            depth_image_format: vk::VkFormat, color_final_layout: vk::VkImageLayout)
+            -> VkcResult<RenderPass>
     {
         let color_attachment = vk::VkAttachmentDescription {
             flags: 0,
-            format: swapchain_image_format,
+            format: color_format,
             samples: vk::VK_SAMPLE_COUNT_1_BIT,
             loadOp: vk::VK_ATTACHMENT_LOAD_OP_CLEAR,
             storeOp: vk::VK_ATTACHMENT_STORE_OP_STORE,
             stencilLoadOp: vk::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
             stencilStoreOp: vk::VK_ATTACHMENT_STORE_OP_DONT_CARE,
             initialLayout: vk::VK_IMAGE_LAYOUT_UNDEFINED,
-            finalLayout: vk::VK_IMAGE_LAYOUT_PRESENT_SRC_KHR,
+            finalLayout: color_final_layout,
         };
 
         let depth_attachment = vk::VkAttachmentDescription {
@@ -68,7 +83,7 @@ impl RenderPass {
             pPreserveAttachments: ptr::null(),
         };
 
-        let dependency = vk::VkSubpassDependency {
+        let mut dependencies = vec![vk::VkSubpassDependency {
             dependencyFlags: 0,
             srcSubpass: vk::VK_SUBPASS_EXTERNAL,
             dstSubpass: 0,
@@ -76,7 +91,19 @@ impl RenderPass {
             srcAccessMask: 0,
             dstStageMask: vk::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
             dstAccessMask: vk::VK_ACCESS_COLOR_ATTACHMENT_READ_BIT | vk::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
-        };
+        }];
+
+        if color_final_layout == vk::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL {
+            dependencies.push(vk::VkSubpassDependency {
+                dependencyFlags: 0,
+                srcSubpass: 0,
+                dstSubpass: vk::VK_SUBPASS_EXTERNAL,
+                srcStageMask: vk::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+                srcAccessMask: vk::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+                dstStageMask: vk::VK_PIPELINE_STAGE_TRANSFER_BIT,
+                dstAccessMask: vk::VK_ACCESS_TRANSFER_READ_BIT,
+            });
+        }
 
         let attachments = [color_attachment, depth_attachment];
 
@@ -88,13 +115,13 @@ impl RenderPass {
             pAttachments: attachments.as_ptr(),
             subpassCount: 1,
             pSubpasses: &subpass,
-            dependencyCount: 1,
-            pDependencies: &dependency,
+            dependencyCount: dependencies.len() as u32,
+            pDependencies: dependencies.as_ptr(),
         };
 
         let mut handle = 0;
         unsafe {
-            ::check(device.vk().core.vkCreateRenderPass(device.handle(), &create_info, ptr::null(), &mut handle));
+            ::check(device.vk().core.vkCreateRenderPass(device.handle(), &create_info, ptr::null(), &mut handle))?;
         }
 
         Ok(RenderPass {
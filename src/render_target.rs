@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use image_crate::RgbaImage;
+use vk;
+use ::{VkcResult, Device, Image, ImageView, RenderPass, Framebuffer, Buffer, CommandPool,
+    CommandBuffer, BufferBarrier, Queue, Fence, SubmitInfo};
+
+
+/// Returns true if pixels of `format` are stored as BGRA rather than RGBA,
+/// or an error if `format` can not be read back as 8-bit RGBA.
+fn is_bgra(format: vk::VkFormat) -> VkcResult<bool> {
+    match format {
+        vk::VK_FORMAT_R8G8B8A8_UNORM | vk::VK_FORMAT_R8G8B8A8_SRGB => Ok(false),
+        vk::VK_FORMAT_B8G8R8A8_UNORM | vk::VK_FORMAT_B8G8R8A8_SRGB => Ok(true),
+        _ => Err(format!("Unable to read back render target format {} as RGBA.", format).into()),
+    }
+}
+
+
+#[derive(Debug)]
+struct Inner {
+    color_image: Image,
+    color_image_view: ImageView,
+    depth_image: Image,
+    depth_image_view: ImageView,
+    render_pass: RenderPass,
+    framebuffer: Framebuffer,
+    extent: vk::VkExtent2D,
+    color_format: vk::VkFormat,
+    device: Device,
+}
+
+/// An offscreen color and depth target which can be read back to host
+/// memory, for rendering without a swapchain.
+///
+/// Its render pass leaves the color image in
+/// `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`, ready to be copied.
+#[derive(Debug, Clone)]
+pub struct RenderTarget {
+    inner: Arc<Inner>,
+}
+
+impl RenderTarget {
+    pub fn new(device: Device, extent: vk::VkExtent2D, color_format: vk::VkFormat,
+            depth_format: vk::VkFormat) -> VkcResult<RenderTarget>
+    {
+        let image_extent = vk::VkExtent3D { width: extent.width, height: extent.height, depth: 1 };
+
+        let color_image = Image::new(device.clone(), image_extent, color_format,
+            vk::VK_IMAGE_TILING_OPTIMAL,
+            vk::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT | vk::VK_IMAGE_USAGE_TRANSFER_SRC_BIT,
+            vk::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let color_image_view = ImageView::new(device.clone(), None, color_image.handle(),
+            color_format, vk::VK_IMAGE_ASPECT_COLOR_BIT)?;
+
+        let depth_image = Image::new(device.clone(), image_extent, depth_format,
+            vk::VK_IMAGE_TILING_OPTIMAL, vk::VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            vk::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let depth_image_view = ImageView::new(device.clone(), None, depth_image.handle(),
+            depth_format, vk::VK_IMAGE_ASPECT_DEPTH_BIT)?;
+
+        let render_pass = RenderPass::with_final_layout(device.clone(), color_format, depth_format,
+            vk::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL)?;
+        let framebuffer = Framebuffer::new(device.clone(), render_pass.clone(),
+            color_image_view.clone(), depth_image_view.clone(), extent.clone())?;
+
+        Ok(RenderTarget {
+            inner: Arc::new(Inner {
+                color_image,
+                color_image_view,
+                depth_image,
+                depth_image_view,
+                render_pass,
+                framebuffer,
+                extent,
+                color_format,
+                device,
+            })
+        })
+    }
+
+    /// Returns the size, in bytes, of a tightly packed copy of the color
+    /// image (assuming 4 bytes per pixel).
+    pub fn readback_size(&self) -> u64 {
+        self.inner.extent.width as u64 * self.inner.extent.height as u64 * 4
+    }
+
+    /// Records a copy of the color image into `buffer`, which must have been
+    /// created with `VK_BUFFER_USAGE_TRANSFER_DST_BIT` and be at least
+    /// `readback_size()` bytes, followed by a barrier making the copy
+    /// visible to the host.
+    ///
+    /// Must be recorded after a pass using `render_pass()` has ended.
+    pub fn record_readback(&self, command_buffer: &mut CommandBuffer, buffer: &Buffer) {
+        let region = vk::VkBufferImageCopy {
+            bufferOffset: 0,
+            bufferRowLength: 0,
+            bufferImageHeight: 0,
+            imageSubresource: vk::VkImageSubresourceLayers {
+                aspectMask: vk::VK_IMAGE_ASPECT_COLOR_BIT,
+                mipLevel: 0,
+                baseArrayLayer: 0,
+                layerCount: 1,
+            },
+            imageOffset: vk::VkOffset3D { x: 0, y: 0, z: 0 },
+            imageExtent: vk::VkExtent3D {
+                width: self.inner.extent.width,
+                height: self.inner.extent.height,
+                depth: 1,
+            },
+        };
+        command_buffer.copy_image_to_buffer(&self.inner.color_image,
+            vk::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL, buffer, &[region]);
+
+        let barrier = BufferBarrier::new(buffer)
+            .access(vk::VK_ACCESS_TRANSFER_WRITE_BIT, vk::VK_ACCESS_HOST_READ_BIT);
+        command_buffer.pipeline_barrier(vk::VK_PIPELINE_STAGE_TRANSFER_BIT,
+            vk::VK_PIPELINE_STAGE_HOST_BIT, 0, &[], &[barrier], &[]);
+    }
+
+    /// Copies the color image to host memory using `queue` and blocks until
+    /// the copy completes.
+    ///
+    /// All rendering to this target must have been submitted (to `queue` or
+    /// synchronized with it) beforehand.
+    pub fn read_rgba(&self, queue: &Queue) -> VkcResult<RgbaImage> {
+        let bgra = is_bgra(self.inner.color_format)?;
+
+        let buffer = Buffer::new(self.inner.device.clone(), self.readback_size(),
+            vk::VK_BUFFER_USAGE_TRANSFER_DST_BIT, vk::VK_SHARING_MODE_EXCLUSIVE,
+            vk::VK_MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::VK_MEMORY_PROPERTY_HOST_COHERENT_BIT)?;
+
+        let command_pool = CommandPool::for_queue(queue, 0)?;
+        let mut command_buffer = CommandBuffer::new(command_pool,
+            vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY)?;
+        command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT)?;
+        self.record_readback(&mut command_buffer, &buffer);
+        command_buffer.end()?;
+
+        let fence = Fence::new(self.inner.device.clone(), false)?;
+        queue.submit(&[SubmitInfo::new().command_buffer(&command_buffer)], Some(&fence))?;
+        fence.wait(u64::max_value())?;
+
+        let mut pixels = buffer.map::<u8>()?[..self.readback_size() as usize].to_vec();
+        if bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.inner.extent.width, self.inner.extent.height, pixels)
+            .ok_or_else(|| "Unable to create an image from the render target contents.".into())
+    }
+
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.inner.render_pass
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.inner.framebuffer
+    }
+
+    pub fn color_image(&self) -> &Image {
+        &self.inner.color_image
+    }
+
+    pub fn depth_image(&self) -> &Image {
+        &self.inner.depth_image
+    }
+
+    pub fn extent(&self) -> vk::VkExtent2D {
+        self.inner.extent.clone()
+    }
+
+    pub fn color_format(&self) -> vk::VkFormat {
+        self.inner.color_format
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.inner.device
+    }
+}
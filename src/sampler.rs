@@ -17,6 +17,8 @@ pub struct Sampler {
 
 impl Sampler {
     pub fn new(device: Device) -> VkcResult<Sampler> {
+        let anisotropy_enable = device.enabled_features().samplerAnisotropy;
+
         let create_info = vk::VkSamplerCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
             pNext: ptr::null(),
@@ -28,10 +30,9 @@ impl Sampler {
             addressModeV: vk::VK_SAMPLER_ADDRESS_MODE_REPEAT,
             addressModeW: vk::VK_SAMPLER_ADDRESS_MODE_REPEAT,
             mipLodBias: 0.,
-            // anisotropyEnable: vk::VK_FALSE,
-            // maxAnisotropy: 1.,
-            anisotropyEnable: vk::VK_TRUE,
-            maxAnisotropy: 16.,
+            // Anisotropic filtering is an optional device feature:
+            anisotropyEnable: anisotropy_enable,
+            maxAnisotropy: if anisotropy_enable == vk::VK_FALSE { 1. } else { 16. },
             compareEnable: vk::VK_FALSE,
             compareOp: vk::VK_COMPARE_OP_ALWAYS,
             minLod: 0.,
@@ -43,7 +44,7 @@ impl Sampler {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().vkCreateSampler(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(Sampler {
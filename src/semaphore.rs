@@ -29,7 +29,7 @@ impl Semaphore {
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreateSemaphore(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(Semaphore {
@@ -6,12 +6,31 @@ use std::fs::File;
 use std::io::{Read, BufReader};
 use vk;
 use vks;
-use ::{VkcResult, Device};
+use spirv::{self, ShaderReflection};
+use ::{VkcResult, Device, Version};
+
+
+/// Returns the newest SPIR-V version `device` is guaranteed to accept.
+fn max_spirv_version(device: &Device) -> (u32, u32) {
+    let api_version: u32 = device.physical_device().api_version().into();
+    if api_version >= Version::new(1, 3, 0).into() {
+        (1, 6)
+    } else if api_version >= Version::new(1, 2, 0).into() {
+        (1, 5)
+    } else if device.is_extension_enabled("VK_KHR_spirv_1_4") {
+        (1, 4)
+    } else if api_version >= Version::new(1, 1, 0).into() {
+        (1, 3)
+    } else {
+        (1, 0)
+    }
+}
 
 
 #[derive(Debug)]
 struct Inner {
     handle: vk::VkShaderModule,
+    reflection: Option<ShaderReflection>,
     device: Device,
 }
 
@@ -21,29 +40,61 @@ pub struct ShaderModule {
 }
 
 impl ShaderModule {
+    /// Creates a shader module from SPIR-V `code`, reflecting its entry
+    /// points and interface.
+    ///
+    /// The code is validated first: it must be a whole number of words with
+    /// a valid header and complete instructions. Byte-swapped modules are
+    /// converted to the host's byte order. A module which can not be
+    /// reflected is still created, without reflection data.
     pub fn new(device: Device, code: &[u8]) -> VkcResult<ShaderModule> {
+        let words = spirv::words_from_bytes(code)
+            .map_err(|err| err.chain("ShaderModule::new: Invalid SPIR-V code."))?;
+        let reflection = match ShaderReflection::parse(&words) {
+            Ok(reflection) => Some(reflection),
+            Err(err) => {
+                warn!("ShaderModule::new: Reflection data is unavailable: {}", err);
+                None
+            },
+        };
+
+        let version = spirv::version(&words);
+        let max_version = max_spirv_version(&device);
+        if version > max_version {
+            warn!("ShaderModule::new: SPIR-V version {}.{} is newer than {}.{}, the newest \
+                version guaranteed to be supported by '{}'.", version.0, version.1, max_version.0,
+                max_version.1, device.physical_device().name());
+        }
+
         let create_info = vk::VkShaderModuleCreateInfo {
             sType: vk::VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
             pNext: ptr::null(),
             flags: 0,
-            codeSize: code.len(),
-            pCode: code.as_ptr() as *const u32,
+            codeSize: words.len() * 4,
+            pCode: words.as_ptr(),
         };
 
         let mut handle = 0;
         unsafe {
             ::check(device.vk().core.vkCreateShaderModule(device.handle(), &create_info,
-                ptr::null(), &mut handle));
+                ptr::null(), &mut handle))?;
         }
 
         Ok(ShaderModule {
             inner: Arc::new(Inner {
                 handle,
+                reflection,
                 device,
             })
         })
     }
 
+    /// Returns the entry points, resources and inputs declared by this
+    /// module, or `None` if the module could not be reflected.
+    pub fn reflection(&self) -> Option<&ShaderReflection> {
+        self.inner.reflection.as_ref()
+    }
+
     pub fn handle(&self) -> vk::VkShaderModule {
         self.inner.handle
     }
@@ -0,0 +1,65 @@
+use std::mem;
+use std::slice;
+use libc::c_void;
+use smallvec::SmallVec;
+use vk;
+use ::Pod;
+
+
+/// A set of specialization constant values used when creating a pipeline
+/// shader stage.
+///
+/// Constants are packed into a single data blob in the order they are added.
+/// Note that boolean constants are 32 bits wide (`vk::VkBool32`) and should
+/// be added as such.
+#[derive(Debug, Clone)]
+pub struct SpecializationInfo {
+    map_entries: SmallVec<[vk::VkSpecializationMapEntry; 16]>,
+    data: Vec<u8>,
+}
+
+impl SpecializationInfo {
+    pub fn new() -> SpecializationInfo {
+        SpecializationInfo {
+            map_entries: SmallVec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Adds a constant value for the specialization constant with the id,
+    /// `constant_id` (as declared by `layout(constant_id = ...)` in GLSL).
+    ///
+    /// `T` must be `Pod` since its bytes are copied into the data blob as-is.
+    pub fn constant<T: Pod>(mut self, constant_id: u32, value: T) -> SpecializationInfo {
+        let size = mem::size_of::<T>();
+        let offset = self.data.len();
+        let bytes = unsafe { slice::from_raw_parts(&value as *const T as *const u8, size) };
+        self.data.extend_from_slice(bytes);
+
+        self.map_entries.push(vk::VkSpecializationMapEntry {
+            constantID: constant_id,
+            offset: offset as u32,
+            size: size,
+        });
+        self
+    }
+
+    pub fn map_entries(&self) -> &[vk::VkSpecializationMapEntry] {
+        &self.map_entries
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns a `VkSpecializationInfo` which points into `self` and is only
+    /// valid as long as `self` is neither moved nor modified.
+    pub fn raw(&self) -> vk::VkSpecializationInfo {
+        vk::VkSpecializationInfo {
+            mapEntryCount: self.map_entries.len() as u32,
+            pMapEntries: self.map_entries.as_ptr(),
+            dataSize: self.data.len(),
+            pData: self.data.as_ptr() as *const c_void,
+        }
+    }
+}
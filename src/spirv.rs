@@ -0,0 +1,1255 @@
+//! SPIR-V parsing and reflection.
+//!
+//! Extracts the information needed to build descriptor set and pipeline
+//! layouts (and to check them against hand-written ones) from a SPIR-V
+//! module without depending on an external reflection library.
+
+use std::collections::{HashMap, HashSet};
+use vk;
+use ::VkcResult;
+
+
+/// The first word of every SPIR-V module.
+pub const MAGIC_NUMBER: u32 = 0x0723_0203;
+
+/// The number of words in a module header.
+const HEADER_LEN: usize = 5;
+
+mod op {
+    pub const NAME: u32 = 5;
+    pub const MEMBER_NAME: u32 = 6;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const EXECUTION_MODE: u32 = 16;
+    pub const TYPE_VOID: u32 = 19;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const TYPE_FUNCTION: u32 = 33;
+    pub const TYPE_FORWARD_POINTER: u32 = 39;
+    pub const CONSTANT: u32 = 43;
+    pub const CONSTANT_COMPOSITE: u32 = 44;
+    pub const SPEC_CONSTANT_TRUE: u32 = 48;
+    pub const SPEC_CONSTANT_FALSE: u32 = 49;
+    pub const SPEC_CONSTANT: u32 = 50;
+    pub const SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+    pub const EXECUTION_MODE_ID: u32 = 331;
+}
+
+mod decoration {
+    pub const SPEC_ID: u32 = 1;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+/// The deepest nesting of types followed before a type is assumed to be
+/// recursive.
+const MAX_TYPE_DEPTH: u32 = 64;
+/// The most vertex input locations a single variable may consume.
+const MAX_VERTEX_LOCATIONS: usize = 256;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+
+/// The newest SPIR-V version understood, as (major, minor).
+pub const MAX_VERSION: (u32, u32) = (1, 6);
+
+
+/// Returns the (major, minor) version encoded in a module's second word.
+fn decode_version(word: u32) -> Option<(u32, u32)> {
+    if word & 0xff00_00ff != 0 { return None; }
+    Some(((word >> 16) & 0xff, (word >> 8) & 0xff))
+}
+
+/// Converts `bytes` into words, byte-swapping them if the module was
+/// written with the opposite endianness, and validates the result.
+pub fn words_from_bytes(bytes: &[u8]) -> VkcResult<Vec<u32>> {
+    if bytes.is_empty() {
+        return Err("SPIR-V: Code is empty.".into());
+    }
+    if bytes.len() % 4 != 0 {
+        return Err(format!("SPIR-V: Code size ({} bytes) is not a multiple of 4; the module is \
+            truncated or not SPIR-V.", bytes.len()).into());
+    }
+
+    let mut words: Vec<u32> = bytes.chunks(4)
+        .map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+        .collect();
+    if words[0] == MAGIC_NUMBER.swap_bytes() {
+        for word in words.iter_mut() {
+            *word = word.swap_bytes();
+        }
+    }
+
+    validate(&words)?;
+    Ok(words)
+}
+
+/// Checks that `words` begins with a valid header and is made up of
+/// complete instructions whose ids are well-formed.
+pub fn validate(words: &[u32]) -> VkcResult<()> {
+    if words.len() < HEADER_LEN {
+        return Err(format!("SPIR-V: Module is {} words long, too short to contain a header \
+            ({} words).", words.len(), HEADER_LEN).into());
+    }
+    if words[0] != MAGIC_NUMBER {
+        return Err(format!("SPIR-V: Invalid magic number {:#010x} (expected {:#010x}); the code \
+            is not SPIR-V.", words[0], MAGIC_NUMBER).into());
+    }
+
+    match decode_version(words[1]) {
+        Some(version) if version.0 == 1 && version <= MAX_VERSION => (),
+        Some((major, minor)) => return Err(format!("SPIR-V: Unsupported version {}.{} (newest \
+            supported: {}.{}).", major, minor, MAX_VERSION.0, MAX_VERSION.1).into()),
+        None => return Err(format!("SPIR-V: Malformed version word {:#010x}.", words[1]).into()),
+    }
+    if words[3] == 0 {
+        return Err("SPIR-V: Invalid id bound of 0.".into());
+    }
+    if words[4] != 0 {
+        return Err(format!("SPIR-V: Reserved header word is {:#x} (expected 0).",
+            words[4]).into());
+    }
+
+    let mut pos = HEADER_LEN;
+    while pos < words.len() {
+        let word_count = (words[pos] >> 16) as usize;
+        if word_count == 0 {
+            return Err(format!("SPIR-V: Instruction at word {} (opcode {}) has a word count of \
+                0.", pos, words[pos] & 0xffff).into());
+        }
+        if pos + word_count > words.len() {
+            return Err(format!("SPIR-V: Instruction at word {} (opcode {}) is {} words long but \
+                only {} remain; the module is truncated.", pos, words[pos] & 0xffff, word_count,
+                words.len() - pos).into());
+        }
+        pos += word_count;
+    }
+    validate_ids(words)
+}
+
+/// Returns the instructions of a module made up of complete instructions as
+/// (word position, opcode, operands).
+fn instructions<'w>(words: &'w [u32]) -> impl Iterator<Item = (usize, u32, &'w [u32])> + 'w {
+    let mut pos = HEADER_LEN;
+    ::std::iter::from_fn(move || {
+        if pos >= words.len() { return None; }
+        let start = pos;
+        let word_count = (words[pos] >> 16) as usize;
+        pos += word_count;
+        Some((start, words[start] & 0xffff, &words[start + 1..pos]))
+    })
+}
+
+/// Returns the minimum number of operands of the opcodes read when
+/// validating and reflecting a module.
+fn min_operands(opcode: u32) -> usize {
+    match opcode {
+        op::TYPE_VOID | op::TYPE_BOOL | op::TYPE_SAMPLER | op::TYPE_STRUCT |
+            op::TYPE_FUNCTION => 1,
+        op::NAME | op::TYPE_FLOAT | op::TYPE_SAMPLED_IMAGE | op::TYPE_RUNTIME_ARRAY |
+            op::SPEC_CONSTANT_TRUE | op::SPEC_CONSTANT_FALSE | op::DECORATE |
+            op::EXECUTION_MODE | op::EXECUTION_MODE_ID | op::CONSTANT_COMPOSITE |
+            op::SPEC_CONSTANT_COMPOSITE | op::TYPE_FORWARD_POINTER => 2,
+        op::MEMBER_NAME | op::ENTRY_POINT | op::TYPE_INT | op::TYPE_VECTOR |
+            op::TYPE_MATRIX | op::TYPE_ARRAY | op::TYPE_POINTER | op::CONSTANT |
+            op::SPEC_CONSTANT | op::VARIABLE | op::MEMBER_DECORATE => 3,
+        op::TYPE_IMAGE => 7,
+        _ => 0,
+    }
+}
+
+/// Returns the result id of a type, constant or variable declaration and
+/// the ids it refers to, which must be declared before it.
+fn declaration(opcode: u32, ops: &[u32]) -> Option<(u32, Vec<u32>)> {
+    Some(match opcode {
+        op::TYPE_VOID | op::TYPE_BOOL | op::TYPE_INT | op::TYPE_FLOAT | op::TYPE_SAMPLER => {
+            (ops[0], Vec::new())
+        },
+        op::TYPE_VECTOR | op::TYPE_MATRIX | op::TYPE_IMAGE | op::TYPE_SAMPLED_IMAGE |
+            op::TYPE_RUNTIME_ARRAY => (ops[0], vec![ops[1]]),
+        op::TYPE_ARRAY => (ops[0], vec![ops[1], ops[2]]),
+        op::TYPE_STRUCT | op::TYPE_FUNCTION => (ops[0], ops[1..].to_vec()),
+        op::TYPE_POINTER => (ops[0], vec![ops[2]]),
+        op::CONSTANT | op::SPEC_CONSTANT | op::SPEC_CONSTANT_TRUE | op::SPEC_CONSTANT_FALSE => {
+            (ops[1], vec![ops[0]])
+        },
+        op::CONSTANT_COMPOSITE | op::SPEC_CONSTANT_COMPOSITE | op::VARIABLE => {
+            // The constituents of a composite or the initializer of a
+            // variable follow its result id.
+            let skip = if opcode == op::VARIABLE { 3 } else { 2 };
+            (ops[1], Some(ops[0]).into_iter().chain(ops[skip..].iter().cloned()).collect())
+        },
+        _ => return None,
+    })
+}
+
+/// Checks the ids of the instructions read when reflecting a module: each
+/// must be non-zero and below the module's id bound, result ids must be
+/// unique and declarations may not refer to themselves or to ids declared
+/// after them, other than those of forward-declared pointers.
+fn validate_ids(words: &[u32]) -> VkcResult<()> {
+    let bound = words[3];
+    let check_bound = |pos: usize, opcode: u32, id: u32| -> VkcResult<()> {
+        if id == 0 || id >= bound {
+            return Err(format!("SPIR-V: Instruction at word {} (opcode {}) uses id {}, which is \
+                outside the id bound ({}).", pos, opcode, id, bound).into());
+        }
+        Ok(())
+    };
+
+    // Where each id is declared, as an instruction index.
+    let mut declared_at = HashMap::new();
+    let mut forward_pointers = HashSet::new();
+    for (index, (pos, opcode, ops)) in instructions(words).enumerate() {
+        if ops.len() < min_operands(opcode) {
+            return Err(format!("SPIR-V: Instruction at word {} (opcode {}) has too few \
+                operands.", pos, opcode).into());
+        }
+        let targets = match opcode {
+            op::NAME | op::MEMBER_NAME | op::DECORATE | op::MEMBER_DECORATE |
+                op::EXECUTION_MODE | op::EXECUTION_MODE_ID | op::TYPE_FORWARD_POINTER => &ops[..1],
+            op::ENTRY_POINT => &ops[1..2],
+            _ => &[],
+        };
+        for &id in targets {
+            check_bound(pos, opcode, id)?;
+        }
+        match opcode {
+            op::ENTRY_POINT => {
+                let interface = &ops[2 + parse_string(&ops[2..]).1..];
+                for &id in interface {
+                    check_bound(pos, opcode, id)?;
+                }
+            },
+            op::EXECUTION_MODE_ID => {
+                for &id in &ops[2..] {
+                    check_bound(pos, opcode, id)?;
+                }
+            },
+            op::TYPE_FORWARD_POINTER => { forward_pointers.insert(ops[0]); },
+            _ => (),
+        }
+
+        if let Some((result, _)) = declaration(opcode, ops) {
+            check_bound(pos, opcode, result)?;
+            if declared_at.insert(result, index).is_some() {
+                return Err(format!("SPIR-V: Instruction at word {} (opcode {}) redeclares id {}.",
+                    pos, opcode, result).into());
+            }
+        }
+    }
+
+    for (index, (pos, opcode, ops)) in instructions(words).enumerate() {
+        let uses = match declaration(opcode, ops) {
+            Some((_, uses)) => uses,
+            None => continue,
+        };
+        for id in uses {
+            check_bound(pos, opcode, id)?;
+            let later = declared_at.get(&id).map(|&at| at >= index).unwrap_or(false);
+            if later && !forward_pointers.contains(&id) {
+                return Err(format!("SPIR-V: Instruction at word {} (opcode {}) refers to id {} \
+                    before it is declared.", pos, opcode, id).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the (major, minor) SPIR-V version of a validated module.
+pub fn version(words: &[u32]) -> (u32, u32) {
+    decode_version(words[1]).unwrap_or((0, 0))
+}
+
+/// Decodes a null-terminated literal string, returning it and the number of
+/// words it occupies.
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (i, &word) in words.iter().enumerate() {
+        for shift in &[0, 8, 16, 24] {
+            let byte = (word >> shift) as u8;
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), i + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+
+/// The stage an entry point is executed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionModel {
+    Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
+    Fragment,
+    GLCompute,
+    Kernel,
+    Other(u32),
+}
+
+impl ExecutionModel {
+    fn from_raw(model: u32) -> ExecutionModel {
+        match model {
+            0 => ExecutionModel::Vertex,
+            1 => ExecutionModel::TessellationControl,
+            2 => ExecutionModel::TessellationEvaluation,
+            3 => ExecutionModel::Geometry,
+            4 => ExecutionModel::Fragment,
+            5 => ExecutionModel::GLCompute,
+            6 => ExecutionModel::Kernel,
+            other => ExecutionModel::Other(other),
+        }
+    }
+
+    /// Returns the shader stage flag corresponding to this model, or 0 if
+    /// it has no Vulkan equivalent.
+    pub fn stage_flags(&self) -> vk::VkShaderStageFlags {
+        match *self {
+            ExecutionModel::Vertex => vk::VK_SHADER_STAGE_VERTEX_BIT,
+            ExecutionModel::TessellationControl => vk::VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT,
+            ExecutionModel::TessellationEvaluation =>
+                vk::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT,
+            ExecutionModel::Geometry => vk::VK_SHADER_STAGE_GEOMETRY_BIT,
+            ExecutionModel::Fragment => vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+            ExecutionModel::GLCompute => vk::VK_SHADER_STAGE_COMPUTE_BIT,
+            ExecutionModel::Kernel | ExecutionModel::Other(_) => 0,
+        }
+    }
+}
+
+
+/// An entry point declared by a module.
+#[derive(Debug, Clone)]
+pub struct EntryPoint {
+    pub name: String,
+    pub execution_model: ExecutionModel,
+    /// The local workgroup size of a compute entry point.
+    pub local_size: Option<[u32; 3]>,
+}
+
+
+/// A member of a uniform or storage buffer block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMemberInfo {
+    pub name: String,
+    pub offset: u32,
+    /// The size in bytes, or 0 for a runtime-sized array.
+    pub size: u32,
+}
+
+
+/// A resource variable decorated with a descriptor set and binding.
+#[derive(Debug, Clone)]
+pub struct DescriptorBindingInfo {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::VkDescriptorType,
+    /// The number of array elements, or 0 for a runtime-sized array.
+    pub count: u32,
+    pub stage_flags: vk::VkShaderStageFlags,
+    pub name: String,
+    /// The members of a uniform or storage buffer block, in declaration
+    /// order. Empty for other descriptor types.
+    pub block_members: Vec<BlockMemberInfo>,
+}
+
+impl DescriptorBindingInfo {
+    /// Checks that a host-side struct whose members are `members` (for
+    /// example from `AsStd140::std140_members`) matches the layout of this
+    /// block.
+    ///
+    /// Each member must be at the same offset as in the shader and be at
+    /// least as large. A trailing runtime-sized array in the shader is
+    /// ignored.
+    pub fn check_block_layout(&self, members: &[BlockMemberInfo]) -> VkcResult<()> {
+        let mut shader_members = &self.block_members[..];
+        if shader_members.last().map(|m| m.size == 0).unwrap_or(false) {
+            shader_members = &shader_members[..shader_members.len() - 1];
+        }
+        if members.len() != shader_members.len() {
+            return Err(format!("Block '{}' (set {}, binding {}) has {} members in the shader \
+                but {} on the host.", self.name, self.set, self.binding, shader_members.len(),
+                members.len()).into());
+        }
+        for (i, (shader, host)) in shader_members.iter().zip(members.iter()).enumerate() {
+            if host.offset != shader.offset || host.size < shader.size {
+                return Err(format!("Block '{}' (set {}, binding {}): Member {} ('{}') is {} \
+                    bytes at offset {} in the shader but {} bytes at offset {} on the host ('{}').",
+                    self.name, self.set, self.binding, i, shader.name, shader.size,
+                    shader.offset, host.size, host.offset, host.name).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// The range of push constant memory used by a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushConstantRangeInfo {
+    pub offset: u32,
+    pub size: u32,
+    pub stage_flags: vk::VkShaderStageFlags,
+}
+
+impl PushConstantRangeInfo {
+    pub fn raw(&self) -> vk::VkPushConstantRange {
+        vk::VkPushConstantRange {
+            stageFlags: self.stage_flags,
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+
+/// A single location consumed by a vertex shader input.
+///
+/// Matrix and array inputs produce one entry per location.
+#[derive(Debug, Clone)]
+pub struct VertexInputInfo {
+    pub location: u32,
+    pub format: vk::VkFormat,
+    pub name: String,
+}
+
+
+/// A specialization constant declared by a module.
+#[derive(Debug, Clone)]
+pub struct SpecializationConstantInfo {
+    pub id: u32,
+    pub name: String,
+}
+
+
+#[derive(Debug, Clone)]
+enum Type {
+    Void,
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+
+/// The instructions of a module relevant to reflection, indexed by id.
+#[derive(Debug, Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    decorations: HashMap<u32, Vec<(u32, Vec<u32>)>>,
+    member_decorations: HashMap<(u32, u32), Vec<(u32, Vec<u32>)>>,
+    types: HashMap<u32, Type>,
+    // The value words of scalar constants, including spec constant defaults.
+    constants: HashMap<u32, Vec<u32>>,
+    // The constituent ids of composite constants.
+    composites: HashMap<u32, Vec<u32>>,
+    spec_constants: Vec<u32>,
+    // (result id, type id, storage class)
+    variables: Vec<(u32, u32, u32)>,
+    // (function id, execution model, name, interface variable ids)
+    entry_points: Vec<(u32, u32, String, Vec<u32>)>,
+    // (function id, mode, operands)
+    execution_modes: Vec<(u32, u32, Vec<u32>)>,
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> VkcResult<Module> {
+        validate(words)?;
+
+        let mut module = Module::default();
+        for (pos, opcode, ops) in instructions(words) {
+            module.instruction(opcode, ops)
+                .map_err(|err| format!("SPIR-V: Malformed instruction (opcode {}) at word {}: {}",
+                    opcode, pos, err))?;
+        }
+        Ok(module)
+    }
+
+    /// Records a single instruction.
+    fn instruction(&mut self, opcode: u32, ops: &[u32]) -> Result<(), &'static str> {
+        if ops.len() < min_operands(opcode) {
+            return Err("too few operands");
+        }
+
+        match opcode {
+            op::NAME => { self.names.insert(ops[0], parse_string(&ops[1..]).0); },
+            op::MEMBER_NAME => {
+                self.member_names.insert((ops[0], ops[1]), parse_string(&ops[2..]).0);
+            },
+            op::ENTRY_POINT => {
+                let (name, name_len) = parse_string(&ops[2..]);
+                let interface = ops[2 + name_len..].to_vec();
+                self.entry_points.push((ops[1], ops[0], name, interface));
+            },
+            op::EXECUTION_MODE | op::EXECUTION_MODE_ID => {
+                self.execution_modes.push((ops[0], ops[1], ops[2..].to_vec()));
+            },
+            op::TYPE_VOID => { self.types.insert(ops[0], Type::Void); },
+            op::TYPE_BOOL => { self.types.insert(ops[0], Type::Bool); },
+            op::TYPE_INT => {
+                self.types.insert(ops[0], Type::Int { width: ops[1], signed: ops[2] != 0 });
+            },
+            op::TYPE_FLOAT => { self.types.insert(ops[0], Type::Float { width: ops[1] }); },
+            op::TYPE_VECTOR => {
+                self.types.insert(ops[0], Type::Vector { component: ops[1], count: ops[2] });
+            },
+            op::TYPE_MATRIX => {
+                self.types.insert(ops[0], Type::Matrix { column: ops[1], count: ops[2] });
+            },
+            op::TYPE_IMAGE => {
+                self.types.insert(ops[0], Type::Image { dim: ops[2], sampled: ops[6] });
+            },
+            op::TYPE_SAMPLER => { self.types.insert(ops[0], Type::Sampler); },
+            op::TYPE_SAMPLED_IMAGE => { self.types.insert(ops[0], Type::SampledImage); },
+            op::TYPE_ARRAY => {
+                self.types.insert(ops[0], Type::Array { element: ops[1], length: ops[2] });
+            },
+            op::TYPE_RUNTIME_ARRAY => {
+                self.types.insert(ops[0], Type::RuntimeArray { element: ops[1] });
+            },
+            op::TYPE_STRUCT => {
+                self.types.insert(ops[0], Type::Struct { members: ops[1..].to_vec() });
+            },
+            op::TYPE_POINTER => { self.types.insert(ops[0], Type::Pointer { pointee: ops[2] }); },
+            op::CONSTANT => { self.constants.insert(ops[1], ops[2..].to_vec()); },
+            op::SPEC_CONSTANT => {
+                self.constants.insert(ops[1], ops[2..].to_vec());
+                self.spec_constants.push(ops[1]);
+            },
+            op::SPEC_CONSTANT_TRUE | op::SPEC_CONSTANT_FALSE => {
+                self.constants.insert(ops[1], vec![(opcode == op::SPEC_CONSTANT_TRUE) as u32]);
+                self.spec_constants.push(ops[1]);
+            },
+            op::CONSTANT_COMPOSITE | op::SPEC_CONSTANT_COMPOSITE => {
+                self.composites.insert(ops[1], ops[2..].to_vec());
+            },
+            op::VARIABLE => { self.variables.push((ops[1], ops[0], ops[2])); },
+            op::DECORATE => {
+                self.decorations.entry(ops[0]).or_insert_with(Vec::new)
+                    .push((ops[1], ops[2..].to_vec()));
+            },
+            op::MEMBER_DECORATE => {
+                self.member_decorations.entry((ops[0], ops[1])).or_insert_with(Vec::new)
+                    .push((ops[2], ops[3..].to_vec()));
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the first literal of `decoration` on `id` (0 if it takes
+    /// none), or `None` if `id` is not decorated with it.
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&id)
+            .and_then(|decs| decs.iter().find(|&&(dec, _)| dec == decoration))
+            .map(|&(_, ref literals)| literals.first().cloned().unwrap_or(0))
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations.get(&(id, member))
+            .and_then(|decs| decs.iter().find(|&&(dec, _)| dec == decoration))
+            .map(|&(_, ref literals)| literals.first().cloned().unwrap_or(0))
+    }
+
+    fn ty(&self, id: u32) -> VkcResult<&Type> {
+        self.types.get(&id).ok_or_else(|| format!("SPIR-V: Undefined type id {}.", id).into())
+    }
+
+    fn constant_value(&self, id: u32) -> VkcResult<u32> {
+        self.constants.get(&id).and_then(|words| words.first().cloned())
+            .ok_or_else(|| format!("SPIR-V: Undefined constant id {}.", id).into())
+    }
+
+    fn name(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(|name| name.as_str()).filter(|name| !name.is_empty())
+    }
+
+    /// Returns the type `ty_id` found at `depth` levels of nesting, failing
+    /// if it is nested too deeply.
+    fn nested_ty(&self, ty_id: u32, depth: u32) -> VkcResult<&Type> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(format!("SPIR-V: Type id {} is nested too deeply or recursive.",
+                ty_id).into());
+        }
+        self.ty(ty_id)
+    }
+
+    /// Returns the size in bytes of `ty_id` as laid out in a block.
+    fn type_size(&self, ty_id: u32) -> VkcResult<u32> {
+        self.nested_type_size(ty_id, 0)
+    }
+
+    fn nested_type_size(&self, ty_id: u32, depth: u32) -> VkcResult<u32> {
+        let overflow = || format!("SPIR-V: The size of type id {} overflows.", ty_id);
+        Ok(match *self.nested_ty(ty_id, depth)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => {
+                self.nested_type_size(component, depth + 1)?.checked_mul(count)
+                    .ok_or_else(overflow)?
+            },
+            Type::Matrix { column, count } => {
+                self.nested_type_size(column, depth + 1)?.checked_mul(count)
+                    .ok_or_else(overflow)?
+            },
+            Type::Array { element, length } => {
+                let stride = match self.decoration(ty_id, decoration::ARRAY_STRIDE) {
+                    Some(stride) => stride,
+                    None => self.nested_type_size(element, depth + 1)?,
+                };
+                stride.checked_mul(self.constant_value(length)?).ok_or_else(overflow)?
+            },
+            // The end of each member has already been checked for overflow.
+            Type::Struct { .. } => {
+                self.nested_block_members(ty_id, depth)?.iter().map(|m| m.offset + m.size).max()
+                    .unwrap_or(0)
+            },
+            _ => 0,
+        })
+    }
+
+    /// Returns the members of the struct `ty_id` as laid out in a block.
+    fn block_members(&self, ty_id: u32) -> VkcResult<Vec<BlockMemberInfo>> {
+        self.nested_block_members(ty_id, 0)
+    }
+
+    fn nested_block_members(&self, ty_id: u32, depth: u32) -> VkcResult<Vec<BlockMemberInfo>> {
+        let overflow = || format!("SPIR-V: The size of type id {} overflows.", ty_id);
+        let members = match *self.nested_ty(ty_id, depth)? {
+            Type::Struct { ref members } => members,
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut block_members = Vec::with_capacity(members.len());
+        let mut end = 0;
+        for (i, &member) in members.iter().enumerate() {
+            let offset = self.member_decoration(ty_id, i as u32, decoration::OFFSET)
+                .unwrap_or(end);
+            let size = match (self.nested_ty(member, depth + 1)?,
+                    self.member_decoration(ty_id, i as u32, decoration::MATRIX_STRIDE)) {
+                (&Type::Matrix { count, .. }, Some(stride)) => {
+                    stride.checked_mul(count).ok_or_else(overflow)?
+                },
+                _ => self.nested_type_size(member, depth + 1)?,
+            };
+            let name = self.member_names.get(&(ty_id, i as u32)).cloned().unwrap_or_default();
+            block_members.push(BlockMemberInfo { name, offset, size });
+            end = end.max(offset.checked_add(size).ok_or_else(overflow)?);
+        }
+        Ok(block_members)
+    }
+
+    /// Returns the descriptor type, count and the type id of a single
+    /// descriptor of a resource variable of type `ty_id` (a pointer) in
+    /// `storage`.
+    fn descriptor_type(&self, ty_id: u32, storage: u32)
+            -> VkcResult<(vk::VkDescriptorType, u32, u32)>
+    {
+        let mut ty_id = match *self.ty(ty_id)? {
+            Type::Pointer { pointee } => pointee,
+            _ => return Err("SPIR-V: Variable type is not a pointer.".into()),
+        };
+
+        let mut count = 1u32;
+        let mut depth = 0;
+        loop {
+            match *self.nested_ty(ty_id, depth)? {
+                Type::Array { element, length } => {
+                    count = count.checked_mul(self.constant_value(length)?)
+                        .ok_or("SPIR-V: The descriptor count overflows.")?;
+                    ty_id = element;
+                },
+                Type::RuntimeArray { element } => {
+                    count = 0;
+                    ty_id = element;
+                },
+                _ => break,
+            }
+            depth += 1;
+        }
+
+        let descriptor_type = match *self.ty(ty_id)? {
+            Type::Sampler => vk::VK_DESCRIPTOR_TYPE_SAMPLER,
+            Type::SampledImage => vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            Type::Image { dim, sampled } => match (dim, sampled) {
+                (DIM_BUFFER, 2) => vk::VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER,
+                (DIM_SUBPASS_DATA, _) => vk::VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT,
+                (_, 2) => vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,
+                _ => vk::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE,
+            },
+            Type::Struct { .. } => {
+                if storage == storage_class::STORAGE_BUFFER ||
+                        self.decoration(ty_id, decoration::BUFFER_BLOCK).is_some()
+                {
+                    vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER
+                } else {
+                    vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER
+                }
+            },
+            ref other => return Err(format!("SPIR-V: Unsupported resource type: {:?}.",
+                other).into()),
+        };
+        Ok((descriptor_type, count, ty_id))
+    }
+
+    /// Returns the vertex attribute format of each location consumed by an
+    /// input of type `ty_id`.
+    fn vertex_formats(&self, ty_id: u32) -> VkcResult<Vec<vk::VkFormat>> {
+        self.nested_vertex_formats(ty_id, 0)
+    }
+
+    fn nested_vertex_formats(&self, ty_id: u32, depth: u32) -> VkcResult<Vec<vk::VkFormat>> {
+        let repeat = |formats: Vec<vk::VkFormat>, count: u32| -> VkcResult<Vec<vk::VkFormat>> {
+            if formats.len().saturating_mul(count as usize) > MAX_VERTEX_LOCATIONS {
+                return Err(format!("SPIR-V: Vertex input type id {} consumes more than {} \
+                    locations.", ty_id, MAX_VERTEX_LOCATIONS).into());
+            }
+            Ok((0..count).flat_map(|_| formats.clone()).collect())
+        };
+        let (scalar, components) = match *self.nested_ty(ty_id, depth)? {
+            Type::Vector { component, count } => (component, count),
+            Type::Matrix { column, count } => {
+                return repeat(self.nested_vertex_formats(column, depth + 1)?, count);
+            },
+            Type::Array { element, length } => {
+                let element_formats = self.nested_vertex_formats(element, depth + 1)?;
+                return repeat(element_formats, self.constant_value(length)?);
+            },
+            _ => (ty_id, 1),
+        };
+
+        let formats = match *self.ty(scalar)? {
+            Type::Float { width: 16 } => [vk::VK_FORMAT_R16_SFLOAT, vk::VK_FORMAT_R16G16_SFLOAT,
+                vk::VK_FORMAT_R16G16B16_SFLOAT, vk::VK_FORMAT_R16G16B16A16_SFLOAT],
+            Type::Float { width: 32 } => [vk::VK_FORMAT_R32_SFLOAT, vk::VK_FORMAT_R32G32_SFLOAT,
+                vk::VK_FORMAT_R32G32B32_SFLOAT, vk::VK_FORMAT_R32G32B32A32_SFLOAT],
+            Type::Float { width: 64 } => [vk::VK_FORMAT_R64_SFLOAT, vk::VK_FORMAT_R64G64_SFLOAT,
+                vk::VK_FORMAT_R64G64B64_SFLOAT, vk::VK_FORMAT_R64G64B64A64_SFLOAT],
+            Type::Int { width: 8, signed: true } => [vk::VK_FORMAT_R8_SINT,
+                vk::VK_FORMAT_R8G8_SINT, vk::VK_FORMAT_R8G8B8_SINT,
+                vk::VK_FORMAT_R8G8B8A8_SINT],
+            Type::Int { width: 8, signed: false } => [vk::VK_FORMAT_R8_UINT,
+                vk::VK_FORMAT_R8G8_UINT, vk::VK_FORMAT_R8G8B8_UINT,
+                vk::VK_FORMAT_R8G8B8A8_UINT],
+            Type::Int { width: 16, signed: true } => [vk::VK_FORMAT_R16_SINT,
+                vk::VK_FORMAT_R16G16_SINT, vk::VK_FORMAT_R16G16B16_SINT,
+                vk::VK_FORMAT_R16G16B16A16_SINT],
+            Type::Int { width: 16, signed: false } => [vk::VK_FORMAT_R16_UINT,
+                vk::VK_FORMAT_R16G16_UINT, vk::VK_FORMAT_R16G16B16_UINT,
+                vk::VK_FORMAT_R16G16B16A16_UINT],
+            Type::Int { width: 32, signed: true } => [vk::VK_FORMAT_R32_SINT,
+                vk::VK_FORMAT_R32G32_SINT, vk::VK_FORMAT_R32G32B32_SINT,
+                vk::VK_FORMAT_R32G32B32A32_SINT],
+            Type::Int { width: 32, signed: false } => [vk::VK_FORMAT_R32_UINT,
+                vk::VK_FORMAT_R32G32_UINT, vk::VK_FORMAT_R32G32B32_UINT,
+                vk::VK_FORMAT_R32G32B32A32_UINT],
+            Type::Int { width: 64, signed: true } => [vk::VK_FORMAT_R64_SINT,
+                vk::VK_FORMAT_R64G64_SINT, vk::VK_FORMAT_R64G64B64_SINT,
+                vk::VK_FORMAT_R64G64B64A64_SINT],
+            Type::Int { width: 64, signed: false } => [vk::VK_FORMAT_R64_UINT,
+                vk::VK_FORMAT_R64G64_UINT, vk::VK_FORMAT_R64G64B64_UINT,
+                vk::VK_FORMAT_R64G64B64A64_UINT],
+            ref other => return Err(format!("SPIR-V: Unsupported vertex input type: {:?}.",
+                other).into()),
+        };
+        if components < 1 || components > 4 {
+            return Err(format!("SPIR-V: Unsupported vertex input component count: {}.",
+                components).into());
+        }
+        Ok(vec![formats[components as usize - 1]])
+    }
+
+    /// Returns the binding of the resource variable `id` of type `ty_id` in
+    /// `storage`, or `None` if it is not decorated with a binding.
+    fn descriptor_binding(&self, id: u32, ty_id: u32, storage: u32,
+            stage_flags: vk::VkShaderStageFlags) -> VkcResult<Option<DescriptorBindingInfo>>
+    {
+        let binding = match self.decoration(id, decoration::BINDING) {
+            Some(binding) => binding,
+            None => return Ok(None),
+        };
+        let set = self.decoration(id, decoration::DESCRIPTOR_SET).unwrap_or(0);
+        let (descriptor_type, count, element) = self.descriptor_type(ty_id, storage)?;
+        let name = self.name(id).or_else(|| match self.types.get(&ty_id) {
+            Some(&Type::Pointer { pointee }) => self.name(pointee),
+            _ => None,
+        }).unwrap_or("").to_owned();
+        // Only buffer blocks are structs.
+        let block_members = self.block_members(element)?;
+
+        Ok(Some(DescriptorBindingInfo {
+            set, binding, descriptor_type, count, stage_flags, name, block_members,
+        }))
+    }
+
+    /// Returns the range used by the push constant variable of type `ty_id`.
+    fn push_constant_range(&self, ty_id: u32, stage_flags: vk::VkShaderStageFlags)
+            -> VkcResult<PushConstantRangeInfo>
+    {
+        let block = match *self.ty(ty_id)? {
+            Type::Pointer { pointee } => pointee,
+            _ => return Err("SPIR-V: Variable type is not a pointer.".into()),
+        };
+        let members = match *self.ty(block)? {
+            Type::Struct { ref members } => members.len() as u32,
+            _ => 0,
+        };
+        let offset = (0..members)
+            .filter_map(|i| self.member_decoration(block, i, decoration::OFFSET))
+            .min()
+            .unwrap_or(0);
+        let size = self.type_size(block)?.checked_sub(offset)
+            .ok_or("SPIR-V: Push constant block has a member beyond its end.")?;
+
+        Ok(PushConstantRangeInfo { offset, size, stage_flags })
+    }
+
+    /// Returns the locations consumed by the vertex input variable `id` of
+    /// type `ty_id`, which is empty for built-ins.
+    fn vertex_inputs(&self, id: u32, ty_id: u32) -> VkcResult<Vec<VertexInputInfo>> {
+        if self.decoration(id, decoration::BUILT_IN).is_some() { return Ok(Vec::new()); }
+        let location = match self.decoration(id, decoration::LOCATION) {
+            Some(location) => location,
+            None => return Ok(Vec::new()),
+        };
+        let pointee = match *self.ty(ty_id)? {
+            Type::Pointer { pointee } => pointee,
+            _ => return Err("SPIR-V: Variable type is not a pointer.".into()),
+        };
+        let name = self.name(id).unwrap_or("").to_owned();
+
+        self.vertex_formats(pointee)?.into_iter().enumerate().map(|(i, format)| {
+            let location = location.checked_add(i as u32)
+                .ok_or("SPIR-V: Vertex input location overflows.")?;
+            Ok(VertexInputInfo { location, format, name: name.clone() })
+        }).collect()
+    }
+
+    /// Returns the local workgroup size of the entry point `function`.
+    fn local_size(&self, function: u32) -> VkcResult<Option<[u32; 3]>> {
+        // A constant decorated with the `WorkgroupSize` built-in overrides
+        // any execution mode.
+        for (&id, constituents) in &self.composites {
+            if self.decoration(id, decoration::BUILT_IN) == Some(BUILT_IN_WORKGROUP_SIZE) &&
+                    constituents.len() == 3
+            {
+                return Ok(Some([self.constant_value(constituents[0])?,
+                    self.constant_value(constituents[1])?, self.constant_value(constituents[2])?]));
+            }
+        }
+
+        for &(target, mode, ref operands) in &self.execution_modes {
+            if target != function || operands.len() < 3 { continue; }
+            match mode {
+                EXECUTION_MODE_LOCAL_SIZE => {
+                    return Ok(Some([operands[0], operands[1], operands[2]]));
+                },
+                EXECUTION_MODE_LOCAL_SIZE_ID => {
+                    return Ok(Some([self.constant_value(operands[0])?,
+                        self.constant_value(operands[1])?, self.constant_value(operands[2])?]));
+                },
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+}
+
+
+/// Information about the entry points and interface of a SPIR-V module.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPoint>,
+    /// Resource bindings, sorted by set then binding.
+    pub descriptor_bindings: Vec<DescriptorBindingInfo>,
+    pub push_constant_ranges: Vec<PushConstantRangeInfo>,
+    /// Vertex shader inputs, sorted by location. Empty unless the module has
+    /// a vertex entry point.
+    pub vertex_inputs: Vec<VertexInputInfo>,
+    /// Specialization constants, sorted by constant id.
+    pub specialization_constants: Vec<SpecializationConstantInfo>,
+}
+
+impl ShaderReflection {
+    /// Reflects the SPIR-V module `words`.
+    ///
+    /// Resources are not traced back to the entry points which use them, so
+    /// each resource is reported as used by every stage the module has an
+    /// entry point for. Variables which can not be reflected, such as those
+    /// of types this module does not model, are skipped with a warning.
+    pub fn parse(words: &[u32]) -> VkcResult<ShaderReflection> {
+        let module = Module::parse(words)?;
+
+        let mut entry_points = Vec::with_capacity(module.entry_points.len());
+        let mut vertex_interface = HashSet::new();
+        for &(function, model, ref name, ref interface) in &module.entry_points {
+            let execution_model = ExecutionModel::from_raw(model);
+            let local_size = if execution_model == ExecutionModel::GLCompute {
+                module.local_size(function).unwrap_or_else(|err| {
+                    warn!("SPIR-V: Unable to reflect the local size of '{}': {}", name, err);
+                    None
+                })
+            } else {
+                None
+            };
+            if execution_model == ExecutionModel::Vertex {
+                vertex_interface.extend(interface.iter().cloned());
+            }
+            entry_points.push(EntryPoint { name: name.clone(), execution_model, local_size });
+        }
+        let stage_flags = entry_points.iter()
+            .fold(0, |flags, ep| flags | ep.execution_model.stage_flags());
+
+        let mut descriptor_bindings = Vec::new();
+        let mut push_constant_ranges = Vec::new();
+        let mut vertex_inputs = Vec::new();
+
+        for &(id, ty_id, storage) in &module.variables {
+            let reflected = match storage {
+                storage_class::UNIFORM_CONSTANT | storage_class::UNIFORM |
+                        storage_class::STORAGE_BUFFER => {
+                    module.descriptor_binding(id, ty_id, storage, stage_flags)
+                        .map(|binding| descriptor_bindings.extend(binding))
+                },
+                storage_class::PUSH_CONSTANT => {
+                    module.push_constant_range(ty_id, stage_flags)
+                        .map(|range| push_constant_ranges.push(range))
+                },
+                // Inputs of other stages in the same module are not vertex
+                // inputs.
+                storage_class::INPUT if vertex_interface.contains(&id) => {
+                    module.vertex_inputs(id, ty_id).map(|inputs| vertex_inputs.extend(inputs))
+                },
+                _ => Ok(()),
+            };
+            if let Err(err) = reflected {
+                warn!("SPIR-V: Skipping variable '{}' (id {}) which can not be reflected: {}",
+                    module.name(id).unwrap_or(""), id, err);
+            }
+        }
+
+        let mut specialization_constants: Vec<_> = module.spec_constants.iter()
+            .filter_map(|&id| module.decoration(id, decoration::SPEC_ID).map(|spec_id| {
+                SpecializationConstantInfo {
+                    id: spec_id,
+                    name: module.name(id).unwrap_or("").to_owned(),
+                }
+            }))
+            .collect();
+
+        descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+        vertex_inputs.sort_by_key(|input| input.location);
+        specialization_constants.sort_by_key(|sc| sc.id);
+
+        Ok(ShaderReflection {
+            entry_points,
+            descriptor_bindings,
+            push_constant_ranges,
+            vertex_inputs,
+            specialization_constants,
+        })
+    }
+
+    /// Returns the entry point named `name`, if any.
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPoint> {
+        self.entry_points.iter().find(|ep| ep.name == name)
+    }
+
+    /// Returns the combined stage flags of all entry points.
+    pub fn stage_flags(&self) -> vk::VkShaderStageFlags {
+        self.entry_points.iter().fold(0, |flags, ep| flags | ep.execution_model.stage_flags())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use vk;
+    use super::*;
+
+    static VERT_SPV: &[u8] = include_bytes!("../shaders/vert.spv");
+    static FRAG_SPV: &[u8] = include_bytes!("../shaders/frag.spv");
+
+    fn reflect(bytes: &[u8]) -> ShaderReflection {
+        ShaderReflection::parse(&words_from_bytes(bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn vertex_shader() {
+        let refl = reflect(VERT_SPV);
+        assert_eq!(refl.entry_points.len(), 1);
+        assert_eq!(refl.entry_points[0].name, "main");
+        assert_eq!(refl.entry_points[0].execution_model, ExecutionModel::Vertex);
+        assert_eq!(refl.stage_flags(), vk::VK_SHADER_STAGE_VERTEX_BIT);
+
+        assert_eq!(refl.descriptor_bindings.len(), 1);
+        let ubo = &refl.descriptor_bindings[0];
+        assert_eq!((ubo.set, ubo.binding, ubo.count), (0, 0, 1));
+        assert_eq!(ubo.descriptor_type, vk::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
+        assert_eq!(ubo.name, "ubo");
+        let members: Vec<_> = ubo.block_members.iter()
+            .map(|m| (m.name.as_str(), m.offset, m.size))
+            .collect();
+        assert_eq!(members, [("model", 0, 64), ("view", 64, 64), ("proj", 128, 64)]);
+
+        let inputs: Vec<_> = refl.vertex_inputs.iter().map(|i| (i.location, i.format)).collect();
+        assert_eq!(inputs, [(0, vk::VK_FORMAT_R32G32B32_SFLOAT),
+            (1, vk::VK_FORMAT_R32G32B32_SFLOAT), (2, vk::VK_FORMAT_R32G32_SFLOAT)]);
+        assert!(refl.push_constant_ranges.is_empty());
+    }
+
+    #[test]
+    fn fragment_shader() {
+        let refl = reflect(FRAG_SPV);
+        assert_eq!(refl.stage_flags(), vk::VK_SHADER_STAGE_FRAGMENT_BIT);
+        assert_eq!(refl.descriptor_bindings.len(), 1);
+        let sampler = &refl.descriptor_bindings[0];
+        assert_eq!((sampler.set, sampler.binding), (0, 1));
+        assert_eq!(sampler.descriptor_type, vk::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER);
+        assert!(refl.vertex_inputs.is_empty());
+    }
+
+    #[test]
+    fn compute_shader() {
+        // A hand-assembled compute shader equivalent to:
+        //
+        //     layout(local_size_x = 8, local_size_y = 4) in;
+        //     layout(constant_id = 3) const uint N = 16;
+        //     layout(set = 1, binding = 2) buffer Data { float values[]; };
+        //     layout(push_constant) uniform Push { uint a; vec4 b; };
+        let words = [
+            MAGIC_NUMBER, 0x0001_0000, 0, 20, 0,
+            // OpEntryPoint GLCompute %1 "main"
+            5 << 16 | 15, 5, 1, 0x6e69_616d, 0,
+            // OpExecutionMode %1 LocalSize 8 4 1
+            6 << 16 | 16, 1, 17, 8, 4, 1,
+            // OpDecorate %2 SpecId 3
+            4 << 16 | 71, 2, 1, 3,
+            // OpDecorate %6 BufferBlock; OpMemberDecorate %6 0 Offset 0
+            3 << 16 | 71, 6, 3,
+            5 << 16 | 72, 6, 0, 35, 0,
+            // OpDecorate %8 DescriptorSet 1; OpDecorate %8 Binding 2
+            4 << 16 | 71, 8, 34, 1,
+            4 << 16 | 71, 8, 33, 2,
+            // OpMemberDecorate %11 0 Offset 0; OpMemberDecorate %11 1 Offset 16
+            5 << 16 | 72, 11, 0, 35, 0,
+            5 << 16 | 72, 11, 1, 35, 16,
+            // %3 = OpTypeInt 32 0; %4 = OpTypeFloat 32; %10 = OpTypeVector %4 4
+            4 << 16 | 21, 3, 32, 0,
+            3 << 16 | 22, 4, 32,
+            4 << 16 | 23, 10, 4, 4,
+            // %2 = OpSpecConstant %3 16
+            4 << 16 | 50, 3, 2, 16,
+            // %5 = OpTypeRuntimeArray %4; %6 = OpTypeStruct %5
+            3 << 16 | 29, 5, 4,
+            3 << 16 | 30, 6, 5,
+            // %7 = OpTypePointer Uniform %6; %8 = OpVariable %7 Uniform
+            4 << 16 | 32, 7, 2, 6,
+            4 << 16 | 59, 7, 8, 2,
+            // %11 = OpTypeStruct %3 %10; %12 = OpTypePointer PushConstant %11
+            4 << 16 | 30, 11, 3, 10,
+            4 << 16 | 32, 12, 9, 11,
+            // %13 = OpVariable %12 PushConstant
+            4 << 16 | 59, 12, 13, 9,
+        ];
+
+        let refl = ShaderReflection::parse(&words).unwrap();
+        assert_eq!(refl.entry_points[0].execution_model, ExecutionModel::GLCompute);
+        assert_eq!(refl.entry_point("main").unwrap().local_size, Some([8, 4, 1]));
+
+        let data = &refl.descriptor_bindings[0];
+        assert_eq!((data.set, data.binding, data.count), (1, 2, 1));
+        assert_eq!(data.descriptor_type, vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+        assert_eq!(data.stage_flags, vk::VK_SHADER_STAGE_COMPUTE_BIT);
+        assert_eq!(data.block_members, [BlockMemberInfo {
+            name: String::new(), offset: 0, size: 0,
+        }]);
+
+        assert_eq!(refl.push_constant_ranges, [PushConstantRangeInfo {
+            offset: 0, size: 32, stage_flags: vk::VK_SHADER_STAGE_COMPUTE_BIT,
+        }]);
+        assert_eq!(refl.specialization_constants.len(), 1);
+        assert_eq!(refl.specialization_constants[0].id, 3);
+    }
+
+    #[test]
+    fn block_layout() {
+        let refl = reflect(VERT_SPV);
+        let ubo = &refl.descriptor_bindings[0];
+        let member = |name: &str, offset, size| {
+            BlockMemberInfo { name: name.to_owned(), offset, size }
+        };
+
+        let mut host = vec![member("model", 0, 64), member("view", 64, 64),
+            member("proj", 128, 64)];
+        assert!(ubo.check_block_layout(&host).is_ok());
+        host[1].offset = 80;
+        assert!(ubo.check_block_layout(&host).is_err());
+        host[1].offset = 64;
+        host[2].size = 48;
+        assert!(ubo.check_block_layout(&host).is_err());
+        host.pop();
+        assert!(ubo.check_block_layout(&host).is_err());
+    }
+
+    #[test]
+    fn unsupported_and_mixed_stages() {
+        // A hand-assembled module with vertex and fragment entry points,
+        // each with a float input at location 0, an `f16vec2` vertex input
+        // and an acceleration structure, which is not modeled.
+        let words = [
+            MAGIC_NUMBER, 0x0001_0000, 0, 20, 0,
+            // OpEntryPoint Vertex %1 "main" %5 %13
+            7 << 16 | 15, 0, 1, 0x6e69_616d, 0, 5, 13,
+            // OpEntryPoint Fragment %2 "frag" %6
+            6 << 16 | 15, 4, 2, 0x6761_7266, 0, 6,
+            // OpDecorate %5 Location 0; OpDecorate %6 Location 0
+            4 << 16 | 71, 5, 30, 0,
+            4 << 16 | 71, 6, 30, 0,
+            // OpDecorate %13 Location 1
+            4 << 16 | 71, 13, 30, 1,
+            // OpDecorate %9 DescriptorSet 0; OpDecorate %9 Binding 0
+            4 << 16 | 71, 9, 34, 0,
+            4 << 16 | 71, 9, 33, 0,
+            // %3 = OpTypeFloat 32; %4 = OpTypePointer Input %3
+            3 << 16 | 22, 3, 32,
+            4 << 16 | 32, 4, 1, 3,
+            // %5 = OpVariable %4 Input; %6 = OpVariable %4 Input
+            4 << 16 | 59, 4, 5, 1,
+            4 << 16 | 59, 4, 6, 1,
+            // %7 = OpTypeAccelerationStructureKHR
+            2 << 16 | 5341, 7,
+            // %8 = OpTypePointer UniformConstant %7; %9 = OpVariable %8 UniformConstant
+            4 << 16 | 32, 8, 0, 7,
+            4 << 16 | 59, 8, 9, 0,
+            // %10 = OpTypeFloat 16; %11 = OpTypeVector %10 2
+            3 << 16 | 22, 10, 16,
+            4 << 16 | 23, 11, 10, 2,
+            // %12 = OpTypePointer Input %11; %13 = OpVariable %12 Input
+            4 << 16 | 32, 12, 1, 11,
+            4 << 16 | 59, 12, 13, 1,
+        ];
+
+        let refl = ShaderReflection::parse(&words).unwrap();
+        assert_eq!(refl.stage_flags(),
+            vk::VK_SHADER_STAGE_VERTEX_BIT | vk::VK_SHADER_STAGE_FRAGMENT_BIT);
+        let inputs: Vec<_> = refl.vertex_inputs.iter().map(|i| (i.location, i.format)).collect();
+        assert_eq!(inputs, [(0, vk::VK_FORMAT_R32_SFLOAT), (1, vk::VK_FORMAT_R16G16_SFLOAT)]);
+        assert!(refl.descriptor_bindings.is_empty());
+    }
+
+    #[test]
+    fn recursive_and_oversized_types() {
+        let mut module = Module::default();
+        module.types.insert(1, Type::Int { width: 32, signed: false });
+        module.constants.insert(2, vec![0x4000_0000]);
+        module.types.insert(3, Type::Array { element: 3, length: 2 });
+        module.types.insert(4, Type::Array { element: 1, length: 2 });
+        module.types.insert(5, Type::Struct { members: vec![5] });
+        module.types.insert(6, Type::Struct { members: vec![1, 4] });
+
+        assert!(module.type_size(3).is_err());
+        assert!(module.type_size(4).is_err());
+        assert!(module.block_members(5).is_err());
+        assert!(module.block_members(6).is_err());
+        assert!(module.vertex_formats(3).is_err());
+        assert!(module.vertex_formats(4).is_err());
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let words = [MAGIC_NUMBER, 0x0001_0000, 0, 1, 0, 4 << 16 | 21, 3];
+        assert!(validate(&words).is_err());
+        assert!(ShaderReflection::parse(&words).is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0001_0000, 0, 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn invalid_ids() {
+        let module = |instructions: &[u32]| {
+            let mut words = vec![MAGIC_NUMBER, 0x0001_0000, 0, 10, 0];
+            words.extend_from_slice(instructions);
+            words
+        };
+
+        // %12 = OpTypeBool, beyond the bound of 10.
+        assert!(validate(&module(&[2 << 16 | 20, 12])).is_err());
+        // %3 = OpTypeBool twice.
+        assert!(validate(&module(&[2 << 16 | 20, 3, 2 << 16 | 20, 3])).is_err());
+        // %3 = OpTypeArray %3 %2
+        assert!(validate(&module(&[4 << 16 | 28, 3, 3, 2])).is_err());
+        // %3 = OpTypeVector %4 2 before and after %4 = OpTypeFloat 32.
+        assert!(validate(&module(&[4 << 16 | 23, 3, 4, 2, 3 << 16 | 22, 4, 32])).is_err());
+        assert!(validate(&module(&[3 << 16 | 22, 4, 32, 4 << 16 | 23, 3, 4, 2])).is_ok());
+        // OpTypeForwardPointer %5 PhysicalStorageBuffer; %6 = OpTypeStruct %5;
+        // %5 = OpTypePointer PhysicalStorageBuffer %6
+        assert!(validate(&module(&[3 << 16 | 39, 5, 5349, 3 << 16 | 30, 6, 5,
+            4 << 16 | 32, 5, 5349, 6])).is_ok());
+        // OpDecorate %0 Location 0
+        assert!(validate(&module(&[4 << 16 | 71, 0, 30, 0])).is_err());
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(words_from_bytes(&[]).is_err());
+        assert!(words_from_bytes(&VERT_SPV[..VERT_SPV.len() - 1]).is_err());
+        assert!(words_from_bytes(b"#version 450\n\0\0\0\0\0\0\0\0").is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0001_0000, 0, 1]).is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0002_0000, 0, 1, 0]).is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0001_0001, 0, 1, 0]).is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0001_0000, 0, 0, 0]).is_err());
+        assert!(validate(&[MAGIC_NUMBER, 0x0001_0000, 0, 1, 0]).is_ok());
+        assert_eq!(version(&words_from_bytes(VERT_SPV).unwrap()), (1, 0));
+    }
+
+    #[test]
+    fn byte_swapped() {
+        let swapped: Vec<u8> = VERT_SPV.chunks(4).flat_map(|w| w.iter().rev().cloned()).collect();
+        assert_eq!(words_from_bytes(&swapped).unwrap(), words_from_bytes(VERT_SPV).unwrap());
+        assert_eq!(reflect(&swapped).vertex_inputs.len(), 3);
+    }
+}
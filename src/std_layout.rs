@@ -0,0 +1,430 @@
+//! std140 and std430 representations of host types.
+//!
+//! Shader blocks use the std140 (uniform buffers) or std430 (storage buffers
+//! and push constants) rules, which align `vec3`s to 16 bytes and, for
+//! std140, round array strides and struct alignments up to 16 bytes.
+//! `AsStd140` and `AsStd430` convert a value into a padded representation
+//! which can be copied into a buffer as is. Derive them for structs with
+//! `#[derive(AsStd140, AsStd430)]`.
+//!
+//! Following the rest of the crate, `[f32; N]` is a `vecN` and `[[f32; R]; C]`
+//! is a column-major `matCxR`. Arrays of vectors, matrices and structs map to
+//! shader arrays but arrays of scalars can not be represented, since they
+//! would be indistinguishable from vectors.
+
+use std::any;
+use std::mem;
+use spirv::BlockMemberInfo;
+use ::{VkcResult, Pod, ShaderModule};
+
+
+/// Pads `T` to a multiple of 16 bytes, as required of array elements with
+/// std140 and of `vec3` array elements with std430.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C, align(16))]
+pub struct Align16<T>(pub T);
+
+
+/// A type with a std140 representation.
+pub trait AsStd140 {
+    /// The padded representation of this type.
+    type Std140: Pod;
+    /// The representation of this type as an array element, padded to the
+    /// array stride.
+    type Std140Element: Pod;
+    /// The base alignment in bytes.
+    const STD140_ALIGNMENT: usize;
+    /// The size in bytes.
+    const STD140_SIZE: usize = mem::size_of::<Self::Std140>();
+
+    fn as_std140(&self) -> Self::Std140;
+
+    fn as_std140_element(&self) -> Self::Std140Element;
+
+    /// Returns the members of a block whose contents are this type, for
+    /// checking against reflection data with
+    /// `DescriptorBindingInfo::check_block_layout`.
+    fn std140_members() -> Vec<BlockMemberInfo> {
+        let size = Self::STD140_SIZE as u32;
+        vec![BlockMemberInfo { name: String::new(), offset: 0, size }]
+    }
+}
+
+/// A type with a std430 representation.
+pub trait AsStd430 {
+    /// The padded representation of this type.
+    type Std430: Pod;
+    /// The representation of this type as an array element, padded to the
+    /// array stride.
+    type Std430Element: Pod;
+    /// The base alignment in bytes.
+    const STD430_ALIGNMENT: usize;
+    /// The size in bytes.
+    const STD430_SIZE: usize = mem::size_of::<Self::Std430>();
+
+    fn as_std430(&self) -> Self::Std430;
+
+    fn as_std430_element(&self) -> Self::Std430Element;
+
+    /// Returns the members of a block whose contents are this type, for
+    /// checking against reflection data with
+    /// `DescriptorBindingInfo::check_block_layout`.
+    fn std430_members() -> Vec<BlockMemberInfo> {
+        let size = Self::STD430_SIZE as u32;
+        vec![BlockMemberInfo { name: String::new(), offset: 0, size }]
+    }
+}
+
+/// The host-side layout of the uniform or storage buffer block at a set and
+/// binding, checked against the shader stages when a pipeline is created.
+#[derive(Debug, Clone)]
+pub struct BlockLayout {
+    set: u32,
+    binding: u32,
+    type_name: &'static str,
+    members: Vec<BlockMemberInfo>,
+}
+
+impl BlockLayout {
+    /// The layout of `T::Std140`.
+    pub fn std140<T: AsStd140>(set: u32, binding: u32) -> BlockLayout {
+        BlockLayout {
+            set,
+            binding,
+            type_name: any::type_name::<T::Std140>(),
+            members: T::std140_members(),
+        }
+    }
+
+    /// The layout of `T::Std430`.
+    pub fn std430<T: AsStd430>(set: u32, binding: u32) -> BlockLayout {
+        BlockLayout {
+            set,
+            binding,
+            type_name: any::type_name::<T::Std430>(),
+            members: T::std430_members(),
+        }
+    }
+
+    /// Checks this layout against the reflection data of every module which
+    /// declares the block. Errors are prefixed with `caller`.
+    pub fn check<'m, I>(&self, caller: &str, modules: I) -> VkcResult<()>
+            where I: IntoIterator<Item = &'m ShaderModule>
+    {
+        let mut declared = false;
+        for module in modules {
+            let bindings = match module.reflection() {
+                Some(reflection) => &reflection.descriptor_bindings,
+                // The block can only be checked when reflection is available.
+                None => {
+                    declared = true;
+                    continue;
+                },
+            };
+            for binding in bindings.iter()
+                    .filter(|b| b.set == self.set && b.binding == self.binding)
+            {
+                declared = true;
+                binding.check_block_layout(&self.members).map_err(|err| {
+                    err.chain(format!("{}: The layout of '{}' does not match the shader.",
+                        caller, self.type_name))
+                })?;
+            }
+        }
+        if !declared {
+            return Err(format!("{}: No stage declares a block at set {}, binding {} for '{}'.",
+                caller, self.set, self.binding, self.type_name).into());
+        }
+        Ok(())
+    }
+}
+
+/// Marks types which can be the elements of an array implementing
+/// `AsStd140`: vectors, matrices, arrays and derived structs.
+pub trait Std140ArrayElement: AsStd140 {}
+
+/// Marks types which can be the elements of an array implementing
+/// `AsStd430`: vectors, matrices, arrays and derived structs.
+pub trait Std430ArrayElement: AsStd430 {}
+
+
+macro_rules! impl_std_layout_scalar {
+    ($ty:ty, $repr:ty) => {
+        impl AsStd140 for $ty {
+            type Std140 = $repr;
+            type Std140Element = Align16<$repr>;
+            const STD140_ALIGNMENT: usize = 4;
+
+            fn as_std140(&self) -> $repr {
+                From::from(*self)
+            }
+
+            fn as_std140_element(&self) -> Align16<$repr> {
+                Align16(self.as_std140())
+            }
+        }
+
+        impl AsStd430 for $ty {
+            type Std430 = $repr;
+            type Std430Element = $repr;
+            const STD430_ALIGNMENT: usize = 4;
+
+            fn as_std430(&self) -> $repr {
+                From::from(*self)
+            }
+
+            fn as_std430_element(&self) -> $repr {
+                self.as_std430()
+            }
+        }
+    };
+}
+
+macro_rules! impl_std_layout_vectors {
+    ($scalar:ty) => {
+        impl_std_layout_scalar!($scalar, $scalar);
+
+        impl_std_layout_vector!([$scalar; 2], 8, Align16<[$scalar; 2]>, [$scalar; 2]);
+        impl_std_layout_vector!([$scalar; 3], 16, Align16<[$scalar; 3]>, Align16<[$scalar; 3]>);
+        impl_std_layout_vector!([$scalar; 4], 16, [$scalar; 4], [$scalar; 4]);
+    };
+}
+
+macro_rules! impl_std_layout_vector {
+    ($ty:ty, $align:expr, $std140_element:ty, $std430_element:ty) => {
+        impl AsStd140 for $ty {
+            type Std140 = $ty;
+            type Std140Element = $std140_element;
+            const STD140_ALIGNMENT: usize = $align;
+
+            fn as_std140(&self) -> $ty {
+                *self
+            }
+
+            fn as_std140_element(&self) -> $std140_element {
+                From::from(*self)
+            }
+        }
+
+        impl AsStd430 for $ty {
+            type Std430 = $ty;
+            type Std430Element = $std430_element;
+            const STD430_ALIGNMENT: usize = $align;
+
+            fn as_std430(&self) -> $ty {
+                *self
+            }
+
+            fn as_std430_element(&self) -> $std430_element {
+                From::from(*self)
+            }
+        }
+
+        impl Std140ArrayElement for $ty {}
+        impl Std430ArrayElement for $ty {}
+    };
+}
+
+unsafe impl<T: Pod> Pod for Align16<T> {}
+
+impl<T> From<T> for Align16<T> {
+    fn from(value: T) -> Align16<T> {
+        Align16(value)
+    }
+}
+
+impl_std_layout_vectors!(f32);
+impl_std_layout_vectors!(i32);
+impl_std_layout_vectors!(u32);
+// A GLSL `bool` is 32 bits.
+impl_std_layout_scalar!(bool, u32);
+
+
+impl<T: Std140ArrayElement, const N: usize> AsStd140 for [T; N] {
+    type Std140 = [T::Std140Element; N];
+    // The stride is a multiple of 16 bytes so no further padding is needed.
+    type Std140Element = [T::Std140Element; N];
+    const STD140_ALIGNMENT: usize = round_up(T::STD140_ALIGNMENT, 16);
+
+    fn as_std140(&self) -> Self::Std140 {
+        ::std::array::from_fn(|i| self[i].as_std140_element())
+    }
+
+    fn as_std140_element(&self) -> Self::Std140Element {
+        self.as_std140()
+    }
+}
+
+impl<T: Std430ArrayElement, const N: usize> AsStd430 for [T; N] {
+    type Std430 = [T::Std430Element; N];
+    // The stride is a multiple of the element alignment so no further
+    // padding is needed.
+    type Std430Element = [T::Std430Element; N];
+    const STD430_ALIGNMENT: usize = T::STD430_ALIGNMENT;
+
+    fn as_std430(&self) -> Self::Std430 {
+        ::std::array::from_fn(|i| self[i].as_std430_element())
+    }
+
+    fn as_std430_element(&self) -> Self::Std430Element {
+        self.as_std430()
+    }
+}
+
+impl<T: Std140ArrayElement, const N: usize> Std140ArrayElement for [T; N] {}
+impl<T: Std430ArrayElement, const N: usize> Std430ArrayElement for [T; N] {}
+
+
+const fn round_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Returns the alignment of a struct whose members have `alignments`,
+/// which is at least `min_alignment`.
+#[doc(hidden)]
+pub const fn struct_alignment<const N: usize>(alignments: [usize; N], min_alignment: usize)
+        -> usize
+{
+    let mut alignment = 1;
+    let mut i = 0;
+    while i < N {
+        if alignments[i] > alignment { alignment = alignments[i]; }
+        i += 1;
+    }
+    round_up(alignment, min_alignment)
+}
+
+/// Returns the number of padding bytes before member `index` of a struct
+/// whose members have `alignments` and `sizes`.
+#[doc(hidden)]
+pub const fn member_padding<const N: usize>(alignments: [usize; N], sizes: [usize; N],
+        index: usize) -> usize
+{
+    let mut end = 0;
+    let mut i = 0;
+    while i < index {
+        end = round_up(end, alignments[i]) + sizes[i];
+        i += 1;
+    }
+    round_up(end, alignments[index]) - end
+}
+
+/// Returns the number of padding bytes after the last member of a struct
+/// whose members have `alignments` and `sizes`, rounding its size up to a
+/// multiple of `struct_alignment`.
+#[doc(hidden)]
+pub const fn trailing_padding<const N: usize>(alignments: [usize; N], sizes: [usize; N],
+        struct_alignment: usize) -> usize
+{
+    let mut end = 0;
+    let mut i = 0;
+    while i < N {
+        end = round_up(end, alignments[i]) + sizes[i];
+        i += 1;
+    }
+    round_up(end, struct_alignment) - end
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use ::{AsStd140, AsStd430};
+
+    #[derive(Clone, Copy, AsStd140, AsStd430)]
+    struct Light {
+        position: [f32; 3],
+        intensity: f32,
+        color: [f32; 3],
+    }
+
+    #[derive(Clone, Copy, AsStd140, AsStd430)]
+    struct Scene {
+        flag: bool,
+        offsets: [[f32; 2]; 3],
+        light: Light,
+        transform: [[f32; 3]; 3],
+        count: u32,
+    }
+
+    fn offsets_and_sizes(members: Vec<::spirv::BlockMemberInfo>) -> Vec<(String, u32, u32)> {
+        members.into_iter().map(|m| (m.name, m.offset, m.size)).collect()
+    }
+
+    #[test]
+    fn vectors_and_arrays() {
+        assert_eq!(mem::size_of::<<[f32; 3] as AsStd140>::Std140Element>(), 16);
+        assert_eq!(mem::size_of::<<[f32; 2] as AsStd140>::Std140Element>(), 16);
+        assert_eq!(mem::size_of::<<[f32; 2] as AsStd430>::Std430Element>(), 8);
+        assert_eq!(<[[f32; 4]; 4] as AsStd140>::STD140_SIZE, 64);
+        assert_eq!(<[[f32; 3]; 3] as AsStd140>::STD140_SIZE, 48);
+        assert_eq!(<[[f32; 2]; 2] as AsStd140>::STD140_SIZE, 32);
+        assert_eq!(<[[f32; 2]; 2] as AsStd430>::STD430_SIZE, 16);
+
+        let mat = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+        let std140 = mat.as_std140();
+        assert_eq!(std140[1].0, [4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn std140() {
+        assert_eq!(offsets_and_sizes(Light::std140_members()), vec![
+            ("position".to_string(), 0, 12),
+            ("intensity".to_string(), 12, 4),
+            ("color".to_string(), 16, 12),
+        ]);
+        assert_eq!(Light::STD140_ALIGNMENT, 16);
+        assert_eq!(Light::STD140_SIZE, 32);
+
+        assert_eq!(offsets_and_sizes(Scene::std140_members()), vec![
+            ("flag".to_string(), 0, 4),
+            ("offsets".to_string(), 16, 48),
+            ("light".to_string(), 64, 32),
+            ("transform".to_string(), 96, 48),
+            ("count".to_string(), 144, 4),
+        ]);
+        assert_eq!(Scene::STD140_SIZE, 160);
+    }
+
+    #[test]
+    fn std430() {
+        assert_eq!(offsets_and_sizes(Light::std430_members()), vec![
+            ("position".to_string(), 0, 12),
+            ("intensity".to_string(), 12, 4),
+            ("color".to_string(), 16, 12),
+        ]);
+        assert_eq!(Light::STD430_ALIGNMENT, 16);
+        assert_eq!(Light::STD430_SIZE, 32);
+
+        assert_eq!(offsets_and_sizes(Scene::std430_members()), vec![
+            ("flag".to_string(), 0, 4),
+            ("offsets".to_string(), 8, 24),
+            ("light".to_string(), 32, 32),
+            ("transform".to_string(), 64, 48),
+            ("count".to_string(), 112, 4),
+        ]);
+        assert_eq!(Scene::STD430_SIZE, 128);
+    }
+
+    #[test]
+    fn values() {
+        let light = Light { position: [1.0, 2.0, 3.0], intensity: 0.5, color: [0.1, 0.2, 0.3] };
+        let std140 = light.as_std140();
+        assert_eq!(std140.position, [1.0, 2.0, 3.0]);
+        assert_eq!(std140.intensity, 0.5);
+        assert_eq!(std140.color, [0.1, 0.2, 0.3]);
+
+        let scene = Scene {
+            flag: true,
+            offsets: [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]],
+            light,
+            transform: [[0.0; 3]; 3],
+            count: 7,
+        };
+        let std430 = scene.as_std430();
+        assert_eq!(std430.flag, 1);
+        assert_eq!(std430.offsets[2], [5.0, 6.0]);
+        assert_eq!(std430.light.color, [0.1, 0.2, 0.3]);
+        assert_eq!(std430.count, 7);
+    }
+}
@@ -20,23 +20,131 @@ pub struct Surface {
     inner: Arc<Inner>,
 }
 
-impl Surface {
-    pub fn new(instance: Instance, window: &winit::Window) -> VkcResult<Surface> {
-        use winit::os::windows::WindowExt;
-        let mut handle = 0;
+pub static SURFACE_EXTENSION: &str = "VK_KHR_surface";
+pub static WIN32_SURFACE_EXTENSION: &str = "VK_KHR_win32_surface";
+pub static XLIB_SURFACE_EXTENSION: &str = "VK_KHR_xlib_surface";
+pub static XCB_SURFACE_EXTENSION: &str = "VK_KHR_xcb_surface";
+pub static WAYLAND_SURFACE_EXTENSION: &str = "VK_KHR_wayland_surface";
+
+
+/// Returns the platform-specific instance extension needed to create a
+/// surface for `window` (in addition to `VK_KHR_surface`).
+#[cfg(windows)]
+pub fn window_surface_extension(_window: &winit::Window) -> &'static str {
+    WIN32_SURFACE_EXTENSION
+}
+
+/// Returns the platform-specific instance extension needed to create a
+/// surface for `window` (in addition to `VK_KHR_surface`).
+///
+/// Windows using winit's Wayland backend require `VK_KHR_wayland_surface`
+/// and all others `VK_KHR_xlib_surface`.
+#[cfg(all(unix, not(target_os = "android"), not(target_os = "macos"), not(target_os = "ios")))]
+pub fn window_surface_extension(window: &winit::Window) -> &'static str {
+    use winit::os::unix::WindowExt;
+    if window.get_wayland_surface().is_some() {
+        WAYLAND_SURFACE_EXTENSION
+    } else {
+        XLIB_SURFACE_EXTENSION
+    }
+}
+
+#[cfg(windows)]
+unsafe fn create_surface(instance: &Instance, window: &winit::Window)
+        -> VkcResult<vks::khr_surface::VkSurfaceKHR>
+{
+    use winit::os::windows::WindowExt;
+    let mut handle = 0;
 
-        let create_info = vks::khr_win32_surface::VkWin32SurfaceCreateInfoKHR {
-            sType: vk::VK_STRUCTURE_TYPE_WIN32_SURFACE_CREATE_INFO_KHR,
+    let create_info = vks::khr_win32_surface::VkWin32SurfaceCreateInfoKHR {
+        sType: vk::VK_STRUCTURE_TYPE_WIN32_SURFACE_CREATE_INFO_KHR,
+        pNext: ptr::null(),
+        flags: 0,
+        hinstance: ptr::null_mut(),
+        hwnd: window.get_hwnd() as *mut vks::win32_types::HWND_T,
+    };
+
+    ::check(instance.vk().khr_win32_surface.vkCreateWin32SurfaceKHR(instance.handle(), &create_info, ptr::null(),
+        &mut handle))?;
+    Ok(handle)
+}
+
+/// Creates a Wayland, Xlib or XCB surface depending on the winit backend in
+/// use and the extensions enabled on `instance`. X11 windows use Xlib unless
+/// only `VK_KHR_xcb_surface` is enabled.
+#[cfg(all(unix, not(target_os = "android"), not(target_os = "macos"), not(target_os = "ios")))]
+unsafe fn create_surface(instance: &Instance, window: &winit::Window)
+        -> VkcResult<vks::khr_surface::VkSurfaceKHR>
+{
+    use winit::os::unix::WindowExt;
+    let mut handle = 0;
+
+    let wayland_handles = (window.get_wayland_display(), window.get_wayland_surface());
+    if let (Some(display), Some(surface)) = wayland_handles {
+        if !instance.is_extension_enabled(WAYLAND_SURFACE_EXTENSION) {
+            return Err(format!("Unable to create a Wayland surface: '{}' is not enabled.",
+                WAYLAND_SURFACE_EXTENSION).into());
+        }
+
+        let create_info = vks::khr_wayland_surface::VkWaylandSurfaceCreateInfoKHR {
+            sType: vk::VK_STRUCTURE_TYPE_WAYLAND_SURFACE_CREATE_INFO_KHR,
             pNext: ptr::null(),
             flags: 0,
-            hinstance: ptr::null_mut(),
-            hwnd: window.get_hwnd() as *mut vks::win32_types::HWND_T,
+            display: display as *mut _,
+            surface: surface as *mut _,
         };
 
-        unsafe {
-            ::check(instance.vk().khr_win32_surface.vkCreateWin32SurfaceKHR(instance.handle(), &create_info, ptr::null(),
-                &mut handle));
-        }
+        ::check(instance.vk().khr_wayland_surface.vkCreateWaylandSurfaceKHR(instance.handle(),
+            &create_info, ptr::null(), &mut handle))?;
+        return Ok(handle);
+    }
+
+    let (display, window_id) = match (window.get_xlib_display(), window.get_xlib_window()) {
+        (Some(display), Some(window_id)) => (display, window_id),
+        _ => return Err("Unable to create a surface: unsupported window backend.".into()),
+    };
+
+    if instance.is_extension_enabled(XLIB_SURFACE_EXTENSION) {
+        let create_info = vks::khr_xlib_surface::VkXlibSurfaceCreateInfoKHR {
+            sType: vk::VK_STRUCTURE_TYPE_XLIB_SURFACE_CREATE_INFO_KHR,
+            pNext: ptr::null(),
+            flags: 0,
+            dpy: display as *mut _,
+            window: window_id as _,
+        };
+
+        ::check(instance.vk().khr_xlib_surface.vkCreateXlibSurfaceKHR(instance.handle(),
+            &create_info, ptr::null(), &mut handle))?;
+    } else if instance.is_extension_enabled(XCB_SURFACE_EXTENSION) {
+        let connection = window.get_xcb_connection()
+            .ok_or("Unable to create an XCB surface: no XCB connection available.")?;
+
+        let create_info = vks::khr_xcb_surface::VkXcbSurfaceCreateInfoKHR {
+            sType: vk::VK_STRUCTURE_TYPE_XCB_SURFACE_CREATE_INFO_KHR,
+            pNext: ptr::null(),
+            flags: 0,
+            connection: connection as *mut _,
+            window: window_id as _,
+        };
+
+        ::check(instance.vk().khr_xcb_surface.vkCreateXcbSurfaceKHR(instance.handle(),
+            &create_info, ptr::null(), &mut handle))?;
+    } else {
+        return Err(format!("Unable to create an X11 surface: neither '{}' nor '{}' is enabled.",
+            XLIB_SURFACE_EXTENSION, XCB_SURFACE_EXTENSION).into());
+    }
+    Ok(handle)
+}
+
+
+impl Surface {
+    /// Creates a surface for `window`.
+    ///
+    /// `instance` must have `VK_KHR_surface` and the extension returned by
+    /// `window_surface_extension` enabled (see
+    /// `InstanceBuilder::surface_extensions_for`).
+    pub fn new(instance: Instance, window: &winit::Window) -> VkcResult<Surface> {
+        let handle = unsafe { create_surface(&instance, window)? };
 
         Ok(Surface {
             inner: Arc::new(Inner {
@@ -55,7 +163,7 @@ impl Surface {
 impl Drop for Inner {
     fn drop(&mut self) {
         unsafe {
-            println!("Destroying surface...");
+            trace!("Destroying surface...");
             self.instance.vk().khr_surface.vkDestroySurfaceKHR(self.instance.handle(), self.handle, ptr::null());
         }
     }
@@ -17,41 +17,41 @@ pub struct SwapchainSupportDetails {
 
 impl SwapchainSupportDetails {
     pub fn new(instance: &Instance, surface: &Surface, physical_device: vk::VkPhysicalDevice)
-            -> SwapchainSupportDetails
+            -> VkcResult<SwapchainSupportDetails>
     {
         unsafe {
             // Capabilities:
             let mut capabilities: vks::khr_surface::VkSurfaceCapabilitiesKHR = mem::uninitialized();
-            instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceCapabilitiesKHR(
-                physical_device, surface.handle(), &mut capabilities);
+            ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceCapabilitiesKHR(
+                physical_device, surface.handle(), &mut capabilities))?;
 
             // Formats:
             let mut format_count = 0u32;
-            instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device,
-                surface.handle(), &mut format_count, ptr::null_mut());
+            ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device,
+                surface.handle(), &mut format_count, ptr::null_mut()))?;
             let mut formats: Vec<vks::khr_surface::VkSurfaceFormatKHR> = Vec::with_capacity(format_count as usize);
             formats.set_len(format_count as usize);
             if format_count != 0 {
-                instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device,
-                    surface.handle(), &mut format_count, formats.as_mut_ptr());
+                ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfaceFormatsKHR(physical_device,
+                    surface.handle(), &mut format_count, formats.as_mut_ptr()))?;
             }
 
             // Present Modes:
             let mut present_mode_count = 0u32;
-            instance.vk().khr_surface.vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device,
-                surface.handle(), &mut present_mode_count, ptr::null_mut());
+            ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device,
+                surface.handle(), &mut present_mode_count, ptr::null_mut()))?;
             let mut present_modes: Vec<vks::khr_surface::VkPresentModeKHR> = Vec::with_capacity(present_mode_count as usize);
             present_modes.set_len(present_mode_count as usize);
             if present_mode_count != 0 {
-                instance.vk().khr_surface.vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device,
-                    surface.handle(), &mut present_mode_count, present_modes.as_mut_ptr());
+                ::check(instance.vk().khr_surface.vkGetPhysicalDeviceSurfacePresentModesKHR(physical_device,
+                    surface.handle(), &mut present_mode_count, present_modes.as_mut_ptr()))?;
             }
 
-            SwapchainSupportDetails {
+            Ok(SwapchainSupportDetails {
                 capabilities,
                 formats,
                 present_modes,
-            }
+            })
         }
     }
 }
@@ -134,7 +134,7 @@ impl Swapchain {
             window_size: Option<vk::VkExtent2D>, old_swapchain: Option<Swapchain>) -> VkcResult<Swapchain>
     {
         let swapchain_details: SwapchainSupportDetails = SwapchainSupportDetails::new(device.instance(),
-            &surface, device.physical_device());
+            &surface, device.physical_device().handle())?;
         let surface_format = choose_swap_surface_format(&swapchain_details.formats);
         let present_mode = choose_swap_present_mode(&swapchain_details.present_modes);
         let extent = choose_swap_extent(&swapchain_details.capabilities, window_size);
@@ -147,8 +147,10 @@ impl Swapchain {
             image_count = swapchain_details.capabilities.maxImageCount;
         }
 
-        let indices = queue::queue_families(device.instance(), &surface, device.physical_device(), queue_flags);
-        let queue_family_indices = [indices.flag_idxs[0] as u32, indices.presentation_support_idxs[0] as u32];
+        let indices = queue::queue_families(device.instance(), Some(&surface), device.physical_device().handle(), queue_flags)?;
+        let present_family_idx = indices.presentation_support_idxs.first().map(|&idx| idx as u32)
+            .ok_or("No queue family supports presentation to the surface.")?;
+        let queue_family_indices = [indices.first_family_idx()?, present_family_idx];
 
         let (image_sharing_mode, queue_family_index_count, p_queue_family_indices);
         if queue_family_indices[0] != queue_family_indices[1] {
@@ -185,17 +187,16 @@ impl Swapchain {
         };
 
         let mut handle = 0;
-        let res = unsafe { device.vk().vkCreateSwapchainKHR(device.handle(), &create_info, ptr::null(), &mut handle) };
-        if res != vk::VK_SUCCESS {
-            panic!("failed to create swap chain!");
+        unsafe {
+            ::check(device.vk().vkCreateSwapchainKHR(device.handle(), &create_info, ptr::null(), &mut handle))?;
         }
 
         let mut image_count = 0;
         let mut images = SmallVec::new();
         unsafe {
-            ::check(device.vk().vkGetSwapchainImagesKHR(device.handle(), handle, &mut image_count, ptr::null_mut()));
+            ::check(device.vk().vkGetSwapchainImagesKHR(device.handle(), handle, &mut image_count, ptr::null_mut()))?;
             images.set_len(image_count as usize);
-            ::check(device.vk().vkGetSwapchainImagesKHR(device.handle(), handle, &mut image_count, images.as_mut_ptr()));
+            ::check(device.vk().vkGetSwapchainImagesKHR(device.handle(), handle, &mut image_count, images.as_mut_ptr()))?;
         }
 
         Ok(Swapchain {
@@ -10,24 +10,21 @@ use ::{VkcResult, Device};
 
 pub fn read_file<P: AsRef<Path>>(file: P) -> VkcResult<Vec<u8>> {
     let file_name = file.as_ref().display().to_string();
-    let f = File::open(file).expect("shader file not found");
-    let file_bytes = f.metadata().unwrap().len() as usize;
+    let f = File::open(file)?;
+    // Only a hint: the size reported may differ from what is read, as with
+    // procfs files or files changing while being read.
+    let file_bytes = f.metadata()?.len() as usize;
     let mut contents = Vec::<u8>::with_capacity(file_bytes);
     let mut reader = BufReader::new(f);
-    match reader.read_to_end(&mut contents) {
-        Ok(bytes) => {
-            assert_eq!(bytes, file_bytes);
-            println!("Read {} bytes from {}", bytes, &file_name);
-        },
-        Err(e) => panic!("{}", e),
-    }
+    let bytes = reader.read_to_end(&mut contents)?;
+    debug!("Read {} bytes from {}", bytes, &file_name);
     Ok(contents)
 }
 
 pub fn file_reader<P: AsRef<Path>>(file: P) -> VkcResult<BufReader<File>> {
     let file_name = file.as_ref().display().to_string();
-    let f = File::open(file).expect("file not found");
-    let file_bytes = f.metadata().unwrap().len() as usize;
+    let f = File::open(file)?;
+    let file_bytes = f.metadata()?.len() as usize;
     // let mut contents = Vec::<u8>::with_capacity(file_bytes);
     Ok(BufReader::new(f))
 }
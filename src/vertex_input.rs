@@ -0,0 +1,180 @@
+use std::mem;
+use vk;
+
+
+/// A type which can be read from a vertex buffer.
+///
+/// Usually implemented with `#[derive(VertexInput)]`, which creates one
+/// attribute per field using each field type's `VertexFormat`.
+pub trait VertexInput: Sized {
+    /// Returns the attributes read from each element of `binding`.
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VkVertexInputAttributeDescription>;
+
+    /// Returns a binding whose elements are tightly packed values of this
+    /// type.
+    ///
+    /// * VERTEX_INPUT_RATE_VERTEX: Move to the next data entry after each
+    ///   vertex.
+    /// * VERTEX_INPUT_RATE_INSTANCE: Move to the next data entry after each
+    ///   instance.
+    fn binding_description(binding: u32, input_rate: vk::VkVertexInputRate)
+            -> vk::VkVertexInputBindingDescription
+    {
+        vk::VkVertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<Self>() as u32,
+            inputRate: input_rate,
+        }
+    }
+}
+
+
+/// A field type which can be read as a vertex attribute.
+pub trait VertexFormat {
+    /// The format of each location.
+    const FORMAT: vk::VkFormat;
+    /// The number of consecutive locations used, one per column for
+    /// matrices.
+    const LOCATIONS: u32 = 1;
+}
+
+/// An integer field type which can be read as normalized floats, selected
+/// with `#[vertex(normalized)]`.
+pub trait NormalizedVertexFormat {
+    const NORMALIZED_FORMAT: vk::VkFormat;
+}
+
+macro_rules! impl_vertex_format {
+    ($ty:ty, $format:ident) => {
+        impl VertexFormat for $ty {
+            const FORMAT: vk::VkFormat = vk::$format;
+        }
+    };
+    ($ty:ty, $format:ident, $columns:expr) => {
+        impl VertexFormat for $ty {
+            const FORMAT: vk::VkFormat = vk::$format;
+            const LOCATIONS: u32 = $columns;
+        }
+    };
+}
+
+macro_rules! impl_normalized_vertex_format {
+    ($ty:ty, $format:ident) => {
+        impl NormalizedVertexFormat for $ty {
+            const NORMALIZED_FORMAT: vk::VkFormat = vk::$format;
+        }
+    };
+}
+
+impl_vertex_format!(f32, VK_FORMAT_R32_SFLOAT);
+impl_vertex_format!([f32; 1], VK_FORMAT_R32_SFLOAT);
+impl_vertex_format!([f32; 2], VK_FORMAT_R32G32_SFLOAT);
+impl_vertex_format!([f32; 3], VK_FORMAT_R32G32B32_SFLOAT);
+impl_vertex_format!([f32; 4], VK_FORMAT_R32G32B32A32_SFLOAT);
+impl_vertex_format!([[f32; 2]; 2], VK_FORMAT_R32G32_SFLOAT, 2);
+impl_vertex_format!([[f32; 3]; 3], VK_FORMAT_R32G32B32_SFLOAT, 3);
+impl_vertex_format!([[f32; 4]; 4], VK_FORMAT_R32G32B32A32_SFLOAT, 4);
+
+impl_vertex_format!(u32, VK_FORMAT_R32_UINT);
+impl_vertex_format!([u32; 1], VK_FORMAT_R32_UINT);
+impl_vertex_format!([u32; 2], VK_FORMAT_R32G32_UINT);
+impl_vertex_format!([u32; 3], VK_FORMAT_R32G32B32_UINT);
+impl_vertex_format!([u32; 4], VK_FORMAT_R32G32B32A32_UINT);
+impl_vertex_format!(i32, VK_FORMAT_R32_SINT);
+impl_vertex_format!([i32; 1], VK_FORMAT_R32_SINT);
+impl_vertex_format!([i32; 2], VK_FORMAT_R32G32_SINT);
+impl_vertex_format!([i32; 3], VK_FORMAT_R32G32B32_SINT);
+impl_vertex_format!([i32; 4], VK_FORMAT_R32G32B32A32_SINT);
+
+impl_vertex_format!(u16, VK_FORMAT_R16_UINT);
+impl_vertex_format!([u16; 1], VK_FORMAT_R16_UINT);
+impl_vertex_format!([u16; 2], VK_FORMAT_R16G16_UINT);
+impl_vertex_format!([u16; 4], VK_FORMAT_R16G16B16A16_UINT);
+impl_vertex_format!(i16, VK_FORMAT_R16_SINT);
+impl_vertex_format!([i16; 1], VK_FORMAT_R16_SINT);
+impl_vertex_format!([i16; 2], VK_FORMAT_R16G16_SINT);
+impl_vertex_format!([i16; 4], VK_FORMAT_R16G16B16A16_SINT);
+
+impl_vertex_format!(u8, VK_FORMAT_R8_UINT);
+impl_vertex_format!([u8; 1], VK_FORMAT_R8_UINT);
+impl_vertex_format!([u8; 2], VK_FORMAT_R8G8_UINT);
+impl_vertex_format!([u8; 4], VK_FORMAT_R8G8B8A8_UINT);
+impl_vertex_format!(i8, VK_FORMAT_R8_SINT);
+impl_vertex_format!([i8; 1], VK_FORMAT_R8_SINT);
+impl_vertex_format!([i8; 2], VK_FORMAT_R8G8_SINT);
+impl_vertex_format!([i8; 4], VK_FORMAT_R8G8B8A8_SINT);
+
+impl_normalized_vertex_format!(u16, VK_FORMAT_R16_UNORM);
+impl_normalized_vertex_format!([u16; 1], VK_FORMAT_R16_UNORM);
+impl_normalized_vertex_format!([u16; 2], VK_FORMAT_R16G16_UNORM);
+impl_normalized_vertex_format!([u16; 4], VK_FORMAT_R16G16B16A16_UNORM);
+impl_normalized_vertex_format!(i16, VK_FORMAT_R16_SNORM);
+impl_normalized_vertex_format!([i16; 1], VK_FORMAT_R16_SNORM);
+impl_normalized_vertex_format!([i16; 2], VK_FORMAT_R16G16_SNORM);
+impl_normalized_vertex_format!([i16; 4], VK_FORMAT_R16G16B16A16_SNORM);
+
+impl_normalized_vertex_format!(u8, VK_FORMAT_R8_UNORM);
+impl_normalized_vertex_format!([u8; 1], VK_FORMAT_R8_UNORM);
+impl_normalized_vertex_format!([u8; 2], VK_FORMAT_R8G8_UNORM);
+impl_normalized_vertex_format!([u8; 4], VK_FORMAT_R8G8B8A8_UNORM);
+impl_normalized_vertex_format!(i8, VK_FORMAT_R8_SNORM);
+impl_normalized_vertex_format!([i8; 1], VK_FORMAT_R8_SNORM);
+impl_normalized_vertex_format!([i8; 2], VK_FORMAT_R8G8_SNORM);
+impl_normalized_vertex_format!([i8; 4], VK_FORMAT_R8G8B8A8_SNORM);
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use vk;
+    use ::{Vertex, VertexInput};
+
+    #[derive(Clone, Copy, VertexInput)]
+    #[repr(C)]
+    struct Instance {
+        #[vertex(location = 3)]
+        model: [[f32; 4]; 4],
+        #[vertex(normalized)]
+        color: [u8; 4],
+        #[vertex(skip)]
+        _padding: [u8; 4],
+        #[vertex(format = "R16G16_SFLOAT")]
+        uv_scale: [u16; 2],
+        layer: u32,
+    }
+
+    fn locations_and_offsets(attributes: &[vk::VkVertexInputAttributeDescription])
+            -> Vec<(u32, u32, vk::VkFormat, u32)>
+    {
+        attributes.iter().map(|a| (a.location, a.binding, a.format, a.offset)).collect()
+    }
+
+    #[test]
+    fn vertex() {
+        assert_eq!(locations_and_offsets(&Vertex::attribute_descriptions(0)), vec![
+            (0, 0, vk::VK_FORMAT_R32G32B32_SFLOAT, 0),
+            (1, 0, vk::VK_FORMAT_R32G32B32_SFLOAT, 12),
+            (2, 0, vk::VK_FORMAT_R32G32_SFLOAT, 24),
+        ]);
+
+        let binding = Vertex::binding_description(0, vk::VK_VERTEX_INPUT_RATE_VERTEX);
+        assert_eq!(binding.stride as usize, mem::size_of::<Vertex>());
+    }
+
+    #[test]
+    fn instance() {
+        assert_eq!(locations_and_offsets(&Instance::attribute_descriptions(1)), vec![
+            (3, 1, vk::VK_FORMAT_R32G32B32A32_SFLOAT, 0),
+            (4, 1, vk::VK_FORMAT_R32G32B32A32_SFLOAT, 16),
+            (5, 1, vk::VK_FORMAT_R32G32B32A32_SFLOAT, 32),
+            (6, 1, vk::VK_FORMAT_R32G32B32A32_SFLOAT, 48),
+            (7, 1, vk::VK_FORMAT_R8G8B8A8_UNORM, 64),
+            (8, 1, vk::VK_FORMAT_R16G16_SFLOAT, 72),
+            (9, 1, vk::VK_FORMAT_R32_UINT, 76),
+        ]);
+
+        let binding = Instance::binding_description(1, vk::VK_VERTEX_INPUT_RATE_INSTANCE);
+        assert_eq!(binding.stride, 80);
+        assert_eq!(binding.inputRate, vk::VK_VERTEX_INPUT_RATE_INSTANCE);
+    }
+}
@@ -0,0 +1,438 @@
+//! Headless instance and device creation.
+//!
+//! Intended to run on machines without a display, including those using a
+//! software implementation such as lavapipe. The test is skipped if no Vulkan
+//! library can be loaded.
+
+extern crate vkc;
+
+use std::ptr;
+use std::env;
+use std::fs;
+use vkc::{vk, device, Version, Instance, InstanceBuilder, Device, DeviceBuilder, QueueRole, CommandPool,
+    CommandBuffer, Fence, SubmitInfo, PhysicalDevice, PhysicalDeviceSelector, RenderTarget,
+    DescriptorSetLayoutBuilder, DescriptorAllocator, Buffer, Sampler,
+    ShaderModule, PipelineLayout, GraphicsPipelineBuilder, ComputePipelineBuilder, Vertex,
+    PipelineCache, UniformBufferObject};
+
+
+static VERT_SPV: &[u8] = include_bytes!("../shaders/vert.spv");
+static FRAG_SPV: &[u8] = include_bytes!("../shaders/frag.spv");
+
+
+fn app_info() -> vk::VkApplicationInfo {
+    static APP_NAME: &[u8] = b"vkc headless test\0";
+
+    vk::VkApplicationInfo {
+        sType: vk::VK_STRUCTURE_TYPE_APPLICATION_INFO,
+        pNext: ptr::null(),
+        pApplicationName: APP_NAME.as_ptr() as *const i8,
+        applicationVersion: Version::new(1, 0, 0).into(),
+        pEngineName: ptr::null(),
+        engineVersion: 0,
+        apiVersion: Version::new(1, 0, 0).into(),
+    }
+}
+
+/// Creates a headless instance, or returns `None` if the Vulkan loader or a
+/// driver is absent, in which case the calling test is skipped.
+///
+/// Any other failure panics.
+fn headless_instance() -> Option<Instance> {
+    match unsafe { Instance::headless(&app_info()) } {
+        Ok(instance) => Some(instance),
+        Err(err) => {
+            let no_driver = err.vk_result_code() == Some(vk::VK_ERROR_INCOMPATIBLE_DRIVER);
+            let message = err.to_string();
+            let no_loader = message.starts_with("Unable to load the Vulkan library");
+            let no_device = message == "No physical devices found.";
+            if !(no_driver || no_loader || no_device) {
+                panic!("Unable to create a headless instance: {}", err);
+            }
+            println!("Skipping headless test: {}", err);
+            None
+        },
+    }
+}
+
+/// Creates a headless instance and a device whose primary queue supports
+/// `queue_flags`, or returns `None` if the test should be skipped.
+fn headless_device(queue_flags: vk::VkQueueFlags) -> Option<(Instance, Device)> {
+    let instance = headless_instance()?;
+    let physical_device = device::choose_physical_device(&instance, None, queue_flags).unwrap();
+    let device = Device::new(instance.clone(), None, physical_device, queue_flags).unwrap();
+    Some((instance, device))
+}
+
+#[test]
+fn headless_compute_device() {
+    let queue_flags = vk::VK_QUEUE_COMPUTE_BIT;
+    let (instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+    assert!(instance.is_headless());
+
+    // Command buffers may only be recorded again if their pool allows it.
+    let command_pool = CommandPool::new(device.clone(), queue_flags, 0).unwrap();
+    let mut command_buffer = CommandBuffer::new(command_pool,
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY).unwrap();
+    command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT).unwrap();
+    command_buffer.end().unwrap();
+    assert!(command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT).is_err());
+
+    let command_pool = CommandPool::new(device.clone(), queue_flags,
+        vk::VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT).unwrap();
+    let mut command_buffer = CommandBuffer::new(command_pool,
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY).unwrap();
+    for _ in 0..2 {
+        command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT).unwrap();
+        command_buffer.end().unwrap();
+    }
+}
+
+#[test]
+fn headless_multi_queue_device() {
+    let instance = match headless_instance() { Some(instance) => instance, None => return };
+
+    let physical_device = device::choose_physical_device(&instance, None,
+        vk::VK_QUEUE_COMPUTE_BIT).unwrap();
+
+    // Each request needs at least one priority, each within [0.0, 1.0].
+    for priorities in &[&[][..], &[1.5f32][..], &[-0.5][..]] {
+        assert!(DeviceBuilder::new(instance.clone(), physical_device.clone())
+            .queues(QueueRole::Primary, vk::VK_QUEUE_COMPUTE_BIT, priorities)
+            .build().is_err());
+    }
+
+    // Requesting more queues than a family has shares them.
+    let max_queue_count = physical_device.queue_families().iter()
+        .map(|family| family.queueCount).max().unwrap();
+    let priorities = vec![1.0; max_queue_count as usize + 1];
+    let device = DeviceBuilder::new(instance.clone(), physical_device.clone())
+        .queues(QueueRole::Primary, vk::VK_QUEUE_COMPUTE_BIT, &priorities)
+        .build().unwrap();
+    assert!(device.queue_is_shared(QueueRole::Primary));
+    for queue in device.queues(QueueRole::Primary) {
+        queue.wait_idle().unwrap();
+    }
+
+    let device = DeviceBuilder::new(instance.clone(), physical_device)
+        .queues(QueueRole::Primary, vk::VK_QUEUE_COMPUTE_BIT, &[1.0])
+        .queues(QueueRole::Compute, vk::VK_QUEUE_COMPUTE_BIT, &[0.5, 0.5])
+        .queues(QueueRole::Transfer, vk::VK_QUEUE_TRANSFER_BIT, &[0.5])
+        .build().unwrap();
+
+    assert_eq!(device.queues(QueueRole::Primary).len(), 1);
+    assert_eq!(device.queues(QueueRole::Compute).len(), 2);
+    let transfer_queue = device.first_queue(QueueRole::Transfer).unwrap();
+    let _command_pool = CommandPool::for_queue(&transfer_queue, 0).unwrap();
+}
+
+#[test]
+fn headless_physical_device_selection() {
+    let instance = match headless_instance() { Some(instance) => instance, None => return };
+
+    let devices = PhysicalDevice::enumerate(&instance).unwrap();
+    assert_eq!(devices.len(), instance.physical_devices().len());
+    for device in &devices {
+        assert!(!device.name().is_empty());
+        assert!(!device.queue_families().is_empty());
+    }
+
+    let selected = PhysicalDeviceSelector::new(vk::VK_QUEUE_COMPUTE_BIT)
+        .select(&instance).unwrap();
+    assert!(devices.iter().any(|d| d.handle() == selected.handle()));
+
+    assert!(PhysicalDeviceSelector::new(vk::VK_QUEUE_COMPUTE_BIT)
+        .extension("VK_VKC_nonexistent_extension")
+        .select(&instance).is_err());
+}
+
+#[test]
+fn instance_builder_missing_requirements() {
+    if headless_instance().is_none() { return; }
+
+    let err = InstanceBuilder::new()
+        .application_name("vkc headless test")
+        .extension("VK_VKC_nonexistent_extension")
+        .optional_extension("VK_VKC_other_nonexistent_extension")
+        .build().unwrap_err();
+    assert!(err.to_string().contains("VK_VKC_nonexistent_extension"));
+    assert!(!err.to_string().contains("VK_VKC_other_nonexistent_extension"));
+
+    let instance = InstanceBuilder::new()
+        .optional_extension("VK_VKC_other_nonexistent_extension")
+        .build().unwrap();
+    assert!(instance.is_headless());
+    assert!(!instance.is_extension_enabled("VK_VKC_other_nonexistent_extension"));
+
+    assert!(InstanceBuilder::new().extension("VK_KHR_\0surface").build().is_err());
+    assert!(InstanceBuilder::new().application_name("vkc\0headless").build().is_err());
+
+    // Extensions requested more than once are enabled once:
+    let name = "VK_KHR_get_physical_device_properties2";
+    let instance = InstanceBuilder::new()
+        .optional_extension(name)
+        .optional_extension(name)
+        .build().unwrap();
+    if instance.is_extension_enabled(name) {
+        let instance = InstanceBuilder::new()
+            .extension(name)
+            .extension(name)
+            .optional_extension(name)
+            .build().unwrap();
+        assert_eq!(instance.enabled_extensions().iter()
+            .filter(|ext| ext.to_bytes() == name.as_bytes()).count(), 1);
+    }
+}
+
+#[test]
+fn headless_device_extension_negotiation() {
+    let instance = match headless_instance() { Some(instance) => instance, None => return };
+
+    let queue_flags = vk::VK_QUEUE_COMPUTE_BIT;
+    let physical_device = device::choose_physical_device(&instance, None, queue_flags).unwrap();
+
+    assert!(DeviceBuilder::new(instance.clone(), physical_device.clone())
+        .queues(QueueRole::Primary, queue_flags, &[1.0])
+        .extension("VK_VKC_nonexistent_extension")
+        .build().is_err());
+
+    let mut features = device::device_features_none();
+    features.shaderFloat64 = vk::VK_TRUE;
+    let device = DeviceBuilder::new(instance.clone(), physical_device.clone())
+        .queues(QueueRole::Primary, queue_flags, &[1.0])
+        .optional_extension("VK_VKC_nonexistent_extension")
+        .optional_features(features)
+        .build().unwrap();
+    assert!(!device.is_extension_enabled("VK_VKC_nonexistent_extension"));
+    assert_eq!(device.enabled_features().shaderFloat64, physical_device.features().shaderFloat64);
+
+    // Extensions requested more than once are enabled once:
+    let name = "VK_KHR_maintenance1";
+    if physical_device.supports_extension(name) {
+        let device = DeviceBuilder::new(instance.clone(), physical_device.clone())
+            .queues(QueueRole::Primary, queue_flags, &[1.0])
+            .extension(name)
+            .extension(name)
+            .optional_extension(name)
+            .build().unwrap();
+        assert_eq!(device.enabled_extensions().iter()
+            .filter(|ext| ext.to_bytes() == name.as_bytes()).count(), 1);
+    }
+}
+
+#[test]
+fn headless_render_target_readback() {
+    let queue_flags = vk::VK_QUEUE_GRAPHICS_BIT;
+    let (_instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+    let queue = device.queue(0).unwrap();
+
+    let extent = vk::VkExtent2D { width: 8, height: 4 };
+    let target = RenderTarget::new(device.clone(), extent.clone(), vk::VK_FORMAT_B8G8R8A8_UNORM,
+        vk::VK_FORMAT_D16_UNORM).unwrap();
+
+    let clear_values = [
+        vk::VkClearValue { color: vk::VkClearColorValue { float32: [1.0, 0.0, 0.0, 1.0] } },
+        vk::VkClearValue { depthStencil: vk::VkClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+    ];
+    let render_area = vk::VkRect2D { offset: vk::VkOffset2D { x: 0, y: 0 }, extent: extent };
+
+    let command_pool = CommandPool::for_queue(&queue, 0).unwrap();
+    let mut command_buffer = CommandBuffer::new(command_pool,
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY).unwrap();
+    command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT).unwrap();
+    command_buffer.begin_render_pass(target.render_pass(), target.framebuffer(), render_area,
+        &clear_values, vk::VK_SUBPASS_CONTENTS_INLINE);
+    command_buffer.end_render_pass();
+    command_buffer.end().unwrap();
+
+    let fence = Fence::new(device.clone(), false).unwrap();
+    queue.submit(&[SubmitInfo::new().command_buffer(&command_buffer)], Some(&fence)).unwrap();
+    fence.wait(u64::max_value()).unwrap();
+
+    let image = target.read_rgba(&queue).unwrap();
+    assert_eq!(image.dimensions(), (8, 4));
+    assert!(image.pixels().all(|px| px.data == [255, 0, 0, 255]));
+}
+
+#[test]
+fn headless_compute_descriptor_set_layout() {
+    let queue_flags = vk::VK_QUEUE_COMPUTE_BIT;
+    let (_instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+
+    let layout = DescriptorSetLayoutBuilder::new(device.clone())
+        .binding(2, vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE, 1, vk::VK_SHADER_STAGE_COMPUTE_BIT)
+        .binding(0, vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER, 4, vk::VK_SHADER_STAGE_COMPUTE_BIT)
+        .build().unwrap();
+    let bindings: Vec<_> = layout.bindings().iter().map(|b| (b.binding(), b.count())).collect();
+    assert_eq!(bindings, [(0, 4), (2, 1)]);
+    assert_eq!(layout.binding(2).unwrap().descriptor_type(), vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);
+    assert!(layout.binding(1).is_none());
+
+    assert!(DescriptorSetLayoutBuilder::new(device.clone())
+        .binding(0, vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER, 1, vk::VK_SHADER_STAGE_COMPUTE_BIT)
+        .binding(0, vk::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE, 1, vk::VK_SHADER_STAGE_COMPUTE_BIT)
+        .build().is_err());
+}
+
+#[test]
+fn headless_descriptor_allocator() {
+    let queue_flags = vk::VK_QUEUE_COMPUTE_BIT;
+    let (_instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+
+    let layout = DescriptorSetLayoutBuilder::new(device.clone())
+        .binding(0, vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER, 1, vk::VK_SHADER_STAGE_COMPUTE_BIT)
+        .build().unwrap();
+    let buffer = Buffer::new(device.clone(), 256, vk::VK_BUFFER_USAGE_STORAGE_BUFFER_BIT,
+        vk::VK_SHARING_MODE_EXCLUSIVE, vk::VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT).unwrap();
+    let pool_sizes = [vk::VkDescriptorPoolSize {
+        type_: vk::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+        descriptorCount: 2,
+    }];
+
+    // Pools hold two sets each so allocating five needs three pools.
+    let mut allocator = DescriptorAllocator::with_pool_sizes(device.clone(),
+        vk::VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT, 2, &pool_sizes);
+    let sets: Vec<_> = (0..5).map(|_| allocator.allocate(&layout).unwrap()).collect();
+    assert_eq!(allocator.pool_count(), 3);
+    let sampler = Sampler::new(device.clone()).unwrap();
+    for set in &sets {
+        set.write_buffer(0, 0, &buffer, 0, 256).unwrap();
+        assert!(set.write_sampler(0, 0, &sampler).is_err());
+        assert!(set.write_buffer(1, 0, &buffer, 0, 256).is_err());
+        assert!(set.write_buffer(0, 1, &buffer, 0, 256).is_err());
+        assert!(set.write_buffer(0, 0, &buffer, 256, vk::VK_WHOLE_SIZE).is_err());
+        assert!(set.write_buffer(0, 0, &buffer, 128, 256).is_err());
+        assert!(set.write_buffer(0, 0, &buffer, 0, 0).is_err());
+        set.write_buffer(0, 0, &buffer, 128, vk::VK_WHOLE_SIZE).unwrap();
+    }
+    for set in sets {
+        unsafe { allocator.free(set).unwrap(); }
+    }
+
+    let pool_count = allocator.pool_count();
+    unsafe { allocator.reset().unwrap(); }
+    let _set = allocator.allocate(&layout).unwrap();
+    assert_eq!(allocator.pool_count(), pool_count);
+
+    let mut allocator = DescriptorAllocator::with_pool_sizes(device.clone(), 0, 2, &pool_sizes);
+    let set = allocator.allocate(&layout).unwrap();
+    assert!(unsafe { allocator.free(set) }.is_err());
+}
+
+#[test]
+fn headless_graphics_pipeline_builder() {
+    let queue_flags = vk::VK_QUEUE_GRAPHICS_BIT;
+    let (_instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+
+    let extent = vk::VkExtent2D { width: 16, height: 16 };
+    let target = RenderTarget::new(device.clone(), extent.clone(), vk::VK_FORMAT_R8G8B8A8_UNORM,
+        vk::VK_FORMAT_D16_UNORM).unwrap();
+    let vert = ShaderModule::new(device.clone(), VERT_SPV).unwrap();
+    let frag = ShaderModule::new(device.clone(), FRAG_SPV).unwrap();
+    let layout = PipelineLayout::from_modules(device.clone(), &[&vert, &frag]).unwrap();
+    assert_eq!(layout.set_layouts().len(), 1);
+    assert_eq!(layout.set_layouts()[0].bindings().len(), 2);
+
+    let builder = GraphicsPipelineBuilder::new(&layout, target.render_pass())
+        .stage(&vert, "main").unwrap()
+        .stage(&frag, "main").unwrap()
+        .vertex_input::<Vertex>(0, vk::VK_VERTEX_INPUT_RATE_VERTEX)
+        .cull_mode(vk::VK_CULL_MODE_NONE)
+        .depth_test(vk::VK_COMPARE_OP_LESS_OR_EQUAL, true)
+        .blend_attachment(GraphicsPipelineBuilder::alpha_blend_attachment());
+    assert!(builder.clone().build().is_err());
+    assert!(builder.clone().stage(&vert, "main").is_err());
+    assert!(builder.clone().stage(&vert, "nonexistent").is_err());
+    assert!(ComputePipelineBuilder::new(&layout, &vert, "main").is_err());
+    assert!(ComputePipelineBuilder::new(&layout, &vert, "nonexistent").is_err());
+    assert!(builder.clone().viewport_extent(extent.clone())
+        .vertex_input::<Vertex>(1, vk::VK_VERTEX_INPUT_RATE_INSTANCE).build().is_err());
+    assert!(builder.clone().viewport_extent(extent.clone())
+        .vertex_binding::<Vertex>(0, vk::VK_VERTEX_INPUT_RATE_INSTANCE).build().is_err());
+
+    // The uniform block must match the std140 layout of the host type.
+    assert!(builder.clone().viewport_extent(extent.clone())
+        .std140_block::<UniformBufferObject>(0, 0).build().is_ok());
+    assert!(builder.clone().viewport_extent(extent.clone())
+        .std140_block::<UniformBufferObject>(0, 5).build().is_err());
+    assert!(builder.clone().viewport_extent(extent.clone())
+        .std140_block::<[[f32; 4]; 4]>(0, 0).build().is_err());
+
+    // The number of viewports and scissors must match.
+    let scissor = vk::VkRect2D { offset: vk::VkOffset2D { x: 0, y: 0 }, extent: extent.clone() };
+    assert!(builder.clone().viewport_extent(extent.clone()).scissor(scissor).build().is_err());
+
+    let pipeline = builder.clone().viewport_extent(extent.clone()).build().unwrap();
+    assert_eq!(pipeline.subpass(), 0);
+
+    // A pipeline with a dynamic viewport and scissor needs neither at
+    // creation and has them set while recording instead.
+    let dynamic_pipeline = builder
+        .dynamic_state(vk::VK_DYNAMIC_STATE_VIEWPORT)
+        .dynamic_state(vk::VK_DYNAMIC_STATE_SCISSOR)
+        .build().unwrap();
+
+    let clear_values = [
+        vk::VkClearValue { color: vk::VkClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } },
+        vk::VkClearValue { depthStencil: vk::VkClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+    ];
+    let render_area = vk::VkRect2D { offset: vk::VkOffset2D { x: 0, y: 0 }, extent: extent };
+    let command_pool = CommandPool::new(device.clone(), queue_flags, 0).unwrap();
+    let mut command_buffer = CommandBuffer::new(command_pool,
+        vk::VK_COMMAND_BUFFER_LEVEL_PRIMARY).unwrap();
+    command_buffer.begin(vk::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT).unwrap();
+    command_buffer.begin_render_pass(target.render_pass(), target.framebuffer(),
+        render_area.clone(), &clear_values, vk::VK_SUBPASS_CONTENTS_INLINE);
+    command_buffer.bind_graphics_pipeline(&dynamic_pipeline);
+    command_buffer.set_viewport(0, &[vk::VkViewport {
+        x: 0.0,
+        y: 0.0,
+        width: 16.0,
+        height: 16.0,
+        minDepth: 0.0,
+        maxDepth: 1.0,
+    }]);
+    command_buffer.set_scissor(0, &[render_area]);
+    command_buffer.end_render_pass();
+    command_buffer.end().unwrap();
+}
+
+#[test]
+fn headless_pipeline_cache() {
+    let queue_flags = vk::VK_QUEUE_GRAPHICS_BIT;
+    let (_instance, device) = match headless_device(queue_flags) { Some(d) => d, None => return };
+
+    let extent = vk::VkExtent2D { width: 16, height: 16 };
+    let target = RenderTarget::new(device.clone(), extent.clone(), vk::VK_FORMAT_R8G8B8A8_UNORM,
+        vk::VK_FORMAT_D16_UNORM).unwrap();
+    let vert = ShaderModule::new(device.clone(), VERT_SPV).unwrap();
+    let frag = ShaderModule::new(device.clone(), FRAG_SPV).unwrap();
+    let layout = PipelineLayout::from_modules(device.clone(), &[&vert, &frag]).unwrap();
+
+    let cache = PipelineCache::new(device.clone()).unwrap();
+    GraphicsPipelineBuilder::new(&layout, target.render_pass())
+        .stage(&vert, "main").unwrap()
+        .stage(&frag, "main").unwrap()
+        .vertex_input::<Vertex>(0, vk::VK_VERTEX_INPUT_RATE_VERTEX)
+        .viewport_extent(extent)
+        .cache(&cache)
+        .build().unwrap();
+
+    let data = cache.data().unwrap();
+    assert!(PipelineCache::validate(device.physical_device(), &data).is_ok());
+
+    // Data from another device is discarded rather than passed to the driver.
+    let mut stale = data.clone();
+    stale[8] ^= 0xff;
+    assert!(PipelineCache::validate(device.physical_device(), &stale).is_err());
+    PipelineCache::with_data(device.clone(), &stale).unwrap();
+
+    let path = env::temp_dir().join(format!("vkc_pipeline_cache_{}.bin", std::process::id()));
+    cache.save(&path).unwrap();
+    let loaded = PipelineCache::load(device.clone(), &path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let missing = PipelineCache::load(device.clone(), &path).unwrap();
+
+    missing.merge(&[&loaded]).unwrap();
+    assert!(missing.merge(&[&missing]).is_err());
+}
@@ -0,0 +1,342 @@
+//! vkc_derive - Derive macros for vkc
+//!
+//! Use the re-exports in `vkc` rather than depending on this crate directly.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use] extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Fields, Field, LitInt, LitStr};
+use syn::spanned::Spanned;
+
+
+/// How the format of a field is determined.
+enum FieldFormat {
+    // From the `VertexFormat` impl of the field type.
+    Default,
+    // From the `NormalizedVertexFormat` impl of the field type.
+    Normalized,
+    // Named explicitly, without the `VK_FORMAT_` prefix.
+    Explicit(LitStr),
+}
+
+/// The options given in a field's `#[vertex(...)]` attributes.
+struct FieldOptions {
+    location: Option<u32>,
+    format: FieldFormat,
+    skip: bool,
+}
+
+impl FieldOptions {
+    fn parse(field: &Field) -> syn::Result<FieldOptions> {
+        let mut options = FieldOptions {
+            location: None,
+            format: FieldFormat::Default,
+            skip: false,
+        };
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("location") {
+                    let location: LitInt = meta.value()?.parse()?;
+                    options.location = Some(location.base10_parse()?);
+                } else if meta.path.is_ident("normalized") {
+                    options.format = FieldFormat::Normalized;
+                } else if meta.path.is_ident("format") {
+                    options.format = FieldFormat::Explicit(meta.value()?.parse()?);
+                } else if meta.path.is_ident("skip") {
+                    options.skip = true;
+                } else {
+                    return Err(meta.error("expected `location`, `normalized`, `format` or `skip`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+
+/// Implements `vkc::VertexInput` for a struct, with one attribute for each
+/// field.
+///
+/// Locations are assigned in field order starting from zero, with matrix
+/// fields taking one location per column. Fields accept the following
+/// options:
+///
+/// * `#[vertex(location = 3)]`: Assigns this field, and those following
+///   it, locations starting from 3.
+/// * `#[vertex(normalized)]`: Reads integer components as normalized
+///   floats, for example `[u8; 4]` as `VK_FORMAT_R8G8B8A8_UNORM`.
+/// * `#[vertex(format = "R16G16_SFLOAT")]`: Uses the named format.
+/// * `#[vertex(skip)]`: Does not create an attribute for this field.
+#[proc_macro_derive(VertexInput, attributes(vertex))]
+pub fn derive_vertex_input(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand_vertex_input(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_vertex_input(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().collect::<Vec<_>>(),
+            Fields::Unnamed(ref fields) => fields.unnamed.iter().collect::<Vec<_>>(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => return Err(syn::Error::new(input.span(),
+            "`VertexInput` can only be derived for structs")),
+    };
+
+    let mut attributes = Vec::with_capacity(fields.len());
+    for (index, field) in fields.iter().enumerate() {
+        let options = FieldOptions::parse(field)?;
+        if options.skip {
+            continue;
+        }
+
+        let ty = &field.ty;
+        let member = match field.ident {
+            Some(ref ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(index);
+                quote!(#index)
+            },
+        };
+        let (format, locations) = match options.format {
+            FieldFormat::Default => (
+                quote!(<#ty as ::vkc::VertexFormat>::FORMAT),
+                quote!(<#ty as ::vkc::VertexFormat>::LOCATIONS),
+            ),
+            FieldFormat::Normalized => (
+                quote!(<#ty as ::vkc::NormalizedVertexFormat>::NORMALIZED_FORMAT),
+                quote!(1u32),
+            ),
+            FieldFormat::Explicit(ref name) => {
+                let format = syn::Ident::new(&format!("VK_FORMAT_{}", name.value()), name.span());
+                (quote!(::vkc::vk::#format), quote!(1u32))
+            },
+        };
+        let set_location = options.location.map(|location| quote!(location = #location;));
+
+        attributes.push(quote! {
+            #set_location
+            let locations = #locations;
+            let offset = ::std::mem::offset_of!(Self, #member);
+            let column_size = ::std::mem::size_of::<#ty>() / locations as usize;
+            for column in 0..locations {
+                attributes.push(::vkc::vk::VkVertexInputAttributeDescription {
+                    location: location + column,
+                    binding,
+                    format: #format,
+                    offset: (offset + column as usize * column_size) as u32,
+                });
+            }
+            location += locations;
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::vkc::VertexInput for #name #ty_generics #where_clause {
+            #[allow(unused_mut, unused_assignments, unused_variables)]
+            fn attribute_descriptions(binding: u32)
+                    -> ::std::vec::Vec<::vkc::vk::VkVertexInputAttributeDescription>
+            {
+                let mut attributes = ::std::vec::Vec::new();
+                let mut location = 0u32;
+                #(#attributes)*
+                attributes
+            }
+        }
+    })
+}
+
+
+/// The names used by one of the `AsStd140` and `AsStd430` derives.
+struct StdLayout {
+    trait_name: &'static str,
+    array_element_trait: &'static str,
+    // Appended to the struct name to name the padded representation.
+    suffix: &'static str,
+    repr_type: &'static str,
+    element_type: &'static str,
+    alignment: &'static str,
+    size: &'static str,
+    convert: &'static str,
+    convert_element: &'static str,
+    members: &'static str,
+    // Struct alignments are rounded up to a multiple of this.
+    min_struct_alignment: usize,
+}
+
+const STD140: StdLayout = StdLayout {
+    trait_name: "AsStd140",
+    array_element_trait: "Std140ArrayElement",
+    suffix: "Std140",
+    repr_type: "Std140",
+    element_type: "Std140Element",
+    alignment: "STD140_ALIGNMENT",
+    size: "STD140_SIZE",
+    convert: "as_std140",
+    convert_element: "as_std140_element",
+    members: "std140_members",
+    min_struct_alignment: 16,
+};
+
+const STD430: StdLayout = StdLayout {
+    trait_name: "AsStd430",
+    array_element_trait: "Std430ArrayElement",
+    suffix: "Std430",
+    repr_type: "Std430",
+    element_type: "Std430Element",
+    alignment: "STD430_ALIGNMENT",
+    size: "STD430_SIZE",
+    convert: "as_std430",
+    convert_element: "as_std430_element",
+    members: "std430_members",
+    min_struct_alignment: 1,
+};
+
+
+/// Implements `vkc::AsStd140` for a struct whose fields all implement it.
+///
+/// Generates a `#[repr(C)]` struct named after the original with a `Std140`
+/// suffix, with explicit padding between fields following the std140 rules.
+#[proc_macro_derive(AsStd140)]
+pub fn derive_as_std140(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand_std_layout(&input, &STD140) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Implements `vkc::AsStd430` for a struct whose fields all implement it.
+///
+/// Generates a `#[repr(C)]` struct named after the original with a `Std430`
+/// suffix, with explicit padding between fields following the std430 rules.
+#[proc_macro_derive(AsStd430)]
+pub fn derive_as_std430(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand_std_layout(&input, &STD430) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_std_layout(input: &DeriveInput, layout: &StdLayout) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().collect::<Vec<_>>(),
+            _ => return Err(syn::Error::new(input.span(),
+                format!("`{}` can only be derived for structs with named fields",
+                    layout.trait_name))),
+        },
+        _ => return Err(syn::Error::new(input.span(),
+            format!("`{}` can only be derived for structs", layout.trait_name))),
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(input.generics.span(),
+            format!("`{}` can not be derived for generic structs", layout.trait_name)));
+    }
+
+    let ident = |name: &str| syn::Ident::new(name, proc_macro2::Span::call_site());
+    let trait_name = ident(layout.trait_name);
+    let array_element_trait = ident(layout.array_element_trait);
+    let repr_type = ident(layout.repr_type);
+    let element_type = ident(layout.element_type);
+    let alignment = ident(layout.alignment);
+    let size = ident(layout.size);
+    let convert = ident(layout.convert);
+    let convert_element = ident(layout.convert_element);
+    let members = ident(layout.members);
+    let min_struct_alignment = layout.min_struct_alignment;
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let repr_name = syn::Ident::new(&format!("{}{}", name, layout.suffix), name.span());
+
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let alignments = quote!([#(<#types as ::vkc::#trait_name>::#alignment),*]);
+    let sizes = quote!([#(<#types as ::vkc::#trait_name>::#size),*]);
+
+    let mut repr_fields = Vec::with_capacity(fields.len());
+    let mut field_values = Vec::with_capacity(fields.len());
+    let mut block_members = Vec::with_capacity(fields.len());
+    for (index, field) in fields.iter().enumerate() {
+        let field_ident = field.ident.as_ref().unwrap();
+        let field_vis = &field.vis;
+        let ty = &field.ty;
+        let padding_ident = syn::Ident::new(&format!("__vkc_padding_{}", index),
+            field_ident.span());
+        let padding = quote!(::vkc::std_layout::member_padding(#alignments, #sizes, #index));
+
+        repr_fields.push(quote! {
+            #padding_ident: [u8; #padding],
+            #field_vis #field_ident: <#ty as ::vkc::#trait_name>::#repr_type,
+        });
+        field_values.push(quote! {
+            #padding_ident: [0; #padding],
+            #field_ident: ::vkc::#trait_name::#convert(&self.#field_ident),
+        });
+        let field_name = field_ident.to_string();
+        block_members.push(quote! {
+            ::vkc::spirv::BlockMemberInfo {
+                name: ::std::string::String::from(#field_name),
+                offset: ::std::mem::offset_of!(#repr_name, #field_ident) as u32,
+                size: <#ty as ::vkc::#trait_name>::#size as u32,
+            }
+        });
+    }
+    let trailing_padding = quote! {
+        ::vkc::std_layout::trailing_padding(#alignments, #sizes,
+            ::vkc::std_layout::struct_alignment(#alignments, #min_struct_alignment))
+    };
+
+    let repr_doc = format!("The {} representation of `{}`.", layout.suffix.to_lowercase(), name);
+
+    Ok(quote! {
+        #[doc = #repr_doc]
+        #[derive(Debug, Clone, Copy)]
+        #[repr(C)]
+        #vis struct #repr_name {
+            #(#repr_fields)*
+            __vkc_trailing_padding: [u8; #trailing_padding],
+        }
+
+        impl ::vkc::#trait_name for #name {
+            type #repr_type = #repr_name;
+            type #element_type = #repr_name;
+            const #alignment: usize =
+                ::vkc::std_layout::struct_alignment(#alignments, #min_struct_alignment);
+
+            fn #convert(&self) -> #repr_name {
+                #repr_name {
+                    #(#field_values)*
+                    __vkc_trailing_padding: [0; #trailing_padding],
+                }
+            }
+
+            fn #convert_element(&self) -> #repr_name {
+                ::vkc::#trait_name::#convert(self)
+            }
+
+            fn #members() -> ::std::vec::Vec<::vkc::spirv::BlockMemberInfo> {
+                vec![#(#block_members),*]
+            }
+        }
+
+        impl ::vkc::#array_element_trait for #name {}
+
+        // Padding is explicit and every field is itself `Pod`.
+        unsafe impl ::vkc::Pod for #repr_name {}
+    })
+}